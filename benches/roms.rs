@@ -0,0 +1,53 @@
+use std::fs;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+use chip8::chip8::{asm, bench, cpu, sound};
+use chip8::chip8::cpu::Cpu;
+use chip8::chip8::frontend::headless::Headless;
+
+const CYCLES: u64 = 10_000;
+
+const ROMS: [&str; 6] = ["PONG", "BRIX", "TETRIS", "INVADERS", "MAZE", "IBM Logo.ch8"];
+
+/// loops keeping the decoder busy, or the sprite drawing
+const DECODE: &str = "loop: ADD V0, 1\n XOR V1, V0\n SHR V2, V1\n SE V3, 0xFF\n JP loop";
+const DRAW: &str = "LD I, 0\n loop: DRW V0, V1, 15\n ADD V0, 3\n ADD V1, 1\n JP loop";
+
+fn machine() -> Cpu {
+    cpu::initialize(Box::new(Headless), Box::new(sound::Null))
+}
+
+fn run(c: &mut Criterion, group: &str, roms: &[(&str, Vec<u8>)]) {
+    let mut group = c.benchmark_group(group);
+    group.throughput(Throughput::Elements(CYCLES));
+    for (name, rom) in roms {
+        // every iteration starts from the same boot, random numbers included
+        let mut cpu = machine();
+        cpu.bootup(rom.clone());
+        let boot = cpu.save_state();
+        group.bench_function(*name, |b| b.iter_batched_ref(
+            || {
+                let mut cpu = machine();
+                cpu.load_state(&boot).unwrap();
+                cpu
+            },
+            |cpu| bench::spin(cpu, CYCLES),
+            BatchSize::SmallInput,
+        ));
+    }
+    group.finish();
+}
+
+fn roms(c: &mut Criterion) {
+    let roms: Vec<_> = ROMS.iter().map(|name| (*name, fs::read(format!("roms/{}", name)).unwrap())).collect();
+    run(c, "roms", &roms);
+}
+
+fn instructions(c: &mut Criterion) {
+    let roms = [("decode", asm::assemble(DECODE, 0x200).unwrap()), ("draw", asm::assemble(DRAW, 0x200).unwrap())];
+    run(c, "instructions", &roms);
+}
+
+criterion_group!(benches, roms, instructions);
+criterion_main!(benches);
@@ -0,0 +1,22 @@
+/// generates the header of the C api from src/capi.rs alone, into OUT_DIR,
+/// and warns when the checked-in capi/chip8.h no longer matches it
+#[cfg(feature = "capi")]
+fn main() {
+    println!("cargo:rerun-if-changed=src/capi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=capi/chip8.h");
+    let config = cbindgen::Config::from_file("cbindgen.toml").expect("cbindgen.toml");
+    let header = std::path::Path::new(&std::env::var("OUT_DIR").expect("OUT_DIR")).join("chip8.h");
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src("src/capi.rs")
+        .generate()
+        .expect("chip8.h")
+        .write_to_file(&header);
+    if std::fs::read(&header).ok() != std::fs::read("capi/chip8.h").ok() {
+        println!("cargo:warning=capi/chip8.h is out of date, copy {} over it", header.display());
+    }
+}
+
+#[cfg(not(feature = "capi"))]
+fn main() {}
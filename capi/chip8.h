@@ -0,0 +1,156 @@
+#ifndef CHIP8_H
+#define CHIP8_H
+
+/* generated from src/capi.rs by build.rs with --features capi, don't edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// bumped on any change breaking callers built against an older header
+#define CHIP8_API_VERSION 1
+
+#define CHIP8_OK 0
+
+#define CHIP8_ERROR -1
+
+#define CHIP8_SCREEN_WIDTH 64
+
+#define CHIP8_SCREEN_HEIGHT 32
+
+#define CHIP8_MEMORY_SIZE 4096
+
+// A machine, only handled through pointers by C
+typedef struct chip8_machine chip8_machine;
+
+// The registers, read and written at once
+typedef struct chip8_registers {
+  uint8_t v[16];
+  uint16_t i;
+  uint16_t pc;
+  uint8_t sp;
+  uint16_t stack[16];
+  uint8_t delay_timer;
+  uint8_t sound_timer;
+} chip8_registers;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+uint32_t chip8_api_version(void);
+
+// a machine with nothing loaded, RND seeded with seed. Freed with chip8_destroy
+struct chip8_machine *chip8_create(uint64_t seed);
+
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed, it can't be used afterwards
+void chip8_destroy(struct chip8_machine *machine);
+
+// why the last failing call failed, valid until the next failing call
+//
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed
+const char *chip8_last_error(const struct chip8_machine *machine);
+
+// resets the machine and boots the rom, copied from rom
+//
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed
+// rom must point to size readable bytes
+int chip8_load_rom(struct chip8_machine *machine, const uint8_t *rom, size_t size);
+
+// runs count instructions without ticking the timers, for hosts timing them with chip8_run_frames.
+// Returns false once the machine stopped on an instruction it doesn't know
+//
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed
+bool chip8_run_cycles(struct chip8_machine *machine, uint64_t count);
+
+// runs count frames of 1/60 s, one instruction and one timer tick each
+//
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed
+bool chip8_run_frames(struct chip8_machine *machine, uint64_t count);
+
+// frames run since the rom was loaded
+//
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed
+uint64_t chip8_frames(const struct chip8_machine *machine);
+
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed
+void chip8_set_key(struct chip8_machine *machine, uint8_t key, bool down);
+
+// sets the whole keypad, bit n for key n
+//
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed
+void chip8_set_keys(struct chip8_machine *machine, uint16_t keys);
+
+// CHIP8_SCREEN_WIDTH * CHIP8_SCREEN_HEIGHT bytes of 0 or 1, row after row,
+// valid as long as the machine is
+//
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed
+const uint8_t *chip8_framebuffer(const struct chip8_machine *machine);
+
+// whether the buzzer sounds
+//
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed
+bool chip8_buzzing(const struct chip8_machine *machine);
+
+// copies size bytes of memory from address into buffer, CHIP8_ERROR past the end of memory
+//
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed
+// buffer must point to size writable bytes
+int chip8_read_memory(const struct chip8_machine *machine,
+                      uint16_t address,
+                      uint8_t *buffer,
+                      size_t size);
+
+// copies size bytes of buffer into memory at address
+//
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed
+// buffer must point to size readable bytes
+int chip8_write_memory(struct chip8_machine *machine,
+                       uint16_t address,
+                       const uint8_t *buffer,
+                       size_t size);
+
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed
+// registers must be null or point to a chip8_registers
+int chip8_get_registers(const struct chip8_machine *machine, struct chip8_registers *registers);
+
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed
+// registers must be null or point to a chip8_registers
+int chip8_set_registers(struct chip8_machine *machine, const struct chip8_registers *registers);
+
+// bytes of a save state, the same for every machine
+size_t chip8_state_size(void);
+
+// writes a save state of chip8_state_size bytes into buffer
+//
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed
+// buffer must point to size writable bytes
+int chip8_save_state(struct chip8_machine *machine, uint8_t *buffer, size_t size);
+
+// # Safety
+// machine must be null or come from chip8_create and not be destroyed
+// buffer must point to size readable bytes
+int chip8_load_state(struct chip8_machine *machine, const uint8_t *buffer, size_t size);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* CHIP8_H */
@@ -0,0 +1,85 @@
+/*
+ * Embeds the interpreter through the C api: runs a rom, prints the screen,
+ * pokes the registers and memory, then checks a save state replays the same frames.
+ *
+ *   cargo build --release --features capi
+ *   cc -o example capi/example.c -Icapi -Ltarget/release -lchip8
+ *   LD_LIBRARY_PATH=target/release ./example roms/PONG
+ */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "chip8.h"
+
+static void print_screen(const uint8_t *pixels) {
+    for (unsigned y = 0; y < CHIP8_SCREEN_HEIGHT; y++) {
+        for (unsigned x = 0; x < CHIP8_SCREEN_WIDTH; x++) {
+            putchar(pixels[y * CHIP8_SCREEN_WIDTH + x] ? '#' : '.');
+        }
+        putchar('\n');
+    }
+}
+
+int main(int argc, char **argv) {
+    if (argc < 2) {
+        fprintf(stderr, "usage: %s <rom>\n", argv[0]);
+        return 1;
+    }
+    if (chip8_api_version() != CHIP8_API_VERSION) {
+        fprintf(stderr, "built against api %u, the library has %u\n", CHIP8_API_VERSION, chip8_api_version());
+        return 1;
+    }
+    FILE *file = fopen(argv[1], "rb");
+    if (!file) {
+        perror(argv[1]);
+        return 1;
+    }
+    static uint8_t rom[CHIP8_MEMORY_SIZE];
+    size_t size = fread(rom, 1, sizeof rom, file);
+    fclose(file);
+
+    chip8_machine *machine = chip8_create(0);
+    if (chip8_load_rom(machine, rom, size) != CHIP8_OK) {
+        fprintf(stderr, "%s\n", chip8_last_error(machine));
+        return 1;
+    }
+    chip8_set_key(machine, 0x1, true);
+    chip8_run_frames(machine, 300);
+    chip8_set_keys(machine, 0);
+    chip8_run_frames(machine, 300);
+    print_screen(chip8_framebuffer(machine));
+
+    chip8_registers registers;
+    chip8_get_registers(machine, &registers);
+    printf("%llu frames, pc %03X, i %03X, v0 %02X\n", (unsigned long long)chip8_frames(machine), registers.pc, registers.i, registers.v[0]);
+    registers.pc = 0x1000;
+    if (chip8_set_registers(machine, &registers) != CHIP8_OK) {
+        printf("refused: %s\n", chip8_last_error(machine));
+    }
+    uint8_t font[5];
+    chip8_read_memory(machine, 0x000, font, sizeof font);
+    printf("font 0: %02X %02X %02X %02X %02X\n", font[0], font[1], font[2], font[3], font[4]);
+    if (chip8_write_memory(machine, 0xFFF, font, sizeof font) != CHIP8_OK) {
+        printf("refused: %s\n", chip8_last_error(machine));
+    }
+
+    /* the frames after a save state are the same when it's loaded back */
+    size_t state_size = chip8_state_size();
+    uint8_t *state = malloc(state_size);
+    static uint8_t before[CHIP8_SCREEN_WIDTH * CHIP8_SCREEN_HEIGHT];
+    chip8_save_state(machine, state, state_size);
+    chip8_run_frames(machine, 600);
+    memcpy(before, chip8_framebuffer(machine), sizeof before);
+    chip8_load_state(machine, state, state_size);
+    chip8_run_frames(machine, 600);
+    int same = memcmp(before, chip8_framebuffer(machine), sizeof before) == 0;
+    printf("save state of %zu bytes: %s\n", state_size, same ? "replays the same" : "DIVERGES");
+    if (chip8_load_state(machine, state, 3) != CHIP8_OK) {
+        printf("refused: %s\n", chip8_last_error(machine));
+    }
+
+    free(state);
+    chip8_destroy(machine);
+    return same ? 0 : 1;
+}
@@ -0,0 +1,236 @@
+/*
+ * Minimal libretro front-end exercising the chip8 core without RetroArch:
+ * loads the core and a rom, runs frames holding a RetroPad button now and then,
+ * prints the last frame, then checks a save state replays the same frames.
+ *
+ *   cargo build --release --features libretro
+ *   cc -o harness libretro/harness.c -ldl
+ *   ./harness target/release/libchip8.so roms/PONG 600
+ */
+#include <dlfcn.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#define RETRO_ENVIRONMENT_SET_PIXEL_FORMAT 10
+#define RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS 11
+#define RETRO_ENVIRONMENT_GET_VARIABLE 15
+#define RETRO_ENVIRONMENT_SET_VARIABLES 16
+#define RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE 17
+#define RETRO_PIXEL_FORMAT_XRGB8888 1
+#define RETRO_DEVICE_ID_JOYPAD_A 8
+
+struct retro_system_info {
+    const char *library_name;
+    const char *library_version;
+    const char *valid_extensions;
+    bool need_fullpath;
+    bool block_extract;
+};
+
+struct retro_system_av_info {
+    struct { unsigned base_width, base_height, max_width, max_height; float aspect_ratio; } geometry;
+    struct { double fps, sample_rate; } timing;
+};
+
+struct retro_game_info {
+    const char *path;
+    const void *data;
+    size_t size;
+    const char *meta;
+};
+
+struct retro_variable {
+    const char *key;
+    const char *value;
+};
+
+struct retro_input_descriptor {
+    unsigned port, device, index, id;
+    const char *description;
+};
+
+static uint32_t frame[64 * 32];
+static unsigned width, height;
+static size_t samples;
+static unsigned frames;
+
+static bool environment(unsigned cmd, void *data) {
+    switch (cmd) {
+    case RETRO_ENVIRONMENT_SET_PIXEL_FORMAT:
+        return *(unsigned *)data == RETRO_PIXEL_FORMAT_XRGB8888;
+    case RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS: {
+        unsigned count = 0;
+        for (struct retro_input_descriptor *d = data; d->description; d++) {
+            count++;
+        }
+        printf("%u input descriptors\n", count);
+        return true;
+    }
+    case RETRO_ENVIRONMENT_SET_VARIABLES:
+        for (struct retro_variable *v = data; v->key; v++) {
+            printf("option %s: %s\n", v->key, v->value);
+        }
+        return true;
+    case RETRO_ENVIRONMENT_GET_VARIABLE: {
+        struct retro_variable *v = data;
+        v->value = strcmp(v->key, "chip8_speed") == 0 ? getenv("CHIP8_SPEED") : NULL;
+        return v->value != NULL;
+    }
+    case RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE:
+        *(bool *)data = false;
+        return true;
+    default:
+        return false;
+    }
+}
+
+static void video_refresh(const void *data, unsigned w, unsigned h, size_t pitch) {
+    width = w;
+    height = h;
+    for (unsigned y = 0; y < h && y < 32; y++) {
+        memcpy(&frame[y * 64], (const char *)data + y * pitch, w * 4);
+    }
+    frames++;
+}
+
+static void audio_sample(int16_t left, int16_t right) {
+    (void)left;
+    (void)right;
+}
+
+static size_t audio_sample_batch(const int16_t *data, size_t count) {
+    (void)data;
+    samples += count;
+    return count;
+}
+
+static void input_poll(void) {}
+
+/* A pressed for a second every five seconds */
+static int16_t input_state(unsigned port, unsigned device, unsigned index, unsigned id) {
+    (void)port;
+    (void)device;
+    (void)index;
+    return id == RETRO_DEVICE_ID_JOYPAD_A && frames % 300 < 60;
+}
+
+#define SYMBOL(name) name = dlsym(core, #name); if (!name) { fprintf(stderr, "missing %s\n", #name); return 1; }
+
+int main(int argc, char **argv) {
+    if (argc < 3) {
+        fprintf(stderr, "usage: %s <core> <rom> [frames]\n", argv[0]);
+        return 1;
+    }
+    unsigned count = argc > 3 ? (unsigned)atoi(argv[3]) : 600;
+
+    void *core = dlopen(argv[1], RTLD_NOW);
+    if (!core) {
+        fprintf(stderr, "%s\n", dlerror());
+        return 1;
+    }
+    unsigned (*retro_api_version)(void);
+    void (*retro_get_system_info)(struct retro_system_info *);
+    void (*retro_get_system_av_info)(struct retro_system_av_info *);
+    void (*retro_set_environment)(bool (*)(unsigned, void *));
+    void (*retro_set_video_refresh)(void (*)(const void *, unsigned, unsigned, size_t));
+    void (*retro_set_audio_sample)(void (*)(int16_t, int16_t));
+    void (*retro_set_audio_sample_batch)(size_t (*)(const int16_t *, size_t));
+    void (*retro_set_input_poll)(void (*)(void));
+    void (*retro_set_input_state)(int16_t (*)(unsigned, unsigned, unsigned, unsigned));
+    void (*retro_init)(void);
+    void (*retro_deinit)(void);
+    bool (*retro_load_game)(const struct retro_game_info *);
+    void (*retro_unload_game)(void);
+    void (*retro_run)(void);
+    size_t (*retro_serialize_size)(void);
+    bool (*retro_serialize)(void *, size_t);
+    bool (*retro_unserialize)(const void *, size_t);
+    SYMBOL(retro_api_version);
+    SYMBOL(retro_get_system_info);
+    SYMBOL(retro_get_system_av_info);
+    SYMBOL(retro_set_environment);
+    SYMBOL(retro_set_video_refresh);
+    SYMBOL(retro_set_audio_sample);
+    SYMBOL(retro_set_audio_sample_batch);
+    SYMBOL(retro_set_input_poll);
+    SYMBOL(retro_set_input_state);
+    SYMBOL(retro_init);
+    SYMBOL(retro_deinit);
+    SYMBOL(retro_load_game);
+    SYMBOL(retro_unload_game);
+    SYMBOL(retro_run);
+    SYMBOL(retro_serialize_size);
+    SYMBOL(retro_serialize);
+    SYMBOL(retro_unserialize);
+
+    struct retro_system_info info;
+    retro_get_system_info(&info);
+    printf("%s %s, api %u, extensions %s\n", info.library_name, info.library_version, retro_api_version(), info.valid_extensions);
+
+    retro_set_environment(environment);
+    retro_set_video_refresh(video_refresh);
+    retro_set_audio_sample(audio_sample);
+    retro_set_audio_sample_batch(audio_sample_batch);
+    retro_set_input_poll(input_poll);
+    retro_set_input_state(input_state);
+    retro_init();
+
+    struct retro_system_av_info av;
+    retro_get_system_av_info(&av);
+    printf("%ux%u at %.0f fps, %.0f hz audio\n", av.geometry.base_width, av.geometry.base_height, av.timing.fps, av.timing.sample_rate);
+
+    FILE *file = fopen(argv[2], "rb");
+    if (!file) {
+        perror(argv[2]);
+        return 1;
+    }
+    static unsigned char rom[4096];
+    struct retro_game_info game = { argv[2], rom, fread(rom, 1, sizeof rom, file), NULL };
+    fclose(file);
+    if (!retro_load_game(&game)) {
+        fprintf(stderr, "couldn't load %s\n", argv[2]);
+        return 1;
+    }
+
+    for (unsigned n = 0; n < count; n++) {
+        retro_run();
+    }
+    for (unsigned y = 0; y < height; y++) {
+        for (unsigned x = 0; x < width; x++) {
+            putchar(frame[y * 64 + x] ? '#' : '.');
+        }
+        putchar('\n');
+    }
+    printf("%u frames, %zu audio frames\n", frames, samples);
+
+    /* the frames after a save state are the same when it's loaded back */
+    size_t size = retro_serialize_size();
+    void *state = malloc(size);
+    static uint32_t before[64 * 32];
+    if (!retro_serialize(state, size)) {
+        fprintf(stderr, "couldn't save the state\n");
+        return 1;
+    }
+    for (unsigned n = 0; n < 120; n++) {
+        retro_run();
+    }
+    memcpy(before, frame, sizeof frame);
+    if (!retro_unserialize(state, size)) {
+        fprintf(stderr, "couldn't load the state\n");
+        return 1;
+    }
+    for (unsigned n = 0; n < 120; n++) {
+        retro_run();
+    }
+    bool same = memcmp(before, frame, sizeof frame) == 0;
+    printf("save state of %zu bytes: %s\n", size, same ? "replays the same" : "DIVERGES");
+
+    free(state);
+    retro_unload_game();
+    retro_deinit();
+    dlclose(core);
+    return same ? 0 : 1;
+}
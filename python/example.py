@@ -0,0 +1,44 @@
+"""Runs a rom on many headless machines at once, pressing random keys,
+and prints how many distinct screens they ended on.
+
+    cargo build --release --features python
+    cp target/release/libchip8.so python/chip8.so
+    python3 python/example.py roms/BRIX
+"""
+import random
+import sys
+from multiprocessing import Pool
+
+import chip8
+
+FRAMES = 3600
+
+
+def play(args):
+    rom, seed = args
+    keys = random.Random(seed)
+    machine = chip8.Machine(seed=seed)
+    machine.load(rom)
+    while machine.frames < FRAMES and machine.running:
+        key = keys.randrange(16)
+        machine.press(key)
+        machine.step(60)
+        machine.release(key)
+    # numpy.asarray(machine.framebuffer()) gives the same pixels as a (32, 64) array
+    return bytes(memoryview(machine.framebuffer()))
+
+
+def main():
+    if len(sys.argv) < 2:
+        sys.exit("usage: example.py <rom> [machines]")
+    with open(sys.argv[1], "rb") as file:
+        rom = file.read()
+    count = int(sys.argv[2]) if len(sys.argv) > 2 else 1000
+    # a machine lives in the process that made it, the pool spreads them over the cores
+    with Pool() as pool:
+        screens = pool.map(play, [(rom, seed) for seed in range(count)], chunksize=64)
+    print(f"{count} machines, {len(set(screens))} distinct screens after {FRAMES} frames")
+
+
+if __name__ == "__main__":
+    main()
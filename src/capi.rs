@@ -0,0 +1,324 @@
+//! C api to embed the interpreter, built with `cargo build --release --features capi`,
+//! which also writes the capi/chip8.h header, see capi/example.c.
+//! Machines are opaque and headless, the host draws the framebuffer and plays the buzzer.
+//! Calls returning int give CHIP8_OK or CHIP8_ERROR, chip8_last_error then tells why.
+
+use std::ffi::{c_char, c_int, CString};
+use std::ptr;
+use std::slice;
+
+use crate::chip8::cpu::{self, *};
+use crate::chip8::frontend::headless::Headless;
+use crate::chip8::sound;
+use crate::chip8::state;
+
+/// bumped on any change breaking callers built against an older header
+pub const CHIP8_API_VERSION: u32 = 1;
+pub const CHIP8_OK: c_int = 0;
+pub const CHIP8_ERROR: c_int = -1;
+pub const CHIP8_SCREEN_WIDTH: u32 = 64;
+pub const CHIP8_SCREEN_HEIGHT: u32 = 32;
+pub const CHIP8_MEMORY_SIZE: usize = 4096;
+
+const _: () = assert!(CHIP8_SCREEN_WIDTH == SCREEN_WIDTH && CHIP8_SCREEN_HEIGHT == SCREEN_HEIGHT && CHIP8_MEMORY_SIZE == MEMORY_SIZE);
+
+/// A machine, only handled through pointers by C
+pub struct Machine {
+    cpu: Cpu,
+    error: CString,
+}
+
+/// The registers, read and written at once
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Registers {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub sp: u8,
+    pub stack: [u16; 16],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl Machine {
+
+    /// keeps the error for chip8_last_error
+    fn fail(&mut self, error: String) -> c_int {
+        error!("{}", error);
+        self.error = CString::new(error).unwrap_or_default();
+        CHIP8_ERROR
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_api_version() -> u32 {
+    CHIP8_API_VERSION
+}
+
+/// a machine with nothing loaded, RND seeded with seed. Freed with chip8_destroy
+#[no_mangle]
+pub extern "C" fn chip8_create(seed: u64) -> *mut Machine {
+    let mut cpu = cpu::initialize(Box::new(Headless), Box::new(sound::Null));
+    cpu.reseed(seed);
+    Box::into_raw(Box::new(Machine { cpu, error: CString::default() }))
+}
+
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed, it can't be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn chip8_destroy(machine: *mut Machine) {
+    if !machine.is_null() {
+        drop(Box::from_raw(machine));
+    }
+}
+
+/// why the last failing call failed, valid until the next failing call
+///
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed
+#[no_mangle]
+pub unsafe extern "C" fn chip8_last_error(machine: *const Machine) -> *const c_char {
+    match machine.as_ref() {
+        Some(machine) => machine.error.as_ptr(),
+        None => b"no machine\0".as_ptr() as *const c_char,
+    }
+}
+
+/// resets the machine and boots the rom, copied from rom
+///
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed
+/// rom must point to size readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(machine: *mut Machine, rom: *const u8, size: usize) -> c_int {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return CHIP8_ERROR,
+    };
+    if rom.is_null() {
+        return machine.fail("no rom".to_string());
+    }
+    if size > MEMORY_SIZE - machine.cpu.load_address as usize {
+        return machine.fail(format!("rom too big, {} bytes", size));
+    }
+    machine.cpu.reset();
+    machine.cpu.clock.frames = 0;
+    machine.cpu.bootup(slice::from_raw_parts(rom, size).to_vec());
+    CHIP8_OK
+}
+
+/// runs count instructions without ticking the timers, for hosts timing them with chip8_run_frames.
+/// Returns false once the machine stopped on an instruction it doesn't know
+///
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_cycles(machine: *mut Machine, count: u64) -> bool {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return false,
+    };
+    for _ in 0..count {
+        if machine.cpu.quit {
+            break;
+        }
+        machine.cpu.step();
+    }
+    !machine.cpu.quit
+}
+
+/// runs count frames of 1/60 s, one instruction and one timer tick each
+///
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frames(machine: *mut Machine, count: u64) -> bool {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return false,
+    };
+    for _ in 0..count {
+        if machine.cpu.quit {
+            break;
+        }
+        machine.cpu.frame();
+    }
+    !machine.cpu.quit
+}
+
+/// frames run since the rom was loaded
+///
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed
+#[no_mangle]
+pub unsafe extern "C" fn chip8_frames(machine: *const Machine) -> u64 {
+    machine.as_ref().map_or(0, |machine| machine.cpu.clock.frames)
+}
+
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_key(machine: *mut Machine, key: u8, down: bool) {
+    if let Some(machine) = machine.as_mut() {
+        machine.cpu.keyboard[key as usize & 0xF] = down;
+    }
+}
+
+/// sets the whole keypad, bit n for key n
+///
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_keys(machine: *mut Machine, keys: u16) {
+    if let Some(machine) = machine.as_mut() {
+        for (key, down) in machine.cpu.keyboard.iter_mut().enumerate() {
+            *down = keys & 1 << key != 0;
+        }
+    }
+}
+
+/// CHIP8_SCREEN_WIDTH * CHIP8_SCREEN_HEIGHT bytes of 0 or 1, row after row,
+/// valid as long as the machine is
+///
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(machine: *const Machine) -> *const u8 {
+    machine.as_ref().map_or(ptr::null(), |machine| machine.cpu.display.as_ptr())
+}
+
+/// whether the buzzer sounds
+///
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed
+#[no_mangle]
+pub unsafe extern "C" fn chip8_buzzing(machine: *const Machine) -> bool {
+    machine.as_ref().is_some_and(|machine| machine.cpu.sound_timer > 0)
+}
+
+/// copies size bytes of memory from address into buffer, CHIP8_ERROR past the end of memory
+///
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed
+/// buffer must point to size writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn chip8_read_memory(machine: *const Machine, address: u16, buffer: *mut u8, size: usize) -> c_int {
+    let machine = match machine.as_ref() {
+        Some(machine) => machine,
+        None => return CHIP8_ERROR,
+    };
+    match machine.cpu.memory.get(address as usize..(address as usize).saturating_add(size)) {
+        Some(memory) if !buffer.is_null() => {
+            ptr::copy_nonoverlapping(memory.as_ptr(), buffer, size);
+            CHIP8_OK
+        },
+        _ => CHIP8_ERROR,
+    }
+}
+
+/// copies size bytes of buffer into memory at address
+///
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed
+/// buffer must point to size readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn chip8_write_memory(machine: *mut Machine, address: u16, buffer: *const u8, size: usize) -> c_int {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return CHIP8_ERROR,
+    };
+    if buffer.is_null() || (address as usize).saturating_add(size) > MEMORY_SIZE {
+        return machine.fail(format!("{} bytes at {:03X} are out of memory", size, address));
+    }
+    machine.cpu.memory[address as usize..address as usize + size].copy_from_slice(slice::from_raw_parts(buffer, size));
+    CHIP8_OK
+}
+
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed
+/// registers must be null or point to a chip8_registers
+#[no_mangle]
+pub unsafe extern "C" fn chip8_get_registers(machine: *const Machine, registers: *mut Registers) -> c_int {
+    let (machine, registers) = match (machine.as_ref(), registers.as_mut()) {
+        (Some(machine), Some(registers)) => (machine, registers),
+        _ => return CHIP8_ERROR,
+    };
+    let cpu = &machine.cpu;
+    *registers = Registers {
+        v: cpu.v,
+        i: cpu.i as u16,
+        pc: cpu.pc,
+        sp: cpu.sp as u8,
+        stack: cpu.stack,
+        delay_timer: cpu.delay_timer,
+        sound_timer: cpu.sound_timer,
+    };
+    CHIP8_OK
+}
+
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed
+/// registers must be null or point to a chip8_registers
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_registers(machine: *mut Machine, registers: *const Registers) -> c_int {
+    let (machine, registers) = match (machine.as_mut(), registers.as_ref()) {
+        (Some(machine), Some(registers)) => (machine, registers),
+        _ => return CHIP8_ERROR,
+    };
+    if registers.i as usize >= MEMORY_SIZE || registers.pc as usize >= MEMORY_SIZE || registers.sp as usize >= STACK_SIZE {
+        return machine.fail(format!("registers out of range: i {:03X}, pc {:03X}, sp {}", registers.i, registers.pc, registers.sp));
+    }
+    let cpu = &mut machine.cpu;
+    cpu.v = registers.v;
+    cpu.i = registers.i as usize;
+    cpu.pc = registers.pc;
+    cpu.sp = registers.sp as usize;
+    cpu.stack = registers.stack;
+    cpu.delay_timer = registers.delay_timer;
+    cpu.sound_timer = registers.sound_timer;
+    CHIP8_OK
+}
+
+/// bytes of a save state, the same for every machine
+#[no_mangle]
+pub extern "C" fn chip8_state_size() -> usize {
+    state::SIZE
+}
+
+/// writes a save state of chip8_state_size bytes into buffer
+///
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed
+/// buffer must point to size writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn chip8_save_state(machine: *mut Machine, buffer: *mut u8, size: usize) -> c_int {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return CHIP8_ERROR,
+    };
+    if buffer.is_null() || size < state::SIZE {
+        return machine.fail(format!("save states take {} bytes, not {}", state::SIZE, size));
+    }
+    let state = machine.cpu.save_state();
+    ptr::copy_nonoverlapping(state.as_ptr(), buffer, state.len());
+    CHIP8_OK
+}
+
+/// # Safety
+/// machine must be null or come from chip8_create and not be destroyed
+/// buffer must point to size readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_state(machine: *mut Machine, buffer: *const u8, size: usize) -> c_int {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return CHIP8_ERROR,
+    };
+    if buffer.is_null() {
+        return machine.fail("no save state".to_string());
+    }
+    match machine.cpu.load_state(slice::from_raw_parts(buffer, size)) {
+        Ok(()) => CHIP8_OK,
+        Err(e) => machine.fail(e),
+    }
+}
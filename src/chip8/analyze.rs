@@ -0,0 +1,388 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use super::cpu::{self, *};
+use super::disasm::{disassemble, pattern};
+use super::frontend::headless::Headless;
+use super::inspect::accesses;
+use super::rom::crc32;
+use super::sound;
+
+/// frames between two key presses of the dynamic run, random keys get games past their title screens
+const KEY_INTERVAL: u64 = 300;
+/// frames a key stays pressed
+const KEY_HOLD: u64 = 60;
+/// addresses listed for each finding
+const EXAMPLES: usize = 8;
+
+/// Instruction set an opcode needs beyond the chip-8 of Cowgod's reference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Platform {
+    /// 0nnn calls into the COSMAC VIP 1802 routines
+    MachineCode,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    fn name(self) -> &'static str {
+        match self {
+            Platform::MachineCode => "cosmac vip",
+            Platform::SuperChip => "super-chip",
+            Platform::XoChip => "xo-chip",
+        }
+    }
+}
+
+/// the extension instruction an opcode is, if any
+fn extension(opcode: u16) -> Option<(Platform, &'static str)> {
+    let x = (opcode >> 8) & 0xF;
+    let n = opcode & 0xF;
+    Some(match (opcode >> 12, x, (opcode >> 4) & 0xF, n) {
+        (0x0, 0x0, 0xE, 0x0) | (0x0, 0x0, 0xE, 0xE) => return None,
+        (0x0, 0x0, 0xC, _) => (Platform::SuperChip, "00Cn SCD nibble"),
+        (0x0, 0x0, 0xD, _) => (Platform::XoChip, "00Dn SCU nibble"),
+        (0x0, 0x0, 0xF, 0xB) => (Platform::SuperChip, "00FB SCR"),
+        (0x0, 0x0, 0xF, 0xC) => (Platform::SuperChip, "00FC SCL"),
+        (0x0, 0x0, 0xF, 0xD) => (Platform::SuperChip, "00FD EXIT"),
+        (0x0, 0x0, 0xF, 0xE) => (Platform::SuperChip, "00FE LOW"),
+        (0x0, 0x0, 0xF, 0xF) => (Platform::SuperChip, "00FF HIGH"),
+        (0x0, _, _, _) => (Platform::MachineCode, "0nnn SYS addr"),
+        (0x5, _, _, 0x2) => (Platform::XoChip, "5xy2 SAVE Vx - Vy"),
+        (0x5, _, _, 0x3) => (Platform::XoChip, "5xy3 LOAD Vx - Vy"),
+        (0xD, _, _, 0x0) => (Platform::SuperChip, "Dxy0 DRW Vx, Vy, 0"),
+        (0xF, 0x0, 0x0, 0x0) => (Platform::XoChip, "F000 LD I, long addr"),
+        (0xF, _, 0x0, 0x1) => (Platform::XoChip, "Fn01 PLANE n"),
+        (0xF, 0x0, 0x0, 0x2) => (Platform::XoChip, "F002 AUDIO"),
+        (0xF, _, 0x3, 0x0) => (Platform::SuperChip, "Fx30 LD HF, Vx"),
+        (0xF, _, 0x3, 0xA) => (Platform::XoChip, "Fx3A PITCH Vx"),
+        (0xF, _, 0x7, 0x5) => (Platform::SuperChip, "Fx75 LD R, Vx"),
+        (0xF, _, 0x8, 0x5) => (Platform::SuperChip, "Fx85 LD Vx, R"),
+        _ => return None,
+    })
+}
+
+/// Behaviour the chip-8 interpreters disagree on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Quirk {
+    Shift,
+    LoadStore,
+    Jump,
+    SpriteWrap,
+    SelfModifying,
+}
+
+impl Quirk {
+    fn describe(self) -> &'static str {
+        match self {
+            Quirk::Shift => "8xy6/8xyE with x != y: the vip shifts Vy into Vx, chip-48 and super-chip shift Vx in place",
+            Quirk::LoadStore => "I used after Fx55/Fx65: the vip moves I past the registers, super-chip leaves it",
+            Quirk::Jump => "Bnnn: the vip jumps to nnn + V0, chip-48 and super-chip to xnn + Vx",
+            Quirk::SpriteWrap => "sprites crossing the screen edge: the vip clips them, some interpreters wrap them",
+            Quirk::SelfModifying => "self-modifying code: runs instructions it wrote itself",
+        }
+    }
+}
+
+/// Static and dynamic findings about a rom
+pub struct Analysis {
+    rom_size: usize,
+    crc32: u32,
+    /// rom addresses reached by following the control flow from 0x200
+    code: Vec<bool>,
+    patterns: BTreeMap<&'static str, usize>,
+    extensions: BTreeMap<(Platform, &'static str), BTreeSet<u16>>,
+    /// quirky instructions found in the code, whatever the values they run with
+    sites: BTreeMap<Quirk, BTreeSet<u16>>,
+    /// quirky instructions that ran with values the interpreters disagree on, and how often
+    hits: BTreeMap<Quirk, BTreeMap<u16, u64>>,
+    frames: u64,
+    executed: Vec<bool>,
+    written: Vec<bool>,
+    /// address of the last Fx55/Fx65 while I hasn't been loaded again
+    load_store: Option<u16>,
+    /// why the dynamic run ended before its frames
+    stopped: Option<String>,
+}
+
+/// analyzes the rom, then runs it for `frames` frames with random key presses
+pub fn analyze(rom: &[u8], frames: u64) -> Analysis {
+    let mut analysis = Analysis {
+        rom_size: rom.len(),
+        crc32: crc32(rom),
+        code: vec![false; MEMORY_SIZE],
+        patterns: BTreeMap::new(),
+        extensions: BTreeMap::new(),
+        sites: BTreeMap::new(),
+        hits: BTreeMap::new(),
+        frames: 0,
+        executed: vec![false; MEMORY_SIZE],
+        written: vec![false; MEMORY_SIZE],
+        load_store: None,
+        stopped: None,
+    };
+    analysis.walk(rom);
+    analysis.run(rom, frames);
+    analysis
+}
+
+fn opcode(memory: &[u8], address: usize) -> u16 {
+    u16::from_be_bytes([memory[address % memory.len()], memory[(address + 1) % memory.len()]])
+}
+
+/// instructions reading memory through I
+fn reads_i(opcode: u16) -> bool {
+    match (opcode >> 12, opcode & 0xFF) {
+        (0xD, _) => true,
+        (0xF, kk) => kk == 0x1E || kk == 0x33 || kk == 0x55 || kk == 0x65,
+        _ => false,
+    }
+}
+
+fn skips(opcode: u16) -> bool {
+    match (opcode >> 12, opcode & 0xFF) {
+        (0x3, _) | (0x4, _) => true,
+        (0x5, kk) | (0x9, kk) => kk & 0xF == 0,
+        (0xE, kk) => kk == 0x9E || kk == 0xA1,
+        _ => false,
+    }
+}
+
+impl Analysis {
+
+    /// disassembles what the control flow from 0x200 reaches, Bnnn targets are left to the dynamic run
+    fn walk(&mut self, rom: &[u8]) {
+        let mut memory = vec![0; MEMORY_SIZE];
+        let end = (0x200 + rom.len()).min(MEMORY_SIZE);
+        memory[0x200..end].copy_from_slice(&rom[..end - 0x200]);
+
+        let mut pending = vec![0x200];
+        while let Some(address) = pending.pop() {
+            if address < 0x200 || address + 1 >= end || self.code[address] {
+                continue;
+            }
+            let opcode = opcode(&memory, address);
+            let extension = extension(opcode);
+            // zeros are padding or a placeholder the program writes over, not a machine code call
+            if opcode == 0x0000 || (extension.is_none() && pattern(opcode) == "unknown") {
+                continue;
+            }
+            self.code[address] = true;
+            self.code[address + 1] = true;
+            *self.patterns.entry(extension.map_or_else(|| pattern(opcode), |(_, name)| name)).or_default() += 1;
+            if let Some(extension) = extension {
+                self.extensions.entry(extension).or_default().insert(address as u16);
+            }
+
+            let x = (opcode >> 8) & 0xF;
+            let y = (opcode >> 4) & 0xF;
+            let next = address + 2;
+            match opcode >> 12 {
+                0x8 if (opcode & 0xF == 0x6 || opcode & 0xF == 0xE) && x != y => {
+                    self.sites.entry(Quirk::Shift).or_default().insert(address as u16);
+                },
+                0xB => {
+                    self.sites.entry(Quirk::Jump).or_default().insert(address as u16);
+                },
+                0xF if (opcode & 0xFF == 0x55 || opcode & 0xFF == 0x65) && reads_i(self::opcode(&memory, next)) => {
+                    self.sites.entry(Quirk::LoadStore).or_default().insert(address as u16);
+                },
+                _ => {},
+            }
+
+            match opcode >> 12 {
+                _ if opcode == 0x00EE || opcode == 0x00FD => {},
+                0x1 => pending.push((opcode & 0x0FFF) as usize),
+                0x2 => pending.extend(&[next, (opcode & 0x0FFF) as usize]),
+                0xB => {},
+                // the long load is 4 bytes, skipping over it too
+                0xF if opcode == 0xF000 => pending.push(next + 2),
+                _ if skips(opcode) => {
+                    let skipped = if self::opcode(&memory, next) == 0xF000 { 4 } else { 2 };
+                    pending.extend(&[next, next + skipped]);
+                },
+                _ => pending.push(next),
+            }
+        }
+    }
+
+    fn hit(&mut self, quirk: Quirk, address: u16) {
+        *self.hits.entry(quirk).or_default().entry(address).or_default() += 1;
+    }
+
+    /// looks at the instruction under the pc before it runs
+    fn observe(&mut self, cpu: &Cpu) {
+        let pc = cpu.pc as usize % MEMORY_SIZE;
+        let opcode = opcode(&cpu.memory, pc);
+        let x = ((opcode >> 8) & 0xF) as usize;
+        let y = ((opcode >> 4) & 0xF) as usize;
+
+        if self.written[pc] || self.written[(pc + 1) % MEMORY_SIZE] {
+            self.hit(Quirk::SelfModifying, pc as u16);
+        }
+        self.executed[pc] = true;
+        self.executed[(pc + 1) % MEMORY_SIZE] = true;
+        if let Some(extension) = extension(opcode) {
+            self.extensions.entry(extension).or_default().insert(pc as u16);
+        }
+
+        match opcode >> 12 {
+            0x8 if (opcode & 0xF == 0x6 || opcode & 0xF == 0xE) && x != y && cpu.v[x] != cpu.v[y] => {
+                self.hit(Quirk::Shift, pc as u16);
+            },
+            0xB if x != 0 && cpu.v[x] != cpu.v[0] => self.hit(Quirk::Jump, pc as u16),
+            0xD => {
+                let (left, top) = (cpu.v[x] as usize % SCREEN_WIDTH as usize, cpu.v[y] as usize % SCREEN_HEIGHT as usize);
+                let crosses = (0..(opcode & 0xF) as usize).any(|row| {
+                    let byte = cpu.memory[(cpu.i + row) % MEMORY_SIZE];
+                    (0..8).any(|bit| byte & (0x80 >> bit) != 0
+                        && (left + bit >= SCREEN_WIDTH as usize || top + row >= SCREEN_HEIGHT as usize))
+                });
+                if crosses {
+                    self.hit(Quirk::SpriteWrap, pc as u16);
+                }
+            },
+            _ => {},
+        }
+
+        if reads_i(opcode) {
+            if let Some(address) = self.load_store {
+                self.hit(Quirk::LoadStore, address);
+            }
+        }
+        self.load_store = match (opcode >> 12, opcode & 0xFF) {
+            (0xF, 0x55) | (0xF, 0x65) => Some(pc as u16),
+            (0xA, _) | (0xF, 0x29) => None,
+            _ => self.load_store,
+        };
+
+        let (_, writes) = accesses(opcode, cpu.i);
+        for address in writes {
+            self.written[address % MEMORY_SIZE] = true;
+        }
+    }
+
+    fn run(&mut self, rom: &[u8], frames: u64) {
+        let mut cpu = cpu::initialize(Box::new(Headless), Box::new(sound::Null));
+        cpu.reseed(0);
+        cpu.bootup(rom.to_vec());
+        let mut keys = StdRng::seed_from_u64(0);
+        let mut key = 0;
+        while self.frames < frames {
+            match self.frames % KEY_INTERVAL {
+                0 => {
+                    key = keys.gen_range(0, KEYBOARD_SIZE);
+                    cpu.keyboard[key] = true;
+                },
+                KEY_HOLD => cpu.keyboard[key] = false,
+                _ => {},
+            }
+            self.observe(&cpu);
+            let (pc, opcode) = (cpu.pc, opcode(&cpu.memory, cpu.pc as usize));
+            cpu.frame();
+            self.frames += 1;
+            if cpu.quit {
+                self.stopped = Some(format!("stopped at {:03X} {}, not supported by this emulator", pc, disassemble(opcode)));
+                break;
+            }
+        }
+    }
+
+    /// the interpreter the rom most likely targets, with the reasons
+    fn variant(&self) -> (&'static str, Vec<&'static str>) {
+        let platforms: BTreeSet<Platform> = self.extensions.keys().map(|(platform, _)| *platform).collect();
+        let mut reasons = Vec::new();
+        if platforms.contains(&Platform::XoChip) {
+            reasons.push("uses xo-chip instructions");
+            return ("xo-chip", reasons);
+        }
+        if platforms.contains(&Platform::SuperChip) {
+            reasons.push("uses super-chip instructions");
+            return ("super-chip 1.1", reasons);
+        }
+        if platforms.contains(&Platform::MachineCode) {
+            reasons.push("calls cosmac vip machine code routines");
+        }
+        if !reasons.is_empty() {
+            return ("cosmac vip chip-8", reasons);
+        }
+        // code written for chip-48 names any Vy in shifts since it's ignored there
+        if self.hits.contains_key(&Quirk::Shift) {
+            reasons.push("shifts name a Vy holding another value, as chip-48 code does since it ignores Vy");
+            return ("chip-48 / super-chip", reasons);
+        }
+        if self.hits.contains_key(&Quirk::LoadStore) {
+            reasons.push("reads through I right after Fx55/Fx65 without loading it, as if I moved on");
+            return ("cosmac vip chip-8", reasons);
+        }
+        if self.hits.contains_key(&Quirk::Jump) {
+            reasons.push("Bnnn depends on the variant and nothing else tells them apart");
+            return ("chip-8, variant undecided", reasons);
+        }
+        reasons.push("no quirk dependence seen, runs the same on the vip, chip-48 and super-chip");
+        ("chip-8", reasons)
+    }
+
+    pub fn report(&self, out: &mut dyn Write) -> io::Result<()> {
+        let rom = 0x200..(0x200 + self.rom_size).min(MEMORY_SIZE);
+        let reached = rom.clone().filter(|address| self.code[*address]).count();
+        let executed = rom.clone().filter(|address| self.executed[*address]).count();
+        writeln!(out, "{} bytes rom, crc32 {:08X}", self.rom_size, self.crc32)?;
+        writeln!(out, "  {} bytes of code reached statically, {} executed in {} frames", reached, executed, self.frames)?;
+        if let Some(stopped) = &self.stopped {
+            writeln!(out, "  {}", stopped)?;
+        }
+
+        writeln!(out, "\nopcodes")?;
+        for (pattern, n) in &self.patterns {
+            writeln!(out, "  {:<24} {:>5}", pattern, n)?;
+        }
+
+        writeln!(out, "\nextensions")?;
+        if self.extensions.is_empty() {
+            writeln!(out, "  none")?;
+        }
+        for ((platform, name), addresses) in &self.extensions {
+            writeln!(out, "  {:<24} {:<12} at {}", name, platform.name(), list(addresses.iter()))?;
+        }
+
+        writeln!(out, "\nquirks (sites in the code, times the variants would have differed at run time)")?;
+        let quirks: BTreeSet<&Quirk> = self.sites.keys().chain(self.hits.keys()).collect();
+        if quirks.is_empty() {
+            writeln!(out, "  none")?;
+        }
+        for quirk in quirks {
+            let sites = self.sites.get(quirk).map_or(0, BTreeSet::len);
+            let hits = self.hits.get(quirk);
+            writeln!(out, "  {}", quirk.describe())?;
+            writeln!(out, "    {} sites, {} times", sites, hits.map_or(0, |hits| hits.values().sum::<u64>()))?;
+            if let Some(hits) = hits {
+                writeln!(out, "    at {}", list(hits.keys()))?;
+            } else if let Some(sites) = self.sites.get(quirk) {
+                writeln!(out, "    at {}", list(sites.iter()))?;
+            }
+        }
+
+        let (variant, reasons) = self.variant();
+        writeln!(out, "\nlikely interpreter: {}", variant)?;
+        for reason in reasons {
+            writeln!(out, "  {}", reason)?;
+        }
+        let quirks = Quirks::default();
+        writeln!(out, "  by default this emulator {}, {}, {} and has no Bnnn, --quirk shift, loadstore and clip change that",
+            if quirks.shift { "shifts Vy into Vx" } else { "shifts Vx in place" },
+            if quirks.load_store { "moves I past the registers after Fx55/Fx65" } else { "leaves I after Fx55/Fx65" },
+            if quirks.clip { "clips sprites" } else { "wraps sprites" })
+    }
+}
+
+fn list<'a>(addresses: impl Iterator<Item = &'a u16>) -> String {
+    let addresses: Vec<u16> = addresses.cloned().collect();
+    let mut text: Vec<String> = addresses.iter().take(EXAMPLES).map(|address| format!("{:03X}", address)).collect();
+    if addresses.len() > EXAMPLES {
+        text.push(format!("and {} more", addresses.len() - EXAMPLES));
+    }
+    text.join(", ")
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+/// An operand once labels are known
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    V(u16),
+    I,
+    /// [I]
+    Memory,
+    Dt,
+    St,
+    K,
+    F,
+    B,
+    Number(u16),
+}
+
+/// A source line worth assembling: where it goes and what it says
+struct Line<'a> {
+    number: usize,
+    address: u16,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+/// Assembles the mnemonics of Cowgod's chip-8 technical reference, as disasm writes them,
+/// into a rom loaded at `origin`:
+///
+/// ```text
+/// ; comments go after a semicolon
+/// start:  LD V0, 0x05        ; numbers in decimal, 0x or # hex and 0b binary
+///         CALL draw          ; labels wherever an address or a number goes
+///         JP start
+/// draw:   DRW V0, V1, 5
+///         RET
+/// sprite: DB 0xF0, 0x90      ; data bytes, DW for big endian words
+/// ```
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, String> {
+    let mut labels = HashMap::new();
+    let mut lines = Vec::new();
+    let mut address = origin as usize;
+    for (number, text) in source.lines().enumerate().map(|(number, text)| (number + 1, text)) {
+        let error = |message: String| format!("line {}: {}", number, message);
+        let mut text = text.split(';').next().unwrap_or("").trim();
+        if let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) {
+                return Err(error(format!("{} is not a label, they go as letters, digits and _", label)));
+            }
+            if labels.insert(label.to_ascii_uppercase(), address as u16).is_some() {
+                return Err(error(format!("label {} is defined twice", label)));
+            }
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let (mnemonic, operands) = match text.split_once(char::is_whitespace) {
+            Some((mnemonic, operands)) => (mnemonic, operands.split(',').map(str::trim).collect()),
+            None => (text, Vec::new()),
+        };
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        let size = match mnemonic.as_str() {
+            "DB" => operands.len(),
+            "DW" => operands.len() * 2,
+            _ => 2,
+        };
+        lines.push(Line { number, address: address as u16, mnemonic, operands });
+        address += size;
+        if address > 0x1000 {
+            return Err(error("the rom doesn't fit in memory".to_string()));
+        }
+    }
+
+    let mut rom = Vec::new();
+    for line in lines {
+        let error = |message: String| format!("line {}: {}", line.number, message);
+        let operands = line.operands.iter().map(|operand| parse(operand, &labels))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(error)?;
+        match line.mnemonic.as_str() {
+            "DB" => for operand in operands {
+                rom.push(value(operand, 0xFF).map_err(error)? as u8);
+            },
+            "DW" => for operand in operands {
+                rom.extend_from_slice(&value(operand, 0xFFFF).map_err(error)?.to_be_bytes());
+            },
+            _ => {
+                let opcode = encode(&line.mnemonic, &operands).map_err(error)?;
+                debug!("{:03X} {:04X} {} {:?}", line.address, opcode, line.mnemonic, operands);
+                rom.extend_from_slice(&opcode.to_be_bytes());
+            },
+        }
+    }
+    Ok(rom)
+}
+
+fn is_label(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && register(text).is_none()
+}
+
+/// the registers and the other names instructions take
+fn register(text: &str) -> Option<Operand> {
+    let text = text.to_ascii_uppercase();
+    Some(match text.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::Memory,
+        "DT" => Operand::Dt,
+        "ST" => Operand::St,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "B" => Operand::B,
+        _ => match text.strip_prefix('V') {
+            Some(x) if x.len() == 1 => Operand::V(u16::from_str_radix(x, 16).ok()?),
+            _ => return None,
+        },
+    })
+}
+
+fn parse(text: &str, labels: &HashMap<String, u16>) -> Result<Operand, String> {
+    if let Some(register) = register(text) {
+        return Ok(register);
+    }
+    let lower = text.to_ascii_lowercase();
+    let number = if let Some(hex) = lower.strip_prefix("0x").or_else(|| lower.strip_prefix('#')) {
+        u16::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u16::from_str_radix(binary, 2).ok()
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        lower.parse().ok()
+    } else {
+        labels.get(&text.to_ascii_uppercase()).copied()
+    };
+    number.map(Operand::Number).ok_or_else(|| match text {
+        "" => "missing operand".to_string(),
+        _ if is_label(text) => format!("label {} is not defined", text),
+        _ => format!("{} is not an operand", text),
+    })
+}
+
+/// the number of an operand, up to max
+fn value(operand: Operand, max: u16) -> Result<u16, String> {
+    match operand {
+        Operand::Number(number) if number <= max => Ok(number),
+        Operand::Number(number) => Err(format!("{:#X} is bigger than {:#X}", number, max)),
+        operand => Err(format!("{:?} is not a number", operand)),
+    }
+}
+
+fn encode(mnemonic: &str, operands: &[Operand]) -> Result<u16, String> {
+    use Operand::*;
+    let nnn = |operand| value(operand, 0xFFF);
+    let kk = |operand| value(operand, 0xFF);
+    let n = |operand| value(operand, 0xF);
+    Ok(match (mnemonic, operands) {
+        ("CLS", []) => 0x00E0,
+        ("RET", []) => 0x00EE,
+        ("SYS", [addr]) => nnn(*addr)?,
+        ("JP", [V(0), addr]) => 0xB000 | nnn(*addr)?,
+        ("JP", [addr]) => 0x1000 | nnn(*addr)?,
+        ("CALL", [addr]) => 0x2000 | nnn(*addr)?,
+        ("SE", [V(x), V(y)]) => 0x5000 | x << 8 | y << 4,
+        ("SE", [V(x), byte]) => 0x3000 | x << 8 | kk(*byte)?,
+        ("SNE", [V(x), V(y)]) => 0x9000 | x << 8 | y << 4,
+        ("SNE", [V(x), byte]) => 0x4000 | x << 8 | kk(*byte)?,
+        ("LD", [V(x), V(y)]) => 0x8000 | x << 8 | y << 4,
+        ("LD", [V(x), Dt]) => 0xF007 | x << 8,
+        ("LD", [V(x), K]) => 0xF00A | x << 8,
+        ("LD", [V(x), Memory]) => 0xF065 | x << 8,
+        ("LD", [V(x), byte]) => 0x6000 | x << 8 | kk(*byte)?,
+        ("LD", [I, addr]) => 0xA000 | nnn(*addr)?,
+        ("LD", [Dt, V(x)]) => 0xF015 | x << 8,
+        ("LD", [St, V(x)]) => 0xF018 | x << 8,
+        ("LD", [F, V(x)]) => 0xF029 | x << 8,
+        ("LD", [B, V(x)]) => 0xF033 | x << 8,
+        ("LD", [Memory, V(x)]) => 0xF055 | x << 8,
+        ("ADD", [I, V(x)]) => 0xF01E | x << 8,
+        ("ADD", [V(x), V(y)]) => 0x8004 | x << 8 | y << 4,
+        ("ADD", [V(x), byte]) => 0x7000 | x << 8 | kk(*byte)?,
+        ("OR", [V(x), V(y)]) => 0x8001 | x << 8 | y << 4,
+        ("AND", [V(x), V(y)]) => 0x8002 | x << 8 | y << 4,
+        ("XOR", [V(x), V(y)]) => 0x8003 | x << 8 | y << 4,
+        ("SUB", [V(x), V(y)]) => 0x8005 | x << 8 | y << 4,
+        ("SHR", [V(x), V(y)]) => 0x8006 | x << 8 | y << 4,
+        ("SHR", [V(x)]) => 0x8006 | x << 8 | x << 4,
+        ("SUBN", [V(x), V(y)]) => 0x8007 | x << 8 | y << 4,
+        ("SHL", [V(x), V(y)]) => 0x800E | x << 8 | y << 4,
+        ("SHL", [V(x)]) => 0x800E | x << 8 | x << 4,
+        ("RND", [V(x), byte]) => 0xC000 | x << 8 | kk(*byte)?,
+        ("DRW", [V(x), V(y), nibble]) => 0xD000 | x << 8 | y << 4 | n(*nibble)?,
+        ("SKP", [V(x)]) => 0xE09E | x << 8,
+        ("SKNP", [V(x)]) => 0xE0A1 | x << 8,
+        _ => return Err(format!("{} doesn't take {:?}", mnemonic, operands)),
+    })
+}
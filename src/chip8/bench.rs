@@ -0,0 +1,161 @@
+#[cfg(feature = "count-allocations")]
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use super::cpu::*;
+use super::disasm::pattern;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED: AtomicU64 = AtomicU64::new(0);
+
+/// The system allocator counting allocations and bytes, the binary installs it as global allocator
+#[cfg(feature = "count-allocations")]
+pub struct Counting;
+
+#[cfg(feature = "count-allocations")]
+unsafe impl GlobalAlloc for Counting {
+
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_add(new_size as u64, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// allocations and bytes allocated so far, None when Counting isn't the global allocator
+fn allocations() -> Option<(u64, u64)> {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    drop(std::hint::black_box(Box::new(0u8)));
+    let after = ALLOCATIONS.load(Ordering::Relaxed);
+    if after == before {
+        return None;
+    }
+    Some((after, ALLOCATED.load(Ordering::Relaxed)))
+}
+
+/// Time spent on the instructions of a pattern
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Timing {
+    pub count: u64,
+    pub total: Duration,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub instructions: u64,
+    pub elapsed: Duration,
+    /// allocations and bytes allocated while running, None when they weren't counted
+    pub allocations: Option<(u64, u64)>,
+    /// by instruction pattern, the cost of reading the clock taken off
+    pub opcodes: BTreeMap<&'static str, Timing>,
+    /// what reading the clock around an instruction costs
+    pub overhead: Duration,
+    /// why the run stopped before its cycles
+    pub fault: Option<String>,
+}
+
+/// the least time between two reads of the clock
+fn clock_overhead() -> Duration {
+    (0..1000).map(|_| {
+        let start = Instant::now();
+        start.elapsed()
+    }).min().unwrap_or_default()
+}
+
+/// runs up to `cycles` instructions as fast as they go, until the machine quits, returns how many ran
+pub fn spin(cpu: &mut Cpu, cycles: u64) -> u64 {
+    let instructions = cpu.clock.instructions;
+    for _ in 0..cycles {
+        cpu.frame();
+        if cpu.quit {
+            break;
+        }
+    }
+    cpu.clock.instructions - instructions
+}
+
+/// Runs `cycles` instructions of a booted machine as fast as they go with nothing else timed,
+/// then runs them again from the same state timing every instruction
+pub fn run(cpu: &mut Cpu, cycles: u64) -> Report {
+    let boot = cpu.save_state();
+
+    let allocations_before = allocations();
+    let start = Instant::now();
+    let instructions = spin(cpu, cycles);
+    let elapsed = start.elapsed();
+    let allocations_after = allocations();
+    let fault = cpu.fault.take();
+
+    // the rng comes back with the state, the second run goes the same way
+    cpu.load_state(&boot).expect("the state was just saved");
+    let overhead = clock_overhead();
+    let mut opcodes: BTreeMap<&'static str, Timing> = BTreeMap::new();
+    for _ in 0..instructions {
+        let pc = cpu.pc as usize;
+        let opcode = u16::from_be_bytes([cpu.memory[pc % MEMORY_SIZE], cpu.memory[(pc + 1) % MEMORY_SIZE]]);
+        let start = Instant::now();
+        cpu.frame();
+        let elapsed = start.elapsed();
+        let timing = opcodes.entry(pattern(opcode)).or_default();
+        timing.count += 1;
+        timing.total += elapsed.saturating_sub(overhead);
+        if cpu.quit {
+            break;
+        }
+    }
+    cpu.fault = None;
+
+    Report {
+        instructions,
+        elapsed,
+        allocations: allocations_before.zip(allocations_after)
+            // the probe of allocations() is one of them
+            .map(|((count, bytes), (count_after, bytes_after))| (count_after - count - 1, bytes_after - bytes - 1)),
+        opcodes,
+        overhead,
+        fault,
+    }
+}
+
+impl Report {
+
+    pub fn instructions_per_second(&self) -> f64 {
+        self.instructions as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn print(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{} instructions in {:.3} s, {:.0} instructions/s", self.instructions,
+            self.elapsed.as_secs_f64(), self.instructions_per_second())?;
+        match self.allocations {
+            Some((count, bytes)) => writeln!(out, "{} allocations, {} bytes, while running", count, bytes)?,
+            None => writeln!(out, "allocations not counted")?,
+        }
+        if let Some(fault) = &self.fault {
+            writeln!(out, "stopped early: {}", fault)?;
+        }
+        writeln!(out)?;
+        writeln!(out, "  {:<24} {:>10} {:>7} {:>10}", "opcode", "count", "time", "ns each")?;
+        let total: Duration = self.opcodes.values().map(|timing| timing.total).sum();
+        let mut opcodes: Vec<_> = self.opcodes.iter().collect();
+        opcodes.sort_by_key(|(_, timing)| std::cmp::Reverse(timing.total));
+        for (pattern, timing) in opcodes {
+            writeln!(out, "  {:<24} {:>10} {:>6.1}% {:>10.1}", pattern, timing.count,
+                timing.total.as_secs_f64() * 100.0 / total.as_secs_f64().max(f64::MIN_POSITIVE),
+                timing.total.as_nanos() as f64 / timing.count as f64)?;
+        }
+        writeln!(out, "  clock reads of {} ns taken off every instruction", self.overhead.as_nanos())
+    }
+}
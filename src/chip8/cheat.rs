@@ -0,0 +1,162 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use super::cpu::*;
+use super::rom::crc32;
+
+/// A cheat code: freezes are rewritten every frame, patches are written once at bootup
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Code {
+    Freeze(u16, u8),
+    Patch(u16, u8),
+}
+
+#[derive(Debug, Clone)]
+pub struct Cheat {
+    pub name: String,
+    pub codes: Vec<Code>,
+}
+
+/// Cheat file, one section per rom keyed by the crc32 of the rom, then one cheat per line
+/// as `name: codes`, `address:value` freezes a byte and `address=value` patches it, in hex.
+/// Blank lines and lines starting with # are ignored:
+///
+/// ```text
+/// [A1B2C3D4] Pong
+/// infinite lives: 2F6:03
+/// skip the intro: 22A=12 22B=54
+/// ```
+pub type Database = HashMap<u32, Vec<Cheat>>;
+
+/// Iterative memory search, every filter keeps the candidates matching against the last snapshot
+#[derive(Debug, Default)]
+pub struct Search {
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Comparison {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+/// Cheats layered on the cpu: the rom cheat database and the frozen addresses
+#[derive(Debug, Default)]
+pub struct Cheats {
+    pub(crate) database: Database,
+    pub(crate) frozen: BTreeMap<u16, u8>,
+    /// driven by scripts, which the browser build doesn't have
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) search: Search,
+}
+
+/// parses `address:value` or `address=value`, in hex
+pub(crate) fn code(text: &str) -> Option<Code> {
+    let parse = |address: &str, value: &str| Some((
+        u16::from_str_radix(address.trim_start_matches("0x"), 16).ok().filter(|a| (*a as usize) < MEMORY_SIZE)?,
+        u8::from_str_radix(value.trim_start_matches("0x"), 16).ok()?,
+    ));
+    if let Some((address, value)) = text.split_once(':') {
+        return parse(address, value).map(|(a, v)| Code::Freeze(a, v));
+    }
+    let (address, value) = text.split_once('=')?;
+    parse(address, value).map(|(a, v)| Code::Patch(a, v))
+}
+
+pub fn load(path: &Path) -> Result<Database, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("couldn't read cheats: {}", e))?;
+    parse(&path.display().to_string(), &text)
+}
+
+/// `path` only names the file in errors
+pub fn parse(path: &str, text: &str) -> Result<Database, String> {
+    let mut database = Database::new();
+    let mut rom = None;
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let bad = || format!("bad cheat at {}:{}", path, n + 1);
+        if let Some(section) = line.strip_prefix('[') {
+            let (crc, _) = section.split_once(']').ok_or_else(bad)?;
+            rom = Some(u32::from_str_radix(crc.trim(), 16).map_err(|_| bad())?);
+            continue;
+        }
+        let crc = rom.ok_or_else(bad)?;
+        let (name, codes) = line.rsplit_once(": ").ok_or_else(bad)?;
+        let codes = codes.split_whitespace().map(code).collect::<Option<Vec<Code>>>().ok_or_else(bad)?;
+        database.entry(crc).or_default().push(Cheat { name: name.trim().to_string(), codes });
+    }
+    Ok(database)
+}
+
+impl Search {
+
+    /// every address is a candidate again
+    pub fn start(&mut self, memory: &[u8]) {
+        self.snapshot = memory.to_vec();
+        self.candidates = (0..memory.len() as u16).collect();
+    }
+
+    pub fn filter(&mut self, memory: &[u8], comparison: Comparison) {
+        if self.snapshot.is_empty() {
+            self.start(memory);
+        }
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|address| {
+            let (before, now) = (snapshot[*address as usize], memory[*address as usize]);
+            match comparison {
+                Comparison::Equal(value) => now == value,
+                Comparison::Changed => now != before,
+                Comparison::Unchanged => now == before,
+                Comparison::Increased => now > before,
+                Comparison::Decreased => now < before,
+            }
+        });
+        self.snapshot = memory.to_vec();
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+}
+
+impl Cpu {
+
+    /// applies the cheats the database has for the rom being booted
+    pub(crate) fn apply_cheats(&mut self, program_buffer: &[u8]) {
+        let crc = crc32(program_buffer);
+        let cheats = match self.cheats.database.get(&crc) {
+            Some(cheats) => cheats.clone(),
+            None => return debug!("no cheats for rom {:08X}", crc),
+        };
+        for cheat in cheats {
+            for code in &cheat.codes {
+                self.apply_code(*code);
+            }
+            self.notify(format!("cheat: {}", cheat.name));
+        }
+    }
+
+    pub(crate) fn apply_code(&mut self, code: Code) {
+        match code {
+            Code::Freeze(address, value) => {
+                self.cheats.frozen.insert(address, value);
+            },
+            Code::Patch(address, value) => self.memory[address as usize] = value,
+        }
+    }
+
+    /// rewrites the frozen addresses
+    pub(crate) fn freeze(&mut self) {
+        for (address, value) in &self.cheats.frozen {
+            self.memory[*address as usize] = *value;
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::{thread, time};
+
+use super::cpu::*;
+
+// sound kept queued when the audio output drives the clock
+pub(crate) const AUDIO_LATENCY: std::time::Duration = time::Duration::from_millis(3 * 1000 / 60);
+
+const MAX_FAST_FORWARD: f32 = 64.0;
+
+/// most emulated frames run for one host frame, so a huge speed still lets the host present and read input
+const MAX_FRAMES_DUE: f32 = 1000.0;
+
+/// Emulation speed, counted in emulated frames of 1/60 s
+/// run for every frame presented by the host
+pub struct Clock {
+    pub(crate) paused: bool,
+    pub(crate) advance: bool,
+    /// emulated frames per host frame, 1.0 is real time
+    pub(crate) speed: f32,
+    /// speed used by the fast-forward toggle
+    pub(crate) fast_forward: f32,
+    /// speed used by the slow motion toggle
+    pub(crate) slow_motion: f32,
+    /// silence the buzzer while fast forwarding instead of keeping its pitch
+    pub(crate) fast_forward_mute: bool,
+    pub(crate) frames: u64,
+    pub(crate) instructions: u64,
+    pub(crate) frame_limit: Option<u64>,
+    budget: f32,
+}
+
+impl Clock {
+
+    pub(crate) fn new() -> Clock {
+        Clock {
+            paused: false,
+            advance: false,
+            speed: 1.0,
+            fast_forward: 4.0,
+            slow_motion: 0.25,
+            fast_forward_mute: false,
+            frames: 0,
+            instructions: 0,
+            frame_limit: None,
+            budget: 0.0,
+        }
+    }
+
+    /// emulated frames to run before presenting the next host frame
+    pub(crate) fn frames_due(&mut self) -> u32 {
+        if self.paused {
+            let advance = self.advance;
+            self.advance = false;
+            return if advance { 1 } else { 0 };
+        }
+        self.budget = (self.budget + self.speed).min(MAX_FRAMES_DUE);
+        let due = self.budget.floor();
+        self.budget -= due;
+        due as u32
+    }
+
+    pub(crate) fn fast_forwarding(&self) -> bool {
+        !self.paused && self.speed > 1.0
+    }
+}
+
+impl Cpu {
+
+    pub(crate) fn toggle_pause(&mut self) {
+        self.clock.paused = !self.clock.paused;
+        self.notify(if self.clock.paused { "paused" } else { "resumed" }.to_string());
+    }
+
+    pub(crate) fn advance_frame(&mut self) {
+        if self.clock.paused {
+            self.clock.advance = true;
+        }
+    }
+
+    pub(crate) fn toggle_speed(&mut self, speed: f32) {
+        self.clock.speed = if self.clock.speed == speed { 1.0 } else { speed };
+        self.notify(format!("speed x{}", self.clock.speed));
+    }
+
+    /// doubles or halves the fast-forward multiplier, the current speed follows when fast forwarding
+    pub(crate) fn scale_fast_forward(&mut self, factor: f32) {
+        let fast_forwarding = self.clock.speed == self.clock.fast_forward;
+        self.clock.fast_forward = (self.clock.fast_forward * factor).clamp(2.0, MAX_FAST_FORWARD);
+        if fast_forwarding {
+            self.clock.speed = self.clock.fast_forward;
+        }
+        self.notify(format!("fast-forward x{}", self.clock.fast_forward));
+    }
+
+    /// feeds the audio output once per host frame, so the tone keeps its pitch whatever the speed
+    pub(crate) fn buzz(&mut self) {
+        let silent = self.muted || self.clock.paused ||
+            (self.clock.fast_forward_mute && self.clock.fast_forwarding());
+        self.audio.buzz(self.sound_timer > 0 && !silent);
+    }
+
+    pub(crate) fn wait(&mut self) {
+        // nothing to show, fast forward as fast as possible
+        if self.clock.fast_forwarding() && !self.frontend.realtime() {
+            return;
+        }
+        if self.audio_sync {
+            // paced by the audio output, wait until it has played enough of the queue
+            if let Some(queued) = self.audio.queued() {
+                if queued > AUDIO_LATENCY {
+                    thread::sleep(queued - AUDIO_LATENCY);
+                }
+                return;
+            }
+        }
+        thread::sleep(WAIT_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// frames due over `host_frames` host frames of 1/60 s
+    fn due(clock: &mut Clock, host_frames: usize) -> Vec<u32> {
+        (0..host_frames).map(|_| clock.frames_due()).collect()
+    }
+
+    #[test]
+    fn real_time() {
+        let mut clock = Clock::new();
+        assert_eq!(due(&mut clock, 4), [1, 1, 1, 1]);
+        assert!(!clock.fast_forwarding());
+    }
+
+    #[test]
+    fn fast_forward_and_slow_motion() {
+        let mut clock = Clock::new();
+        clock.speed = clock.fast_forward;
+        assert_eq!(due(&mut clock, 3), [4, 4, 4]);
+        assert!(clock.fast_forwarding());
+
+        clock.speed = clock.slow_motion;
+        assert_eq!(due(&mut clock, 8), [0, 0, 0, 1, 0, 0, 0, 1]);
+
+        // fractions carry over to the next host frame
+        clock.speed = 1.5;
+        assert_eq!(due(&mut clock, 4), [1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn pause_and_advance() {
+        let mut clock = Clock::new();
+        clock.speed = 0.5;
+        assert_eq!(due(&mut clock, 1), [0]);
+        clock.paused = true;
+        assert_eq!(due(&mut clock, 3), [0, 0, 0]);
+        clock.advance = true;
+        assert_eq!(due(&mut clock, 3), [1, 0, 0], "one frame per advance");
+        assert!(!clock.fast_forwarding());
+
+        // the half frame budgeted before the pause is still there
+        clock.paused = false;
+        assert_eq!(due(&mut clock, 2), [1, 0]);
+    }
+
+    #[test]
+    fn catch_up_is_capped() {
+        let mut clock = Clock::new();
+        clock.speed = 1e9;
+        assert_eq!(due(&mut clock, 2), [MAX_FRAMES_DUE as u32; 2]);
+        clock.speed = f32::INFINITY;
+        assert_eq!(due(&mut clock, 2), [MAX_FRAMES_DUE as u32; 2]);
+        clock.speed = 1.0;
+        assert_eq!(due(&mut clock, 2), [1, 1], "nothing left over from the capped frames");
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::cpu::*;
+use super::frontend::sdl;
+use super::rom;
+use super::sound::{Tone, Waveform};
+
+/// Settings of the config file, all of them optional. The global ones override the defaults,
+/// the ones of the rom in `roms` override the global ones, and the command line all of them:
+///
+/// ```toml
+/// speed = 1.5
+/// [palette]
+/// foreground = "#33FF66"
+/// [keymap]
+/// 5 = "Space"
+///
+/// # crc32 of the rom like cheat files, or its file name
+/// [roms.7D75A857]
+/// quirks = { shift = true }
+/// [roms."blitz.ch8"]
+/// load_address = 0x200
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// emulated frames per host frame, 1.0 is real time
+    pub speed: Option<f32>,
+    /// window pixels per chip-8 pixel
+    pub scale: Option<u32>,
+    /// where the rom is loaded and starts
+    pub load_address: Option<u16>,
+    pub quirks: QuirkProfile,
+    pub palette: Palette,
+    /// sdl key names of the chip-8 keys, by hex digit
+    pub keymap: BTreeMap<String, String>,
+    pub audio: AudioProfile,
+}
+
+/// see cpu::Quirks
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuirkProfile {
+    pub shift: Option<bool>,
+    pub load_store: Option<bool>,
+    pub clip: Option<bool>,
+}
+
+/// colours of the sdl window as #RRGGBB
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Palette {
+    pub foreground: Option<String>,
+    pub background: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioProfile {
+    /// square, sine or triangle
+    pub waveform: Option<String>,
+    pub frequency: Option<f32>,
+    pub volume: Option<f32>,
+    pub mute: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// the file read, if any
+    pub path: Option<PathBuf>,
+    global: Profile,
+    roms: BTreeMap<String, Profile>,
+}
+
+/// $XDG_CONFIG_HOME/chip8/config.toml or the platform equivalent
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("chip8").join("config.toml"))
+}
+
+/// reads the config file at path, or at the default path where a missing file is an empty config
+pub fn load(path: Option<&Path>) -> Result<Config, String> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => match default_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Config::default()),
+        },
+    };
+    let text = fs::read_to_string(&path).map_err(|e| format!("couldn't read the config {}: {}", path.display(), e))?;
+    let mut config = parse(&path.display().to_string(), &text)?;
+    config.path = Some(path);
+    Ok(config)
+}
+
+pub fn parse(path: &str, text: &str) -> Result<Config, String> {
+    let bad = |e: toml::de::Error| format!("bad config {}: {}", path, e);
+    let mut table: toml::value::Table = toml::from_str(text).map_err(bad)?;
+    let roms: BTreeMap<String, Profile> = match table.remove("roms") {
+        Some(roms) => roms.try_into().map_err(bad)?,
+        None => BTreeMap::new(),
+    };
+    let global: Profile = toml::Value::Table(table).try_into().map_err(bad)?;
+    for profile in std::iter::once(&global).chain(roms.values()) {
+        profile.speed().map_err(|e| format!("bad config {}: {}", path, e))?;
+    }
+    Ok(Config { path: None, global, roms })
+}
+
+fn colour(text: &str) -> Result<(u8, u8, u8), String> {
+    let hex = text.strip_prefix('#').filter(|hex| hex.len() == 6 && hex.is_ascii())
+        .ok_or_else(|| format!("{} is not a colour, they go as #RRGGBB", text))?;
+    let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).map_err(|_| format!("{} is not a colour, they go as #RRGGBB", text));
+    Ok((channel(0)?, channel(2)?, channel(4)?))
+}
+
+impl Config {
+
+    /// the settings of a rom, found by crc32 first and then by file name
+    pub fn rom(&self, path: Option<&Path>, rom: &[u8]) -> Option<&Profile> {
+        let crc = format!("{:08X}", rom::crc32(rom));
+        let name = path.and_then(Path::file_name).and_then(|name| name.to_str());
+        self.roms.iter().find(|(key, _)| key.eq_ignore_ascii_case(&crc))
+            .or_else(|| self.roms.iter().find(|(key, _)| Some(key.as_str()) == name))
+            .map(|(_, profile)| profile)
+    }
+
+    /// every setting, from the command line, the rom, the global ones or the defaults
+    pub fn effective(&self, command_line: &Profile, path: Option<&Path>, rom: &[u8]) -> Profile {
+        let mut profile = command_line.clone();
+        if let Some(rom) = self.rom(path, rom) {
+            profile = profile.or(rom);
+        }
+        profile.or(&self.global).or(&Profile::defaults())
+    }
+}
+
+impl Profile {
+
+    /// the settings this interpreter always ran with
+    pub fn defaults() -> Profile {
+        Profile {
+            speed: Some(1.0),
+            scale: Some(sdl::SCALE_FACTOR),
+            load_address: Some(0x200),
+            quirks: QuirkProfile {
+                shift: Some(false),
+                load_store: Some(false),
+                clip: Some(false),
+            },
+            palette: Palette {
+                foreground: Some("#FFFFFF".to_string()),
+                background: Some("#000000".to_string()),
+            },
+            keymap: (0..KEYBOARD_SIZE).map(|key| (format!("{:X}", key), format!("{:X}", key))).collect(),
+            audio: AudioProfile {
+                waveform: Some("sine".to_string()),
+                frequency: Some(440.0),
+                volume: Some(1.0),
+                mute: Some(false),
+            },
+        }
+    }
+
+    /// these settings, and the ones of other where these have none
+    pub fn or(self, other: &Profile) -> Profile {
+        let mut keymap = other.keymap.clone();
+        keymap.extend(self.keymap);
+        Profile {
+            speed: self.speed.or(other.speed),
+            scale: self.scale.or(other.scale),
+            load_address: self.load_address.or(other.load_address),
+            quirks: QuirkProfile {
+                shift: self.quirks.shift.or(other.quirks.shift),
+                load_store: self.quirks.load_store.or(other.quirks.load_store),
+                clip: self.quirks.clip.or(other.quirks.clip),
+            },
+            palette: Palette {
+                foreground: self.palette.foreground.or_else(|| other.palette.foreground.clone()),
+                background: self.palette.background.or_else(|| other.palette.background.clone()),
+            },
+            keymap,
+            audio: AudioProfile {
+                waveform: self.audio.waveform.or_else(|| other.audio.waveform.clone()),
+                frequency: self.audio.frequency.or(other.audio.frequency),
+                volume: self.audio.volume.or(other.audio.volume),
+                mute: self.audio.mute.or(other.audio.mute),
+            },
+        }
+    }
+
+    pub fn to_toml(&self) -> Result<String, String> {
+        toml::to_string(self).map_err(|e| e.to_string())
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        Quirks {
+            shift: self.quirks.shift.unwrap_or_default(),
+            load_store: self.quirks.load_store.unwrap_or_default(),
+            clip: self.quirks.clip.unwrap_or_default(),
+        }
+    }
+
+    /// zero or less would never run a frame
+    pub fn speed(&self) -> Result<f32, String> {
+        match self.speed.unwrap_or(1.0) {
+            speed if speed.is_finite() && speed > 0.0 => Ok(speed),
+            speed => Err(format!("speed {} is not above 0", speed)),
+        }
+    }
+
+    pub fn load_address(&self) -> Result<u16, String> {
+        match self.load_address.unwrap_or(0x200) {
+            address if (address as usize) < MEMORY_SIZE => Ok(address),
+            address => Err(format!("load address {:X} is out of memory", address)),
+        }
+    }
+
+    pub fn tone(&self) -> Result<Tone, String> {
+        Ok(Tone {
+            waveform: self.audio.waveform.as_deref().unwrap_or("sine").parse::<Waveform>()
+                .map_err(|e| format!("bad waveform, {}", e))?,
+            frequency: self.audio.frequency.unwrap_or(440.0),
+            volume: self.audio.volume.unwrap_or(1.0),
+        })
+    }
+
+    pub fn sdl(&self) -> Result<sdl::Settings, String> {
+        let mut keymap: [String; KEYBOARD_SIZE] = Default::default();
+        for (key, name) in &self.keymap {
+            let key = usize::from_str_radix(key, 16).ok().filter(|key| *key < KEYBOARD_SIZE)
+                .ok_or_else(|| format!("keymap: {} is not a key, keys go from 0 to F", key))?;
+            keymap[key] = name.clone();
+        }
+        if let Some(key) = keymap.iter().position(String::is_empty) {
+            return Err(format!("keymap: no key for {:X}", key));
+        }
+        Ok(sdl::Settings {
+            scale_factor: self.scale.unwrap_or(sdl::SCALE_FACTOR).max(1),
+            foreground: colour(self.palette.foreground.as_deref().unwrap_or("#FFFFFF"))?,
+            background: colour(self.palette.background.as_deref().unwrap_or("#000000"))?,
+            keymap,
+        })
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use serde_json::{json, Value};
+
+use super::cpu::*;
+use super::dap::base64;
+
+/// json-rpc error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const FAILED: i64 = -32000;
+
+type Clients = Arc<Mutex<HashMap<usize, TcpStream>>>;
+
+/// a request of a client, or why its line couldn't be parsed
+type Request = (usize, Result<Value, String>);
+
+/// Remote control of the running machine for test automation: json-rpc 2.0 over tcp,
+/// one message per line. Requests are served between host frames:
+///
+/// ```text
+/// pause, resume                    -> {"pc"}
+/// step {"frames": 1}               -> {"pc", "frames"}, pauses
+/// reset, load {"path"}             boots the rom again, or another one
+/// press {"key"}, release {"key"}   keys from 0 to 15
+/// registers                        -> {"v", "i", "pc", "sp", "stack", "dt", "st", "frames", "paused"}
+/// memory {"address", "length"}     -> {"address", "data"}, data in base64
+/// framebuffer                      -> {"frame", "width", "height", "pixels"}, a base64 byte per pixel
+/// breakpoints {"addresses": []}    replaces the breakpoints
+/// subscribe {"every": 1}           frame notifications every n host frames, 0 stops them
+/// quit
+/// ```
+///
+/// Every client gets the notifications: `frame` with the framebuffer, `breakpoint` with the pc
+/// once the machine paused on one, and `quit`.
+pub struct Server {
+    address: SocketAddr,
+    requests: Receiver<Request>,
+    clients: Clients,
+    rom: Vec<u8>,
+    breakpoints: BTreeSet<u16>,
+    /// host frames between frame notifications, None when nobody subscribed
+    every: Option<u64>,
+    host_frames: u64,
+    /// the instruction under the pc runs even if it holds a breakpoint
+    resumed: bool,
+}
+
+/// listens on a tcp address ("port" or "host:port"), clients connect at any time
+pub fn listen(address: &str, rom: Vec<u8>) -> Result<Server, String> {
+    let address = if address.contains(':') { address.to_string() } else { format!("127.0.0.1:{}", address) };
+    let listener = TcpListener::bind(&address).map_err(|e| format!("couldn't listen on {}: {}", address, e))?;
+    let address = listener.local_addr().map_err(|e| e.to_string())?;
+    info!("remote control on {}", address);
+
+    let (sender, requests) = mpsc::channel();
+    let clients = Clients::default();
+    let accepted = clients.clone();
+    thread::spawn(move || {
+        for (id, stream) in listener.incoming().enumerate() {
+            match stream {
+                Ok(stream) => connect(id, stream, &accepted, sender.clone()),
+                Err(e) => error!("couldn't accept a control client: {}", e),
+            }
+        }
+    });
+
+    Ok(Server {
+        address,
+        requests,
+        clients,
+        rom,
+        breakpoints: BTreeSet::new(),
+        every: None,
+        host_frames: 0,
+        resumed: false,
+    })
+}
+
+/// reads the requests of a client on its own thread
+fn connect(id: usize, stream: TcpStream, clients: &Clients, sender: Sender<Request>) {
+    let reader = match stream.try_clone() {
+        Ok(reader) => reader,
+        Err(e) => {
+            error!("couldn't read the control client: {}", e);
+            return;
+        },
+    };
+    if let Ok(peer) = stream.peer_addr() {
+        info!("control client {} connected from {}", id, peer);
+    }
+    clients.lock().unwrap().insert(id, stream);
+    let clients = clients.clone();
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            if sender.send((id, serde_json::from_str(&line).map_err(|e| e.to_string()))).is_err() {
+                break;
+            }
+        }
+        clients.lock().unwrap().remove(&id);
+        info!("control client {} disconnected", id);
+    });
+}
+
+fn framebuffer(cpu: &Cpu) -> Value {
+    json!({
+        "frame": cpu.clock.frames,
+        "width": SCREEN_WIDTH,
+        "height": SCREEN_HEIGHT,
+        "pixels": base64(&cpu.display),
+    })
+}
+
+impl Server {
+
+    /// where the clients connect, with the port picked by the system when 0 was asked
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// writes to one client, or to all of them, dropping the ones gone
+    fn send(&self, client: Option<usize>, message: Value) {
+        let line = format!("{}\n", message);
+        debug!("control -> {}", message);
+        self.clients.lock().unwrap().retain(|id, stream| {
+            client.is_some_and(|client| client != *id) || stream.write_all(line.as_bytes()).is_ok()
+        });
+    }
+
+    fn notify(&self, method: &str, params: Value) {
+        self.send(None, json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn handle(&mut self, cpu: &mut Cpu, client: usize, request: Result<Value, String>) {
+        let (id, result) = match request {
+            Ok(request) => {
+                debug!("control <- {}", request);
+                let method = request["method"].as_str().unwrap_or("");
+                (request["id"].clone(), self.call(cpu, method, &request["params"]))
+            },
+            Err(e) => (Value::Null, Err((PARSE_ERROR, e))),
+        };
+        let response = match result {
+            // requests without an id are notifications, nobody waits for their result
+            Ok(_) if id.is_null() => return,
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+        };
+        self.send(Some(client), response);
+    }
+
+    fn call(&mut self, cpu: &mut Cpu, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        let invalid = |message: &str| (INVALID_PARAMS, message.to_string());
+        match method {
+            "pause" => {
+                cpu.clock.paused = true;
+                Ok(json!({ "pc": cpu.pc }))
+            },
+            "resume" => {
+                cpu.clock.paused = false;
+                self.resumed = true;
+                Ok(json!({ "pc": cpu.pc }))
+            },
+            "step" => {
+                cpu.clock.paused = true;
+                for _ in 0..params["frames"].as_u64().unwrap_or(1) {
+                    cpu.frame();
+                    if cpu.quit {
+                        break;
+                    }
+                }
+                cpu.draw();
+                Ok(json!({ "pc": cpu.pc, "frames": cpu.clock.frames }))
+            },
+            "reset" => {
+                cpu.reset();
+                cpu.bootup(self.rom.clone());
+                Ok(Value::Null)
+            },
+            "load" => {
+                let path = params["path"].as_str().ok_or_else(|| invalid("load needs a path"))?;
+                let rom = super::rom::load(Path::new(path)).map_err(|e| (FAILED, e))?;
+                if rom.len() > MEMORY_SIZE - cpu.load_address as usize {
+                    return Err((FAILED, format!("rom too big, {} bytes", rom.len())));
+                }
+                cpu.reset();
+                cpu.bootup(rom.clone());
+                cpu.notify(format!("loaded {}", path));
+                self.rom = rom;
+                Ok(Value::Null)
+            },
+            "press" | "release" => {
+                let key = params["key"].as_u64().filter(|key| *key < KEYBOARD_SIZE as u64)
+                    .ok_or_else(|| invalid("keys go from 0 to 15"))?;
+                cpu.keyboard[key as usize] = method == "press";
+                Ok(Value::Null)
+            },
+            "registers" => Ok(json!({
+                "v": cpu.v,
+                "i": cpu.i,
+                "pc": cpu.pc,
+                "sp": cpu.sp,
+                "stack": cpu.stack,
+                "dt": cpu.delay_timer,
+                "st": cpu.sound_timer,
+                "frames": cpu.clock.frames,
+                "paused": cpu.clock.paused,
+            })),
+            "memory" => {
+                let address = params["address"].as_u64().ok_or_else(|| invalid("memory needs an address"))? as usize;
+                let length = params["length"].as_u64().unwrap_or(1) as usize;
+                let data = cpu.memory.get(address..address.saturating_add(length)).ok_or_else(|| invalid("outside memory"))?;
+                Ok(json!({ "address": address, "data": base64(data) }))
+            },
+            "framebuffer" => Ok(framebuffer(cpu)),
+            "breakpoints" => {
+                let addresses = params["addresses"].as_array().ok_or_else(|| invalid("breakpoints need addresses"))?;
+                self.breakpoints = addresses.iter()
+                    .map(|address| address.as_u64().filter(|a| *a < MEMORY_SIZE as u64).map(|a| a as u16))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| invalid("addresses go from 0 to 4095"))?;
+                Ok(json!({ "addresses": self.breakpoints }))
+            },
+            "subscribe" => {
+                self.every = params["every"].as_u64().unwrap_or(1).checked_sub(1).map(|every| every + 1);
+                Ok(Value::Null)
+            },
+            "quit" => {
+                cpu.quit = true;
+                Ok(Value::Null)
+            },
+            _ => Err((METHOD_NOT_FOUND, format!("{} is not a method", method))),
+        }
+    }
+
+    /// runs the machine, serving the clients between host frames, until it quits
+    pub fn run(&mut self, cpu: &mut Cpu) {
+        loop {
+            while let Ok((client, request)) = self.requests.try_recv() {
+                self.handle(cpu, client, request);
+            }
+            if cpu.quit {
+                break;
+            }
+
+            let breakpoints = &self.breakpoints;
+            let mut resumed = self.resumed;
+            let halted = cpu.run_frame(&mut |cpu| {
+                if resumed {
+                    resumed = false;
+                    return false;
+                }
+                breakpoints.contains(&cpu.pc)
+            });
+            self.resumed = resumed;
+            if halted {
+                cpu.clock.paused = true;
+                self.notify("breakpoint", json!({ "pc": cpu.pc, "frames": cpu.clock.frames }));
+            }
+            self.host_frames += 1;
+            if self.every.is_some_and(|every| self.host_frames.is_multiple_of(every)) {
+                self.notify("frame", framebuffer(cpu));
+            }
+        }
+        self.notify("quit", json!({ "frames": cpu.clock.frames }));
+    }
+}
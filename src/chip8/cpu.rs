@@ -1,7 +1,14 @@
-use std::{thread, time};
+use std::time;
 //use std::io;
 use super::optcodes::*;
-use super::sound::*;
+use super::frontend::Frontend;
+use super::sound::Audio;
+use super::clock::Clock;
+use super::osd::Osd;
+use super::trace::Trace;
+use super::profile::Profile;
+use super::inspect::Accesses;
+use super::cheat::Cheats;
 //use super::keyboard::*;
 
 pub(crate) const STACK_SIZE: usize = 0xF + 1;
@@ -17,6 +24,17 @@ pub(crate) const WAIT_INTERVAL: std::time::Duration = time::Duration::from_milli
 // pub(crate) const SCREEN_WIDTH: u32 = 128;
 // pub(crate) const SCREEN_HEIGHT: u32 = 64;
 
+/// Behaviours interpreters disagree on, all off runs like this interpreter always did
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Quirks {
+    /// 8xy6/8xyE shift Vy into Vx like the vip, instead of shifting Vx in place
+    pub shift: bool,
+    /// Fx55/Fx65 move I past the registers like the vip
+    pub load_store: bool,
+    /// sprites crossing the screen edge are clipped instead of wrapped
+    pub clip: bool,
+}
+
 pub struct Cpu {
     pub(crate) v: [u8; 16],
     pub(crate) memory: [u8; 4096],
@@ -30,30 +48,34 @@ pub struct Cpu {
     pub(crate) sp: usize,
 
     /// emulator internals
-    pub(crate) sdl_context: sdl2::Sdl,
-    pub(crate) screen: sdl2::render::Canvas<sdl2::video::Window>,
-    pub(crate) audio: Buzzer,
+    pub(crate) frontend: Box<dyn Frontend>,
+    pub(crate) audio: Box<dyn Audio>,
+    pub(crate) muted: bool,
+    pub(crate) audio_sync: bool,
+    pub(crate) clock: Clock,
+    pub(crate) osd: Osd,
+    pub(crate) trace: Option<Trace>,
+    pub(crate) profile: Option<Profile>,
+    /// recent memory accesses, tracked while the memory viewer is open
+    pub(crate) accesses: Option<Accesses>,
+    pub(crate) cheats: Cheats,
+    /// state of the splitmix64 generator behind RND, small enough for save states to keep whole
+    pub(crate) rng: u64,
+    pub(crate) quirks: Quirks,
+    /// where roms are loaded and start, 0x200 but for a few machines like the eti 660
+    pub(crate) load_address: u16,
+    /// save state kept by SaveState for LoadState
+    pub(crate) quick_save: Option<Vec<u8>>,
     pub(crate) display: [u8; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize],
     pub(crate) keyboard: [bool; KEYBOARD_SIZE],
     pub(crate) quit: bool,
-    pub(crate) scale_factor: u32,
+    /// why the emulation stopped on its own, an instruction it couldn't run
+    pub(crate) fault: Option<String>,
     pub(crate) display_redraw: bool,
 }
 
-pub fn initialize() -> Result<Cpu, String> {
-
-    let scale_factor: u32 = 10;
-
-    let sdl_context = sdl2::init()?;
-    let video_subsys = sdl_context.video()?;
-    let title = format!("{}", env!("CARGO_PKG_NAME"));
-    let window = video_subsys.window(&title, SCREEN_WIDTH * scale_factor, SCREEN_HEIGHT * scale_factor)
-        .position_centered()
-        .opengl()
-        .build()
-        .map_err(|e| e.to_string())?;
-    
-    Ok(Cpu {
+pub fn initialize(frontend: Box<dyn Frontend>, audio: Box<dyn Audio>) -> Cpu {
+    Cpu {
         v: [0; 16],
         memory: [0; MEMORY_SIZE],
         i: 0,
@@ -62,19 +84,62 @@ pub fn initialize() -> Result<Cpu, String> {
         sound_timer: 0,
         stack: [0; 16],
         sp: 0,
-        screen: window.into_canvas().build().map_err(|e| e.to_string())?,
-        audio: super::sound::new(),
+        frontend,
+        audio,
+        muted: false,
+        audio_sync: false,
+        clock: Clock::new(),
+        osd: Osd::new(),
+        trace: None,
+        profile: None,
+        accesses: None,
+        cheats: Cheats::default(),
+        rng: rand::random(),
+        quirks: Quirks::default(),
+        load_address: 0x200,
+        quick_save: None,
         display: [0u8; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize],
         keyboard: [false; KEYBOARD_SIZE],
-        sdl_context: sdl_context,
         quit: false,
-        scale_factor: scale_factor,
+        fault: None,
         display_redraw: true,
-    })
+    }
 }
 
 impl Cpu {
 
+    /// puts the machine back in its power-on state, the rom has to be booted up again
+    pub fn reset(&mut self) {
+        self.v = [0; 16];
+        self.memory = [0; MEMORY_SIZE];
+        self.i = 0;
+        self.pc = self.load_address;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.stack = [0; STACK_SIZE];
+        self.sp = 0;
+        self.keyboard = [false; KEYBOARD_SIZE];
+        self.cheats.frozen.clear();
+        self.quit = false;
+        self.fault = None;
+        self.display_redraw = true;
+        self.clear_screen();
+    }
+
+    /// restarts the RND numbers from a seed
+    pub(crate) fn reseed(&mut self, seed: u64) {
+        self.rng = seed;
+    }
+
+    /// the next RND number
+    pub(crate) fn random(&mut self) -> u8 {
+        self.rng = self.rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        ((z ^ (z >> 31)) >> 56) as u8
+    }
+
     pub fn bootup(&mut self, program_buffer: Vec<u8>) {
         // load fontset
         let font_set = [
@@ -99,65 +164,128 @@ impl Cpu {
             self.memory[i] = font_set[i];
         }
         // load program
-        for i in 0..program_buffer.len() {
-            self.memory[i + 0x200] = program_buffer[i];
+        let start = self.load_address as usize;
+        if start + program_buffer.len() > MEMORY_SIZE {
+            error!("rom too big, {} bytes at {:03X}", program_buffer.len(), start);
+        }
+        for (i, byte) in program_buffer.iter().take(MEMORY_SIZE.saturating_sub(start)).enumerate() {
+            self.memory[start + i] = *byte;
         }
+        self.pc = self.load_address;
+        self.apply_cheats(&program_buffer);
         self.clear_screen();
     }
 
 
     pub fn run(&mut self) {
-        loop {
-            trace!("main loop");
-            self.load_keyboard_status();
+        while !self.quit {
+            self.run_frame(&mut |_| false);
+        }
+        self.dump();
+        info!("quitting");
+    }
+
+    /// runs one host frame: polls the keyboard, emulates the frames due,
+    /// presents screen and sound then waits for the next one.
+    /// `halt` is asked before every instruction, returns true if it stopped the frame
+    pub(crate) fn run_frame(&mut self, halt: &mut dyn FnMut(&mut Cpu) -> bool) -> bool {
+        let halted = self.host_frame(halt);
+        self.wait();
+        halted
+    }
+
+    /// run_frame without the wait, for hosts pacing the frames themselves
+    pub(crate) fn host_frame(&mut self, halt: &mut dyn FnMut(&mut Cpu) -> bool) -> bool {
+        trace!("main loop");
+        self.load_keyboard_status();
+        if self.quit {
+            return false;
+        }
+
+        let mut halted = false;
+        for _ in 0..self.clock.frames_due() {
+            if halt(self) {
+                halted = true;
+                break;
+            }
+            self.frame();
             if self.quit {
-                self.dump();
-                info!("quitting");
                 break;
             }
+        }
+        self.draw();
+        self.buzz();
+        halted
+    }
 
-            match self.optcode() {
-                OptCode::SYS(opt) => self.sys(opt),
-                OptCode::CLS(_) => self.cls(),
-                OptCode::RET(_) => self.ret(),
-                OptCode::LDVxByte(opt) => self.ld_vx_byte(opt),
-                OptCode::SNEVxByte(opt) => self.sne_vx_byte(opt),
-                OptCode::CALL(opt) => self.call(opt),
-                OptCode::LDIAddr(opt) => self.ld_i_addr(opt),
-                OptCode::LDIVx(opt) => self.ld_i_vx(opt),
-                OptCode::JP(opt) => self.jp(opt),
-                OptCode::ADDIVx(opt) => self.add_i_vx(opt),
-                OptCode::LDVxI(opt) => self.ld_vx_i(opt),
-                OptCode::LDFVx(opt) => self.ld_f_vx(opt),
-                OptCode::DRWNibble(opt) => self.drw_vx_vy_nibble(opt),
-                OptCode::ADDVxByte(opt) => self.add_vx_byte(opt),
-                OptCode::ANDVxVy(opt) => self.and_vx_vy(opt),
-                OptCode::SEVxByte(opt)=> self.se_vx_byte(opt),
-                OptCode::SNEVxVy(opt) => self.sne_vx_vy(opt),
-                OptCode::ADDVxVy(opt)=> self.add_vx_vy(opt),
-                OptCode::LDVxVy(opt) => self.ld_vx_vy(opt),
-                OptCode::RNDVxByte(opt) => self.rnd_vx_byte(opt),
-                OptCode::SKPVx(opt) => self.skp_vx(opt),
-                OptCode::SKNPVx(opt) => self.sknp_vx(opt),
-                OptCode::XORVxVy(opt) => self.xor_vx_vy(opt),
-                OptCode::ORVxVy(opt) => self.or_vx_vy(opt),
-                OptCode::LDVxDT(opt) => self.ld_vx_dt(opt),
-                OptCode::SHRVxVy(opt) => self.shr_vx_vy(opt),
-                OptCode::SUBVxVy(opt) => self.sub_vx_vy(opt),
-                OptCode::LDSTVx(opt) => self.ld_st_vx(opt),
-                OptCode::LDDTVx(opt) => self.ld_dt_vx(opt),
-                OptCode::SHLVxVy(opt) => self.shl_vx_vy(opt),
-                OptCode::SEVxVy(opt) => self.se_vx_vy(opt),
-                OptCode::LDBVx(opt) => self.ld_b_vx(opt),
-                OptCode::LDVxK(opt)=> self.ld_vx_k(opt),
+    /// emulates 1/60 s: one instruction and one timer tick
+    pub(crate) fn frame(&mut self) {
+        self.step();
+        self.freeze();
+        self.timer_tick();
+        self.clock.frames += 1;
+        if self.clock.frame_limit.is_some_and(|limit| self.clock.frames >= limit) {
+            info!("frame limit reached");
+            self.quit = true;
+        }
+    }
 
+    /// fetches, decodes and executes one instruction, recording it when profiling or tracing
+    pub(crate) fn step(&mut self) {
+        self.clock.instructions += 1;
+        if let Some(mut profile) = self.profile.take() {
+            profile.record(self);
+            self.profile = Some(profile);
+        }
+        if let Some(mut accesses) = self.accesses.take() {
+            accesses.record(self);
+            self.accesses = Some(accesses);
+        }
+        match self.trace.take() {
+            Some(trace) => self.traced_step(trace),
+            None => self.execute(),
+        }
+    }
 
-                OptCode::None(opt) => self.none(opt),
-                _ => {},
-            }
-            self.draw();
-            self.timer_tick();
-            self.wait();
+    pub(crate) fn execute(&mut self) {
+        match self.optcode() {
+            OptCode::SYS(opt) => self.sys(opt),
+            OptCode::CLS(_) => self.cls(),
+            OptCode::RET(_) => self.ret(),
+            OptCode::LDVxByte(opt) => self.ld_vx_byte(opt),
+            OptCode::SNEVxByte(opt) => self.sne_vx_byte(opt),
+            OptCode::CALL(opt) => self.call(opt),
+            OptCode::LDIAddr(opt) => self.ld_i_addr(opt),
+            OptCode::LDIVx(opt) => self.ld_i_vx(opt),
+            OptCode::JP(opt) => self.jp(opt),
+            OptCode::ADDIVx(opt) => self.add_i_vx(opt),
+            OptCode::LDVxI(opt) => self.ld_vx_i(opt),
+            OptCode::LDFVx(opt) => self.ld_f_vx(opt),
+            OptCode::DRWNibble(opt) => self.drw_vx_vy_nibble(opt),
+            OptCode::ADDVxByte(opt) => self.add_vx_byte(opt),
+            OptCode::ANDVxVy(opt) => self.and_vx_vy(opt),
+            OptCode::SEVxByte(opt)=> self.se_vx_byte(opt),
+            OptCode::SNEVxVy(opt) => self.sne_vx_vy(opt),
+            OptCode::ADDVxVy(opt)=> self.add_vx_vy(opt),
+            OptCode::LDVxVy(opt) => self.ld_vx_vy(opt),
+            OptCode::RNDVxByte(opt) => self.rnd_vx_byte(opt),
+            OptCode::SKPVx(opt) => self.skp_vx(opt),
+            OptCode::SKNPVx(opt) => self.sknp_vx(opt),
+            OptCode::XORVxVy(opt) => self.xor_vx_vy(opt),
+            OptCode::ORVxVy(opt) => self.or_vx_vy(opt),
+            OptCode::LDVxDT(opt) => self.ld_vx_dt(opt),
+            OptCode::SHRVxVy(opt) => self.shr_vx_vy(opt),
+            OptCode::SUBVxVy(opt) => self.sub_vx_vy(opt),
+            OptCode::LDSTVx(opt) => self.ld_st_vx(opt),
+            OptCode::LDDTVx(opt) => self.ld_dt_vx(opt),
+            OptCode::SHLVxVy(opt) => self.shl_vx_vy(opt),
+            OptCode::SEVxVy(opt) => self.se_vx_vy(opt),
+            OptCode::LDBVx(opt) => self.ld_b_vx(opt),
+            OptCode::LDVxK(opt)=> self.ld_vx_k(opt),
+
+
+            OptCode::None(opt) => self.none(opt),
+            _ => {},
         }
     }
 
@@ -170,20 +298,10 @@ impl Cpu {
         if self.sound_timer > 0 {
             self.sound_timer -= 1;
         }
-        
-        if self.sound_timer > 0 {
-            self.audio.start();
-        } else {
-            self.audio.stop();
-        }
-    }
-
-    pub(crate) fn wait(&mut self) {
-        thread::sleep(WAIT_INTERVAL);        
     }
 
     fn optcode(&mut self) -> OptCode {
-        let (a, b) = (self.memory[self.pc as usize], self.memory[(self.pc + 1) as usize]);
+        let (a, b) = (self.memory[self.pc as usize % MEMORY_SIZE], self.memory[(self.pc as usize + 1) % MEMORY_SIZE]);
         let opt = u16::from_be_bytes([a, b]);
         debug!("optcode => {:#X} {:#X}", opt, (opt & 0xF000));
         match opt & 0xF000 {
@@ -262,5 +380,6 @@ impl Cpu {
             }
             println!("");
         }
+        self.notify("state dumped to stdout".to_string());
     }
 }
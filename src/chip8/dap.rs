@@ -0,0 +1,454 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use serde_json::{json, Value};
+
+use super::cpu::*;
+use super::symbols::{self, Symbols};
+
+const THREAD_ID: i64 = 1;
+
+/// variables references of the scopes
+const REGISTERS_REFERENCE: i64 = 1;
+const STACK_REFERENCE: i64 = 2;
+
+/// Debug Adapter Protocol session, the editor drives the cpu through json requests
+pub struct Session {
+    output: Box<dyn Write>,
+    requests: Receiver<Value>,
+    seq: i64,
+    symbols: Symbols,
+    /// breakpoints set from source lines, per source file
+    source_breakpoints: HashMap<String, Vec<u16>>,
+    instruction_breakpoints: BTreeSet<u16>,
+    stop_on_entry: bool,
+    running: bool,
+    /// the instruction under the pc runs even if it holds a breakpoint
+    resumed: bool,
+    /// stack depth to return to when stepping out, or over a call
+    step_out: Option<usize>,
+}
+
+/// serves the editor on stdio, or on a tcp address ("port" or "host:port")
+pub fn serve(cpu: &mut Cpu, transport: &str) -> Result<(), String> {
+    if transport == "stdio" {
+        return new(io::stdin(), Box::new(io::stdout())).attach(cpu).map_err(|e| e.to_string());
+    }
+    let address = if transport.contains(':') { transport.to_string() } else { format!("127.0.0.1:{}", transport) };
+    let listener = TcpListener::bind(&address).map_err(|e| e.to_string())?;
+    info!("waiting for the editor on {}", address);
+    let (stream, peer) = listener.accept().map_err(|e| e.to_string())?;
+    info!("editor connected from {}", peer);
+    let reader = stream.try_clone().map_err(|e| e.to_string())?;
+    new(reader, Box::new(stream)).attach(cpu).map_err(|e| e.to_string())
+}
+
+/// requests are read on their own thread so the editor can pause a running cpu
+pub fn new<R: Read + Send + 'static>(input: R, output: Box<dyn Write>) -> Session {
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = BufReader::new(input);
+        loop {
+            match read_message(&mut input) {
+                Ok(Some(message)) => if sender.send(message).is_err() {
+                    break;
+                },
+                Ok(None) => break,
+                Err(e) => {
+                    error!("couldn't read debug adapter message: {}", e);
+                    break;
+                },
+            }
+        }
+    });
+
+    Session {
+        output,
+        requests,
+        seq: 0,
+        symbols: Symbols::default(),
+        source_breakpoints: HashMap::new(),
+        instruction_breakpoints: BTreeSet::new(),
+        stop_on_entry: false,
+        running: false,
+        resumed: false,
+        step_out: None,
+    }
+}
+
+/// reads a `Content-Length` framed json message, None at end of input
+fn read_message(input: &mut dyn BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0u8; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub(crate) fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = u32::from(chunk[0]) << 16 |
+            u32::from(*chunk.get(1).unwrap_or(&0)) << 8 |
+            u32::from(*chunk.get(2).unwrap_or(&0));
+        text.push(ALPHABET[(n >> 18) as usize & 63] as char);
+        text.push(ALPHABET[(n >> 12) as usize & 63] as char);
+        text.push(if chunk.len() > 1 { ALPHABET[(n >> 6) as usize & 63] as char } else { '=' });
+        text.push(if chunk.len() > 2 { ALPHABET[n as usize & 63] as char } else { '=' });
+    }
+    text
+}
+
+/// parses a memory reference, decimal or 0x prefixed hex
+fn address(reference: &str) -> Option<usize> {
+    match reference.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => reference.parse().ok(),
+    }
+}
+
+fn variable(name: &str, value: usize, width: usize, memory_reference: bool) -> Value {
+    let mut variable = json!({
+        "name": name,
+        "value": format!("{:#0width$X}", value, width = width + 2),
+        "variablesReference": 0,
+    });
+    if memory_reference {
+        variable["memoryReference"] = json!(format!("{:#X}", value));
+    }
+    variable
+}
+
+impl Session {
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        debug!("dap -> {}", body);
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.output.flush()
+    }
+
+    fn respond(&mut self, request: &Value, body: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn stopped(&mut self, reason: &str) -> io::Result<()> {
+        self.running = false;
+        self.step_out = None;
+        self.event("stopped", json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }))
+    }
+
+    fn breakpoint_hit(&self, pc: u16) -> bool {
+        self.instruction_breakpoints.contains(&pc) ||
+            self.source_breakpoints.values().any(|addresses| addresses.contains(&pc))
+    }
+
+    fn resume(&mut self) {
+        self.running = true;
+        self.resumed = true;
+    }
+
+    fn launch(&mut self, cpu: &mut Cpu, arguments: &Value) -> Result<Value, String> {
+        let program = arguments["program"].as_str().ok_or("launch needs a program")?;
+        let program_buffer = super::rom::load(Path::new(program))?;
+        if let Some(path) = arguments["symbols"].as_str() {
+            self.symbols = symbols::load(Path::new(path))?;
+        }
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        cpu.reset();
+        cpu.bootup(program_buffer);
+        cpu.notify(format!("loaded {}", program));
+        Ok(json!({}))
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let file = arguments["source"]["path"].as_str().ok_or("breakpoints need a source path")?.to_string();
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in arguments["breakpoints"].as_array().cloned().unwrap_or_default() {
+            let line = breakpoint["line"].as_u64().unwrap_or(0);
+            match self.symbols.addresses(&file, line) {
+                Some((line, found)) => {
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "line": line,
+                        "instructionReference": format!("{:#X}", found[0]),
+                    }));
+                    addresses.extend(found);
+                },
+                None => breakpoints.push(json!({ "verified": false, "line": line, "message": "no code at this line" })),
+            }
+        }
+        self.source_breakpoints.insert(file, addresses);
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Value {
+        self.instruction_breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for breakpoint in arguments["breakpoints"].as_array().cloned().unwrap_or_default() {
+            let reference = breakpoint["instructionReference"].as_str().and_then(address);
+            let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+            match reference.map(|a| a as i64 + offset) {
+                Some(address) if (0..MEMORY_SIZE as i64).contains(&address) => {
+                    self.instruction_breakpoints.insert(address as u16);
+                    breakpoints.push(json!({ "verified": true, "instructionReference": format!("{:#X}", address) }));
+                },
+                _ => breakpoints.push(json!({ "verified": false, "message": "address outside memory" })),
+            }
+        }
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn frame(&self, cpu: &Cpu, id: usize, address: u16) -> Value {
+        let opcode = u16::from_be_bytes([
+            cpu.memory[address as usize % MEMORY_SIZE],
+            cpu.memory[(address as usize + 1) % MEMORY_SIZE],
+        ]);
+        let mut frame = json!({
+            "id": id,
+            "name": format!("{:#05X}: {:04X}", address, opcode),
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": format!("{:#X}", address),
+        });
+        if let Some(symbol) = self.symbols.line(address) {
+            frame["source"] = json!({ "path": symbol.file });
+            frame["line"] = json!(symbol.line);
+        }
+        frame
+    }
+
+    /// the current pc, then the call sites saved on the stack, innermost first
+    fn stack_trace(&self, cpu: &Cpu) -> Value {
+        let mut frames = vec![self.frame(cpu, 0, cpu.pc)];
+        for (n, s) in (0..cpu.sp.min(STACK_SIZE)).rev().enumerate() {
+            frames.push(self.frame(cpu, n + 1, cpu.stack[s]));
+        }
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn variables(cpu: &Cpu, reference: i64) -> Value {
+        let variables: Vec<Value> = match reference {
+            REGISTERS_REFERENCE => {
+                let mut registers: Vec<Value> = cpu.v.iter().enumerate()
+                    .map(|(x, v)| variable(&format!("v{:X}", x), *v as usize, 2, false))
+                    .collect();
+                registers.push(variable("i", cpu.i, 3, true));
+                registers.push(variable("pc", cpu.pc as usize, 3, true));
+                registers.push(variable("sp", cpu.sp, 1, false));
+                registers.push(variable("dt", cpu.delay_timer as usize, 2, false));
+                registers.push(variable("st", cpu.sound_timer as usize, 2, false));
+                registers
+            },
+            STACK_REFERENCE => cpu.stack.iter().enumerate()
+                .map(|(s, address)| variable(&format!("s[{:X}]", s), *address as usize, 3, true))
+                .collect(),
+            _ => Vec::new(),
+        };
+        json!({ "variables": variables })
+    }
+
+    fn read_memory(cpu: &Cpu, arguments: &Value) -> Result<Value, String> {
+        let reference = arguments["memoryReference"].as_str().and_then(address).ok_or("bad memory reference")?;
+        let start = reference as i64 + arguments["offset"].as_i64().unwrap_or(0);
+        let count = arguments["count"].as_u64().unwrap_or(0) as i64;
+        let readable_start = start.clamp(0, MEMORY_SIZE as i64) as usize;
+        let readable_end = (start + count).clamp(0, MEMORY_SIZE as i64) as usize;
+        let data = &cpu.memory[readable_start..readable_end.max(readable_start)];
+        Ok(json!({
+            "address": format!("{:#X}", start),
+            "data": base64(data),
+            "unreadableBytes": count - data.len() as i64,
+        }))
+    }
+
+    /// single instruction, the screen is refreshed so the editor user sees its effect
+    fn step(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        cpu.frame();
+        cpu.draw();
+        if cpu.quit {
+            return self.event("terminated", json!({}));
+        }
+        self.stopped("step")
+    }
+
+    /// handles a request, returns false once the editor disconnected
+    fn handle(&mut self, cpu: &mut Cpu, request: Value) -> io::Result<bool> {
+        debug!("dap <- {}", request);
+        let command = request["command"].as_str().unwrap_or("").to_string();
+        let arguments = request["arguments"].clone();
+        match command.as_str() {
+            "initialize" => {
+                self.respond(&request, Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsInstructionBreakpoints": true,
+                    "supportsReadMemoryRequest": true,
+                    "supportsTerminateRequest": true,
+                })))?;
+                self.event("initialized", json!({}))?;
+            },
+            "launch" => {
+                let body = self.launch(cpu, &arguments);
+                self.respond(&request, body)?;
+            },
+            "setBreakpoints" => {
+                let body = self.set_breakpoints(&arguments);
+                self.respond(&request, body)?;
+            },
+            "setInstructionBreakpoints" => {
+                let body = self.set_instruction_breakpoints(&arguments);
+                self.respond(&request, Ok(body))?;
+            },
+            "setExceptionBreakpoints" => self.respond(&request, Ok(json!({ "breakpoints": [] })))?,
+            "configurationDone" => {
+                self.respond(&request, Ok(json!({})))?;
+                if self.stop_on_entry {
+                    self.stopped("entry")?;
+                } else {
+                    self.resume();
+                }
+            },
+            "threads" => self.respond(&request, Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "chip-8" }] })))?,
+            "stackTrace" => {
+                let body = self.stack_trace(cpu);
+                self.respond(&request, Ok(body))?;
+            },
+            "scopes" => self.respond(&request, Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK_REFERENCE, "expensive": false },
+            ]})))?,
+            "variables" => {
+                let reference = arguments["variablesReference"].as_i64().unwrap_or(0);
+                self.respond(&request, Ok(Session::variables(cpu, reference)))?;
+            },
+            "readMemory" => self.respond(&request, Session::read_memory(cpu, &arguments))?,
+            "continue" => {
+                self.respond(&request, Ok(json!({ "allThreadsContinued": true })))?;
+                self.resume();
+            },
+            "next" => {
+                self.respond(&request, Ok(json!({})))?;
+                let pc = cpu.pc as usize % MEMORY_SIZE;
+                if cpu.memory[pc] & 0xF0 == 0x20 {
+                    // runs the whole subroutine, until the stack is back to this depth
+                    self.step_out = Some(cpu.sp);
+                    self.resume();
+                } else {
+                    self.step(cpu)?;
+                }
+            },
+            "stepIn" => {
+                self.respond(&request, Ok(json!({})))?;
+                self.step(cpu)?;
+            },
+            "stepOut" => {
+                self.respond(&request, Ok(json!({})))?;
+                if cpu.sp == 0 {
+                    self.step(cpu)?;
+                } else {
+                    self.step_out = Some(cpu.sp - 1);
+                    self.resume();
+                }
+            },
+            "pause" => {
+                self.respond(&request, Ok(json!({})))?;
+                self.stopped("pause")?;
+            },
+            "terminate" => {
+                self.respond(&request, Ok(json!({})))?;
+                cpu.quit = true;
+                self.event("terminated", json!({}))?;
+            },
+            "disconnect" => {
+                self.respond(&request, Ok(json!({})))?;
+                return Ok(false);
+            },
+            _ => self.respond(&request, Err(format!("{} is not supported", command)))?,
+        }
+        Ok(true)
+    }
+
+    /// runs one host frame, stopping on breakpoints and at the end of a step out or over
+    fn run(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        let mut resumed = self.resumed;
+        let step_out = self.step_out;
+        let mut reason = "breakpoint";
+        let halted = cpu.run_frame(&mut |cpu| {
+            if resumed {
+                resumed = false;
+                return false;
+            }
+            if step_out.is_some_and(|depth| cpu.sp <= depth) {
+                reason = "step";
+                return true;
+            }
+            self.breakpoint_hit(cpu.pc)
+        });
+        self.resumed = resumed;
+        if halted {
+            self.stopped(reason)?;
+        } else if cpu.quit {
+            self.running = false;
+            self.event("terminated", json!({}))?;
+        }
+        Ok(())
+    }
+
+    /// serves the editor until it disconnects
+    pub fn attach(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        loop {
+            let request = if self.running {
+                match self.requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match self.requests.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => return Ok(()),
+                }
+            };
+            match request {
+                Some(request) => if !self.handle(cpu, request)? {
+                    return Ok(());
+                },
+                None => self.run(cpu)?,
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+/// Disassembles an opcode with the mnemonics of Cowgod's chip-8 technical reference,
+/// instructions the cpu doesn't implement yet are still named, anything else is a DW data word
+pub fn disassemble(opcode: u16) -> String {
+    let nnn = opcode & 0x0FFF;
+    let x = (opcode >> 8) & 0xF;
+    let y = (opcode >> 4) & 0xF;
+    let kk = opcode & 0xFF;
+    let n = opcode & 0xF;
+    match (opcode >> 12, x, y, n) {
+        (0x0, 0x0, 0xE, 0x0) => "CLS".to_string(),
+        (0x0, 0x0, 0xE, 0xE) => "RET".to_string(),
+        (0x0, _, _, _) => format!("SYS {:#05X}", nnn),
+        (0x1, _, _, _) => format!("JP {:#05X}", nnn),
+        (0x2, _, _, _) => format!("CALL {:#05X}", nnn),
+        (0x3, _, _, _) => format!("SE V{:X}, {:#04X}", x, kk),
+        (0x4, _, _, _) => format!("SNE V{:X}, {:#04X}", x, kk),
+        (0x5, _, _, 0x0) => format!("SE V{:X}, V{:X}", x, y),
+        (0x6, _, _, _) => format!("LD V{:X}, {:#04X}", x, kk),
+        (0x7, _, _, _) => format!("ADD V{:X}, {:#04X}", x, kk),
+        (0x8, _, _, 0x0) => format!("LD V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x1) => format!("OR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x2) => format!("AND V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x3) => format!("XOR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x4) => format!("ADD V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x5) => format!("SUB V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x6) => format!("SHR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x7) => format!("SUBN V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0xE) => format!("SHL V{:X}, V{:X}", x, y),
+        (0x9, _, _, 0x0) => format!("SNE V{:X}, V{:X}", x, y),
+        (0xA, _, _, _) => format!("LD I, {:#05X}", nnn),
+        (0xB, _, _, _) => format!("JP V0, {:#05X}", nnn),
+        (0xC, _, _, _) => format!("RND V{:X}, {:#04X}", x, kk),
+        (0xD, _, _, _) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        (0xE, _, 0x9, 0xE) => format!("SKP V{:X}", x),
+        (0xE, _, 0xA, 0x1) => format!("SKNP V{:X}", x),
+        (0xF, _, 0x0, 0x7) => format!("LD V{:X}, DT", x),
+        (0xF, _, 0x0, 0xA) => format!("LD V{:X}, K", x),
+        (0xF, _, 0x1, 0x5) => format!("LD DT, V{:X}", x),
+        (0xF, _, 0x1, 0x8) => format!("LD ST, V{:X}", x),
+        (0xF, _, 0x1, 0xE) => format!("ADD I, V{:X}", x),
+        (0xF, _, 0x2, 0x9) => format!("LD F, V{:X}", x),
+        (0xF, _, 0x3, 0x3) => format!("LD B, V{:X}", x),
+        (0xF, _, 0x5, 0x5) => format!("LD [I], V{:X}", x),
+        (0xF, _, 0x6, 0x5) => format!("LD V{:X}, [I]", x),
+        _ => format!("DW {:#06X}", opcode),
+    }
+}
+
+/// The instruction pattern an opcode belongs to, as written in Cowgod's reference
+pub fn pattern(opcode: u16) -> &'static str {
+    match (opcode >> 12, (opcode >> 8) & 0xF, (opcode >> 4) & 0xF, opcode & 0xF) {
+        (0x0, 0x0, 0xE, 0x0) => "00E0 CLS",
+        (0x0, 0x0, 0xE, 0xE) => "00EE RET",
+        (0x0, _, _, _) => "0nnn SYS addr",
+        (0x1, _, _, _) => "1nnn JP addr",
+        (0x2, _, _, _) => "2nnn CALL addr",
+        (0x3, _, _, _) => "3xkk SE Vx, byte",
+        (0x4, _, _, _) => "4xkk SNE Vx, byte",
+        (0x5, _, _, 0x0) => "5xy0 SE Vx, Vy",
+        (0x6, _, _, _) => "6xkk LD Vx, byte",
+        (0x7, _, _, _) => "7xkk ADD Vx, byte",
+        (0x8, _, _, 0x0) => "8xy0 LD Vx, Vy",
+        (0x8, _, _, 0x1) => "8xy1 OR Vx, Vy",
+        (0x8, _, _, 0x2) => "8xy2 AND Vx, Vy",
+        (0x8, _, _, 0x3) => "8xy3 XOR Vx, Vy",
+        (0x8, _, _, 0x4) => "8xy4 ADD Vx, Vy",
+        (0x8, _, _, 0x5) => "8xy5 SUB Vx, Vy",
+        (0x8, _, _, 0x6) => "8xy6 SHR Vx, Vy",
+        (0x8, _, _, 0x7) => "8xy7 SUBN Vx, Vy",
+        (0x8, _, _, 0xE) => "8xyE SHL Vx, Vy",
+        (0x9, _, _, 0x0) => "9xy0 SNE Vx, Vy",
+        (0xA, _, _, _) => "Annn LD I, addr",
+        (0xB, _, _, _) => "Bnnn JP V0, addr",
+        (0xC, _, _, _) => "Cxkk RND Vx, byte",
+        (0xD, _, _, _) => "Dxyn DRW Vx, Vy, nibble",
+        (0xE, _, 0x9, 0xE) => "Ex9E SKP Vx",
+        (0xE, _, 0xA, 0x1) => "ExA1 SKNP Vx",
+        (0xF, _, 0x0, 0x7) => "Fx07 LD Vx, DT",
+        (0xF, _, 0x0, 0xA) => "Fx0A LD Vx, K",
+        (0xF, _, 0x1, 0x5) => "Fx15 LD DT, Vx",
+        (0xF, _, 0x1, 0x8) => "Fx18 LD ST, Vx",
+        (0xF, _, 0x1, 0xE) => "Fx1E ADD I, Vx",
+        (0xF, _, 0x2, 0x9) => "Fx29 LD F, Vx",
+        (0xF, _, 0x3, 0x3) => "Fx33 LD B, Vx",
+        (0xF, _, 0x5, 0x5) => "Fx55 LD [I], Vx",
+        (0xF, _, 0x6, 0x5) => "Fx65 LD Vx, [I]",
+        _ => "unknown",
+    }
+}
+
+/// Lists a rom loaded at `origin` an instruction per line, with the address and opcode in a comment,
+/// the asm subcommand assembles it back into the same rom
+pub fn listing(rom: &[u8], origin: u16) -> String {
+    let mut listing = String::new();
+    for (at, word) in rom.chunks(2).enumerate() {
+        let address = origin as usize + at * 2;
+        let line = match word {
+            [high, low] => {
+                let opcode = u16::from_be_bytes([*high, *low]);
+                format!("    {:<20} ; {:03X} {:04X}\n", disassemble(opcode), address, opcode)
+            },
+            _ => format!("    {:<20} ; {:03X} {:02X}\n", format!("DB {:#04X}", word[0]), address, word[0]),
+        };
+        listing.push_str(&line);
+    }
+    listing
+}
@@ -1,48 +1,48 @@
-//use sdl2::event::Event;
-//use sdl2::pixels;
-//use sdl2::gfx::primitives::DrawRenderer;
-use sdl2::pixels::PixelFormatEnum;
-use sdl2::rect::Rect;
-
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
 
 use super::cpu::*;
 
 impl Cpu {
 
     pub(crate) fn clear_screen(&mut self) {
-        //self.screen.set_draw_color(pixels::Color::RGB(0xFF, 0xFF, 0xFF));
         self.display = [0u8; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize];
     }
     
     pub(crate) fn draw(&mut self) {
-        if !self.display_redraw {
+        self.inspect();
+        let overlay = self.overlay();
+        if !self.display_redraw && overlay == self.osd.shown {
             return;
         }
-        let texture_creator = self.screen.texture_creator();
-        let mut texture = texture_creator.create_texture_streaming(PixelFormatEnum::RGB24, SCREEN_WIDTH, SCREEN_HEIGHT)
-            .map_err(|e| e.to_string()).unwrap();
-        
-        texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
-            trace!("pitch:{} height:{} width:{}", pitch, SCREEN_HEIGHT, SCREEN_WIDTH);
-            for y in 0..SCREEN_HEIGHT as usize {
-                for x in 0..SCREEN_WIDTH as usize {
-                    let offset = y*pitch + x*3;
-                    trace!("y*pitch + x*3 => {}*{} + {}*3 = offset:{}", y, pitch, x, offset);
-                    trace!("x:{} y:{} screen_width:{}", x, y, SCREEN_WIDTH);
-                    trace!("x + y * self.screen_width = {}", x + y * SCREEN_WIDTH as usize);
-                    let state = if self.display[x + y * SCREEN_WIDTH as usize] == 1 {255} else {0};
-                    buffer[offset] = state;
-                    buffer[offset + 1] = state;
-                    buffer[offset + 2] = state;
-                    //print!("{}", state);
-                }
-                //println!("");
-            }
-        }).unwrap();
-
-        self.screen.copy(&texture, None, Some(Rect::new(0, 0, SCREEN_WIDTH * self.scale_factor, SCREEN_HEIGHT * self.scale_factor))).unwrap();
-        self.screen.present();
+        self.frontend.draw(&self.display, &overlay);
+        self.osd.shown = overlay;
         self.display_redraw = false;
     }
-}
 
+    /// saves the chip-8 display as a grayscale png, one pixel per chip-8 pixel
+    pub fn screenshot(&self, path: &Path) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("couldn't create the screenshot: {}", e))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), SCREEN_WIDTH, SCREEN_HEIGHT);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let pixels: Vec<u8> = self.display.iter().map(|pixel| if *pixel == 1 { 0xFF } else { 0x00 }).collect();
+        encoder.write_header()
+            .and_then(|mut writer| writer.write_image_data(&pixels))
+            .map_err(|e| format!("couldn't write the screenshot: {}", e))
+    }
+
+    /// compares the chip-8 display with a screenshot, lit pixels are the ones that aren't black
+    pub fn matches_screenshot(&self, path: &Path) -> Result<bool, String> {
+        let bad = |e: png::DecodingError| format!("couldn't read the screenshot {}: {}", path.display(), e);
+        let file = File::open(path).map_err(|e| format!("couldn't open the screenshot {}: {}", path.display(), e))?;
+        let mut reader = png::Decoder::new(BufReader::new(file)).read_info().map_err(bad)?;
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).map_err(bad)?;
+        if (info.width, info.height, info.color_type, info.bit_depth) != (SCREEN_WIDTH, SCREEN_HEIGHT, png::ColorType::Grayscale, png::BitDepth::Eight) {
+            return Err(format!("{} is not a screenshot of this emulator", path.display()));
+        }
+        Ok(pixels.iter().zip(self.display.iter()).all(|(pixel, lit)| (*pixel != 0) == (*lit == 1)))
+    }
+}
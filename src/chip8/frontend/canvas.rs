@@ -0,0 +1,36 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::{Command, Frontend};
+use super::super::cpu::*;
+use super::super::osd::Overlay;
+
+/// colours of the lit and unlit pixels, rgba
+const FOREGROUND: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+const BACKGROUND: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+
+/// Browser frontend, keeps the screen as rgba pixels the page copies into an html canvas.
+/// The page sets the keypad directly, so polling has nothing to report.
+pub struct Canvas {
+    pixels: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Frontend for Canvas {
+
+    fn draw(&mut self, display: &[u8], _overlay: &Overlay) {
+        let mut pixels = self.pixels.borrow_mut();
+        for (pixel, lit) in pixels.chunks_exact_mut(4).zip(display.iter()) {
+            pixel.copy_from_slice(if *lit != 0 { &FOREGROUND } else { &BACKGROUND });
+        }
+    }
+
+    fn poll(&mut self, _keyboard: &mut [bool; KEYBOARD_SIZE]) -> Vec<Command> {
+        Vec::new()
+    }
+}
+
+/// the frontend and the pixels it draws to, which never move so the page can keep a pointer to them
+pub fn new() -> (Canvas, Rc<RefCell<Vec<u8>>>) {
+    let pixels = Rc::new(RefCell::new(BACKGROUND.repeat((SCREEN_WIDTH * SCREEN_HEIGHT) as usize)));
+    (Canvas { pixels: pixels.clone() }, pixels)
+}
@@ -0,0 +1,19 @@
+use super::{Command, Frontend};
+use super::super::cpu::*;
+use super::super::osd::Overlay;
+
+/// No screen and no keypad, for batch runs and servers
+pub struct Headless;
+
+impl Frontend for Headless {
+
+    fn draw(&mut self, _display: &[u8], _overlay: &Overlay) {}
+
+    fn poll(&mut self, _keyboard: &mut [bool; KEYBOARD_SIZE]) -> Vec<Command> {
+        Vec::new()
+    }
+
+    fn realtime(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,391 @@
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::ttf::Font;
+use sdl2::video::Window;
+use sdl2::VideoSubsystem;
+
+use super::{Command, Machine, Poke};
+use super::super::cpu::MEMORY_SIZE;
+
+/// bytes per row of the hex grid
+const COLUMNS: usize = 16;
+/// rows of the hex grid shown at once
+const ROWS: usize = 32;
+/// lines above the hex grid: registers, pointers and help
+const HEADER_LINES: usize = 4;
+
+/// sprite panel: columns of bytes drawn as 8 pixels wide rows
+const SPRITE_COLUMNS: usize = 4;
+const SPRITE_ROWS: usize = 32;
+const SPRITE_SCALE: u32 = 4;
+
+const MARGIN: i32 = 8;
+
+/// V0 to VF, then I and PC
+const REGISTERS: usize = 18;
+
+const BACKGROUND: Color = Color { r: 0x10, g: 0x10, b: 0x18, a: 0xFF };
+const FOREGROUND: Color = Color { r: 0xE0, g: 0xE0, b: 0xE0, a: 0xFF };
+const DIMMED: Color = Color { r: 0x80, g: 0x80, b: 0x90, a: 0xFF };
+const PC_COLOUR: Color = Color { r: 0x40, g: 0x80, b: 0xFF, a: 0xFF };
+const I_COLOUR: Color = Color { r: 0xFF, g: 0xD0, b: 0x40, a: 0xFF };
+const CURSOR_COLOUR: Color = Color { r: 0xFF, g: 0xFF, b: 0xFF, a: 0xFF };
+
+#[derive(PartialEq)]
+enum Focus {
+    Memory,
+    Registers,
+}
+
+/// Second SDL window showing memory as a hex / ascii grid with the registers above it.
+/// Bytes glow red when written and green when read, the PC is boxed in blue and I in yellow.
+/// Arrows move the cursor, hex digits edit the byte or register under it, Tab switches
+/// between memory and registers, Home and End jump to PC and I.
+/// The sprite panel draws the bytes from the cursor on as 8 pixels wide sprite rows.
+pub struct MemoryWindow {
+    canvas: Canvas<Window>,
+    char_width: i32,
+    line_height: i32,
+    focus: Focus,
+    cursor: usize,
+    /// first row of the grid shown
+    top: usize,
+    register: usize,
+    edit: String,
+    pc: u16,
+    i: usize,
+}
+
+pub fn new(video: &VideoSubsystem, font: &Font) -> Result<MemoryWindow, String> {
+    let (char_width, line_height) = font.size_of_char('0').map_err(|e| e.to_string())?;
+    let text_width = char_width * (5 + COLUMNS as u32 * 3 + 1 + COLUMNS as u32);
+    let sprite_width = SPRITE_COLUMNS as u32 * (8 * SPRITE_SCALE + MARGIN as u32);
+    let width = text_width + sprite_width + 3 * MARGIN as u32;
+    let height = line_height * (HEADER_LINES + ROWS) as u32 + 2 * MARGIN as u32;
+    let window = video.window("chip8 memory", width, height)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    Ok(MemoryWindow {
+        canvas: window.into_canvas().build().map_err(|e| e.to_string())?,
+        char_width: char_width as i32,
+        line_height: line_height as i32,
+        focus: Focus::Memory,
+        cursor: 0x200,
+        top: 0x200 / COLUMNS,
+        register: 0,
+        edit: String::new(),
+        pc: 0x200,
+        i: 0,
+    })
+}
+
+/// window the event happened in, if any
+pub(crate) fn window_id(event: &Event) -> Option<u32> {
+    match event {
+        Event::Window { window_id, .. } |
+        Event::KeyDown { window_id, .. } |
+        Event::KeyUp { window_id, .. } |
+        Event::TextInput { window_id, .. } |
+        Event::MouseMotion { window_id, .. } |
+        Event::MouseButtonDown { window_id, .. } |
+        Event::MouseButtonUp { window_id, .. } |
+        Event::MouseWheel { window_id, .. } => Some(*window_id),
+        _ => None,
+    }
+}
+
+fn hex_digit(keycode: Keycode) -> Option<char> {
+    let digit = match keycode {
+        Keycode::Num0 | Keycode::Kp0 => '0',
+        Keycode::Num1 | Keycode::Kp1 => '1',
+        Keycode::Num2 | Keycode::Kp2 => '2',
+        Keycode::Num3 | Keycode::Kp3 => '3',
+        Keycode::Num4 | Keycode::Kp4 => '4',
+        Keycode::Num5 | Keycode::Kp5 => '5',
+        Keycode::Num6 | Keycode::Kp6 => '6',
+        Keycode::Num7 | Keycode::Kp7 => '7',
+        Keycode::Num8 | Keycode::Kp8 => '8',
+        Keycode::Num9 | Keycode::Kp9 => '9',
+        Keycode::A => 'A',
+        Keycode::B => 'B',
+        Keycode::C => 'C',
+        Keycode::D => 'D',
+        Keycode::E => 'E',
+        Keycode::F => 'F',
+        _ => return None,
+    };
+    Some(digit)
+}
+
+/// where a register sits in the header: line, column and width in characters
+fn register_cell(register: usize) -> (usize, usize, usize) {
+    match register {
+        0..=15 => (register / 8, (register % 8) * 6 + 3, 2),
+        16 => (2, 2, 3),
+        _ => (2, 10, 3),
+    }
+}
+
+impl MemoryWindow {
+
+    pub(crate) fn id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    fn cell(&self, line: usize, column: usize, width: usize) -> Rect {
+        Rect::new(MARGIN + column as i32 * self.char_width, MARGIN + line as i32 * self.line_height,
+            width as u32 * self.char_width as u32, self.line_height as u32)
+    }
+
+    /// the hex and ascii cells of a byte, None when scrolled out
+    fn byte_cells(&self, address: usize) -> Option<(Rect, Rect)> {
+        let row = (address / COLUMNS).checked_sub(self.top).filter(|row| *row < ROWS)?;
+        let column = address % COLUMNS;
+        let line = HEADER_LINES + row;
+        Some((self.cell(line, 5 + column * 3, 2), self.cell(line, 5 + COLUMNS * 3 + 1 + column, 1)))
+    }
+
+    fn label(&mut self, font: &Font, text: &str, line: usize, column: usize, colour: Color) -> Result<(), String> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        let surface = font.render(text).blended(colour).map_err(|e| e.to_string())?;
+        let texture_creator = self.canvas.texture_creator();
+        let texture = texture_creator.create_texture_from_surface(&surface).map_err(|e| e.to_string())?;
+        let target = self.cell(line, column, 0);
+        self.canvas.copy(&texture, None, Some(Rect::new(target.x(), target.y(), surface.width(), surface.height())))
+    }
+
+    fn outline(&mut self, rect: Rect, colour: Color) -> Result<(), String> {
+        self.canvas.set_draw_color(colour);
+        self.canvas.draw_rect(rect)
+    }
+
+    /// keeps the cursor inside memory and on screen
+    fn move_cursor(&mut self, offset: isize) {
+        self.edit.clear();
+        self.cursor = (self.cursor as isize + offset).rem_euclid(MEMORY_SIZE as isize) as usize;
+        let row = self.cursor / COLUMNS;
+        if row < self.top {
+            self.top = row;
+        } else if row >= self.top + ROWS {
+            self.top = row + 1 - ROWS;
+        }
+    }
+
+    fn scroll(&mut self, rows: isize) {
+        let last = MEMORY_SIZE / COLUMNS - ROWS;
+        self.top = (self.top as isize + rows).clamp(0, last as isize) as usize;
+    }
+
+    /// adds a typed digit, the edit is sent once all the digits of the value are in
+    fn type_digit(&mut self, digit: char) -> Option<Command> {
+        self.edit.push(digit);
+        let width = match self.focus {
+            Focus::Registers => register_cell(self.register).2,
+            Focus::Memory => 2,
+        };
+        if self.edit.len() < width {
+            return None;
+        }
+        let value = usize::from_str_radix(&self.edit, 16).unwrap_or_default();
+        self.edit.clear();
+        Some(Command::Poke(match self.focus {
+            Focus::Memory => {
+                let poke = Poke::Memory(self.cursor, value as u8);
+                self.move_cursor(1);
+                poke
+            },
+            Focus::Registers => match self.register {
+                0..=15 => Poke::V(self.register, value as u8),
+                16 => Poke::I(value),
+                _ => Poke::Pc(value as u16),
+            },
+        }))
+    }
+
+    fn click(&mut self, x: i32, y: i32) {
+        let line = ((y - MARGIN) / self.line_height) as usize;
+        let column = ((x - MARGIN) / self.char_width) as usize;
+        self.edit.clear();
+        if line >= HEADER_LINES {
+            let byte_column = match column {
+                5..=52 => (column - 5) / 3,
+                54..=69 => column - 54,
+                _ => return,
+            };
+            self.focus = Focus::Memory;
+            self.cursor = ((self.top + line - HEADER_LINES) * COLUMNS + byte_column) % MEMORY_SIZE;
+            return;
+        }
+        if let Some(register) = (0..REGISTERS).find(|r| {
+            let (l, c, w) = register_cell(*r);
+            l == line && (c..c + w).contains(&column)
+        }) {
+            self.focus = Focus::Registers;
+            self.register = register;
+        }
+    }
+
+    /// handles an event of this window, returns the commands it issues
+    pub(crate) fn event(&mut self, event: &Event) -> Vec<Command> {
+        let mut commands = Vec::new();
+        match event {
+            Event::Window { win_event: WindowEvent::Close, .. } => commands.push(Command::MemoryViewer),
+            Event::MouseWheel { y, .. } => self.scroll(-3 * *y as isize),
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } => self.click(*x, *y),
+            Event::KeyDown { keycode: Some(keycode), .. } => match (*keycode, &self.focus) {
+                (Keycode::Escape, _) => if self.edit.is_empty() {
+                    commands.push(Command::MemoryViewer);
+                } else {
+                    self.edit.clear();
+                },
+                (Keycode::Backspace, _) => {
+                    self.edit.pop();
+                },
+                (Keycode::Tab, _) => {
+                    self.edit.clear();
+                    self.focus = if self.focus == Focus::Memory { Focus::Registers } else { Focus::Memory };
+                },
+                (Keycode::P, _) => commands.push(Command::Pause),
+                (Keycode::N, _) => commands.push(Command::Advance),
+                (Keycode::Home, _) => {
+                    self.focus = Focus::Memory;
+                    self.move_cursor(self.pc as isize - self.cursor as isize);
+                },
+                (Keycode::End, _) => {
+                    self.focus = Focus::Memory;
+                    self.move_cursor(self.i as isize - self.cursor as isize);
+                },
+                (Keycode::Left, Focus::Memory) => self.move_cursor(-1),
+                (Keycode::Right, Focus::Memory) => self.move_cursor(1),
+                (Keycode::Up, Focus::Memory) => self.move_cursor(-(COLUMNS as isize)),
+                (Keycode::Down, Focus::Memory) => self.move_cursor(COLUMNS as isize),
+                (Keycode::PageUp, Focus::Memory) => self.move_cursor(-((COLUMNS * ROWS) as isize)),
+                (Keycode::PageDown, Focus::Memory) => self.move_cursor((COLUMNS * ROWS) as isize),
+                (Keycode::Left, Focus::Registers) | (Keycode::Up, Focus::Registers) => {
+                    self.edit.clear();
+                    self.register = (self.register + REGISTERS - 1) % REGISTERS;
+                },
+                (Keycode::Right, Focus::Registers) | (Keycode::Down, Focus::Registers) => {
+                    self.edit.clear();
+                    self.register = (self.register + 1) % REGISTERS;
+                },
+                (keycode, _) => if let Some(command) = hex_digit(keycode).and_then(|digit| self.type_digit(digit)) {
+                    commands.push(command);
+                },
+            },
+            _ => {},
+        }
+        commands
+    }
+
+    pub(crate) fn draw(&mut self, font: &Font, machine: &Machine) -> Result<(), String> {
+        self.pc = machine.pc;
+        self.i = machine.i;
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(BACKGROUND);
+        self.canvas.clear();
+
+        // registers, the one being edited shows the digits typed so far
+        let mut lines = vec![String::new(), String::new()];
+        for (x, v) in machine.v.iter().enumerate() {
+            lines[x / 8].push_str(&format!("V{:X} {:02X} ", x, v));
+        }
+        lines.push(format!("I {:03X}  PC {:03X}  SP {:X}  DT {:02X}  ST {:02X}  {}",
+            machine.i, machine.pc, machine.sp, machine.delay_timer, machine.sound_timer,
+            if machine.paused { "paused" } else { "running, pause to edit" }));
+        if self.focus == Focus::Registers && !self.edit.is_empty() {
+            let (line, column, width) = register_cell(self.register);
+            let typed = format!("{:_<width$}", self.edit, width = width);
+            lines[line].replace_range(column..column + width, &typed);
+        }
+        for (line, text) in lines.iter().enumerate() {
+            self.label(font, text, line, 0, FOREGROUND)?;
+        }
+        self.label(font, "arrows move  hex edits  tab registers  home pc  end i", 3, 0, DIMMED)?;
+        let sprite_column = 5 + COLUMNS * 3 + 1 + COLUMNS + 2;
+        self.label(font, &format!("sprites {:03X}", self.cursor), 3, sprite_column, DIMMED)?;
+
+        // access heat behind the bytes
+        for row in 0..ROWS {
+            for column in 0..COLUMNS {
+                let address = (self.top + row) * COLUMNS + column;
+                let (read, write) = (machine.reads[address], machine.writes[address]);
+                if read == 0 && write == 0 {
+                    continue;
+                }
+                let colour = if write >= read { Color::RGBA(0xFF, 0x30, 0x30, write) } else { Color::RGBA(0x30, 0xFF, 0x30, read) };
+                if let Some((hex, ascii)) = self.byte_cells(address) {
+                    self.canvas.set_draw_color(colour);
+                    self.canvas.fill_rect(hex)?;
+                    self.canvas.fill_rect(ascii)?;
+                }
+            }
+        }
+
+        for row in 0..ROWS {
+            let start = (self.top + row) * COLUMNS;
+            let bytes = &machine.memory[start..start + COLUMNS];
+            let mut text = format!("{:03X}  ", start);
+            for (column, byte) in bytes.iter().enumerate() {
+                if self.focus == Focus::Memory && start + column == self.cursor && !self.edit.is_empty() {
+                    text.push_str(&format!("{:_<2} ", self.edit));
+                } else {
+                    text.push_str(&format!("{:02X} ", byte));
+                }
+            }
+            text.push(' ');
+            text.extend(bytes.iter().map(|byte| if byte.is_ascii_graphic() { *byte as char } else { '.' }));
+            self.label(font, &text, HEADER_LINES + row, 0, FOREGROUND)?;
+        }
+
+        for address in [machine.pc as usize, machine.pc as usize + 1].iter() {
+            if let Some((hex, ascii)) = self.byte_cells(address % MEMORY_SIZE) {
+                self.outline(hex, PC_COLOUR)?;
+                self.outline(ascii, PC_COLOUR)?;
+            }
+        }
+        if let Some((hex, ascii)) = self.byte_cells(machine.i % MEMORY_SIZE) {
+            self.outline(hex, I_COLOUR)?;
+            self.outline(ascii, I_COLOUR)?;
+        }
+        match self.focus {
+            Focus::Memory => if let Some((hex, ascii)) = self.byte_cells(self.cursor) {
+                self.outline(hex, CURSOR_COLOUR)?;
+                self.outline(ascii, CURSOR_COLOUR)?;
+            },
+            Focus::Registers => {
+                let (line, column, width) = register_cell(self.register);
+                let cell = self.cell(line, column, width);
+                self.outline(cell, CURSOR_COLOUR)?;
+            },
+        }
+
+        // the bytes from the cursor on, as sprite rows
+        let panel = self.cell(HEADER_LINES, sprite_column, 0);
+        for column in 0..SPRITE_COLUMNS {
+            let x = panel.x() + column as i32 * (8 * SPRITE_SCALE as i32 + MARGIN);
+            self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+            self.canvas.fill_rect(Rect::new(x, panel.y(), 8 * SPRITE_SCALE, SPRITE_ROWS as u32 * SPRITE_SCALE))?;
+            self.canvas.set_draw_color(FOREGROUND);
+            for row in 0..SPRITE_ROWS {
+                let byte = machine.memory[(self.cursor + column * SPRITE_ROWS + row) % MEMORY_SIZE];
+                for bit in 0..8 {
+                    if byte & (0x80 >> bit) != 0 {
+                        self.canvas.fill_rect(Rect::new(x + bit * SPRITE_SCALE as i32, panel.y() + row as i32 * SPRITE_SCALE as i32,
+                            SPRITE_SCALE, SPRITE_SCALE))?;
+                    }
+                }
+            }
+        }
+
+        self.canvas.present();
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+#[cfg(target_arch = "wasm32")]
+pub mod canvas;
+pub mod headless;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod memory;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod sdl;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tty;
+
+use super::cpu::KEYBOARD_SIZE;
+use super::osd::Overlay;
+
+/// Emulator requests coming from the host, apart from the chip-8 keypad
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Quit,
+    Dump,
+    Mute,
+    Pause,
+    /// runs a single frame while paused
+    Advance,
+    FastForward,
+    SlowMotion,
+    /// doubles the fast-forward multiplier
+    Faster,
+    /// halves the fast-forward multiplier
+    Slower,
+    /// shows or hides the fps / ips counter
+    Stats,
+    /// opens or closes the memory viewer
+    MemoryViewer,
+    /// keeps a save state of the machine, one slot
+    SaveState,
+    /// goes back to the kept save state
+    LoadState,
+    /// edits the machine from a debugger view
+    Poke(Poke),
+}
+
+/// Edits of the machine state, applied while paused
+#[derive(Debug, PartialEq)]
+pub enum Poke {
+    Memory(usize, u8),
+    V(usize, u8),
+    I(usize),
+    Pc(u16),
+}
+
+/// What debugger views show of the machine, reads and writes are the
+/// recent accesses of every byte as a heat fading from 255 to 0
+pub struct Machine<'a> {
+    pub memory: &'a [u8],
+    pub reads: &'a [u8],
+    pub writes: &'a [u8],
+    pub v: &'a [u8; 16],
+    pub i: usize,
+    pub pc: u16,
+    pub sp: usize,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub paused: bool,
+}
+
+/// Everything the cpu needs from the host: a screen and a keypad
+pub trait Frontend {
+    /// presents the chip-8 display buffer, one byte per pixel,
+    /// with the on-screen display drawn over it
+    fn draw(&mut self, display: &[u8], overlay: &Overlay);
+
+    /// refreshes the keypad state and returns the commands issued since the last poll
+    fn poll(&mut self, keyboard: &mut [bool; KEYBOARD_SIZE]) -> Vec<Command>;
+
+    /// shows the machine in the debugger views once per host frame, None closes them
+    fn inspect(&mut self, _machine: Option<&Machine>) {}
+
+    /// false when nobody watches the emulation, fast-forward is then uncapped
+    fn realtime(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,248 @@
+use sdl2::keyboard::Keycode;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::ttf::Font;
+use sdl2::video::Window;
+use std::path::Path;
+
+use super::{Command, Frontend, Machine};
+use super::memory::{self, MemoryWindow};
+use super::super::cpu::*;
+use super::super::osd::Overlay;
+
+pub(crate) const SCALE_FACTOR: u32 = 10;
+
+const FONT_SIZE: u16 = 16;
+const OSD_MARGIN: i32 = 4;
+
+/// fonts tried when none is given for the on-screen display
+const DEFAULT_FONTS: [&str; 5] = [
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSansMono.ttf",
+    "/Library/Fonts/Courier New.ttf",
+    "C:\\Windows\\Fonts\\consola.ttf",
+];
+
+/// Window settings, from the config file and the command line
+pub struct Settings {
+    pub scale_factor: u32,
+    /// colours of lit and unlit pixels
+    pub foreground: (u8, u8, u8),
+    pub background: (u8, u8, u8),
+    /// sdl names of the keys pressed for the chip-8 keys 0 to F
+    pub keymap: [String; KEYBOARD_SIZE],
+}
+
+/// SDL2 window frontend, the screen is scaled up by `scale_factor`
+pub struct Sdl {
+    _sdl_context: sdl2::Sdl,
+    screen: sdl2::render::Canvas<sdl2::video::Window>,
+    event_pump: sdl2::EventPump,
+    scale_factor: u32,
+    foreground: Color,
+    background: Color,
+    keymap: [Keycode; KEYBOARD_SIZE],
+    font: Option<Font<'static, 'static>>,
+    video: sdl2::VideoSubsystem,
+    memory: Option<MemoryWindow>,
+    /// commands raised outside of poll, returned by the next one
+    pending: Vec<Command>,
+}
+
+/// loads the on-screen display font, the ttf context lives as long as the program
+fn load_font(path: Option<&Path>) -> Result<Font<'static, 'static>, String> {
+    let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string())?;
+    let ttf_context: &'static sdl2::ttf::Sdl2TtfContext = Box::leak(Box::new(ttf_context));
+    match path {
+        Some(path) => ttf_context.load_font(path, FONT_SIZE),
+        None => DEFAULT_FONTS.iter()
+            .filter(|path| Path::new(path).exists())
+            .find_map(|path| ttf_context.load_font(path, FONT_SIZE).ok())
+            .ok_or_else(|| "no default font found, use --font".to_string()),
+    }
+}
+
+/// draws a line of text over a translucent box, returns its size
+fn text(screen: &mut Canvas<Window>, font: &Font, text: &str, x: i32, y: i32) -> Result<(u32, u32), String> {
+    let surface = font.render(text).blended(Color::RGB(0xFF, 0xFF, 0xFF)).map_err(|e| e.to_string())?;
+    let texture_creator = screen.texture_creator();
+    let texture = texture_creator.create_texture_from_surface(&surface).map_err(|e| e.to_string())?;
+    let (width, height) = (surface.width(), surface.height());
+    screen.set_blend_mode(BlendMode::Blend);
+    screen.set_draw_color(Color::RGBA(0, 0, 0, 0xA0));
+    screen.fill_rect(Rect::new(x - 2, y, width + 4, height))?;
+    screen.copy(&texture, None, Some(Rect::new(x, y, width, height)))?;
+    Ok((width, height))
+}
+
+pub fn new(font: Option<&Path>, settings: &Settings) -> Result<Sdl, String> {
+    let scale_factor = settings.scale_factor;
+    let mut keymap = [Keycode::Num0; KEYBOARD_SIZE];
+    for (keycode, name) in keymap.iter_mut().zip(&settings.keymap) {
+        *keycode = Keycode::from_name(name).ok_or_else(|| format!("no key named {}", name))?;
+    }
+    let (red, green, blue) = settings.foreground;
+    let foreground = Color::RGB(red, green, blue);
+    let (red, green, blue) = settings.background;
+    let background = Color::RGB(red, green, blue);
+
+    let sdl_context = sdl2::init()?;
+    let video_subsys = sdl_context.video()?;
+    let title = env!("CARGO_PKG_NAME");
+    let window = video_subsys.window(title, SCREEN_WIDTH * scale_factor, SCREEN_HEIGHT * scale_factor)
+        .position_centered()
+        .opengl()
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    Ok(Sdl {
+        screen: window.into_canvas().build().map_err(|e| e.to_string())?,
+        event_pump: sdl_context.event_pump()?,
+        _sdl_context: sdl_context,
+        scale_factor,
+        foreground,
+        background,
+        keymap,
+        font: match load_font(font) {
+            Ok(font) => Some(font),
+            Err(e) => {
+                warn!("on-screen display disabled: {}", e);
+                None
+            },
+        },
+        video: video_subsys,
+        memory: None,
+        pending: Vec::new(),
+    })
+}
+
+impl Sdl {
+
+    fn draw_overlay(&mut self, overlay: &Overlay) -> Result<(), String> {
+        let font = match &self.font {
+            Some(font) => font,
+            None => return Ok(()),
+        };
+        let mut y = OSD_MARGIN;
+        for line in &overlay.lines {
+            let (_, height) = text(&mut self.screen, font, line, OSD_MARGIN, y)?;
+            y += height as i32;
+        }
+        if overlay.paused {
+            let label = "PAUSED";
+            let (width, _) = font.size_of(label).map_err(|e| e.to_string())?;
+            let x = (SCREEN_WIDTH * self.scale_factor) as i32 - width as i32 - OSD_MARGIN;
+            text(&mut self.screen, font, label, x, OSD_MARGIN)?;
+        }
+        Ok(())
+    }
+}
+
+impl Frontend for Sdl {
+
+    fn draw(&mut self, display: &[u8], overlay: &Overlay) {
+        let texture_creator = self.screen.texture_creator();
+        let mut texture = texture_creator.create_texture_streaming(PixelFormatEnum::RGB24, SCREEN_WIDTH, SCREEN_HEIGHT)
+            .map_err(|e| e.to_string()).unwrap();
+
+        texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+            trace!("pitch:{} height:{} width:{}", pitch, SCREEN_HEIGHT, SCREEN_WIDTH);
+            for y in 0..SCREEN_HEIGHT as usize {
+                for x in 0..SCREEN_WIDTH as usize {
+                    let offset = y*pitch + x*3;
+                    trace!("y*pitch + x*3 => {}*{} + {}*3 = offset:{}", y, pitch, x, offset);
+                    trace!("x:{} y:{} screen_width:{}", x, y, SCREEN_WIDTH);
+                    trace!("x + y * self.screen_width = {}", x + y * SCREEN_WIDTH as usize);
+                    let colour = if display[x + y * SCREEN_WIDTH as usize] == 1 { self.foreground } else { self.background };
+                    buffer[offset] = colour.r;
+                    buffer[offset + 1] = colour.g;
+                    buffer[offset + 2] = colour.b;
+                }
+            }
+        }).unwrap();
+
+        self.screen.copy(&texture, None, Some(Rect::new(0, 0, SCREEN_WIDTH * self.scale_factor, SCREEN_HEIGHT * self.scale_factor))).unwrap();
+        if let Err(e) = self.draw_overlay(overlay) {
+            error!("couldn't draw the on-screen display: {}", e);
+        }
+        self.screen.present();
+    }
+
+    fn inspect(&mut self, machine: Option<&Machine>) {
+        let machine = match machine {
+            Some(machine) => machine,
+            None => {
+                self.memory = None;
+                return;
+            },
+        };
+        let font = match &self.font {
+            Some(font) => font,
+            None => {
+                error!("the memory viewer needs a font, use --font");
+                self.pending.push(Command::MemoryViewer);
+                return;
+            },
+        };
+        if self.memory.is_none() {
+            match memory::new(&self.video, font) {
+                Ok(window) => self.memory = Some(window),
+                Err(e) => {
+                    error!("couldn't open the memory viewer: {}", e);
+                    self.pending.push(Command::MemoryViewer);
+                    return;
+                },
+            }
+        }
+        if let Some(Err(e)) = self.memory.as_mut().map(|window| window.draw(font, machine)) {
+            error!("couldn't draw the memory viewer: {}", e);
+        }
+    }
+
+    fn poll(&mut self, keyboard: &mut [bool; KEYBOARD_SIZE]) -> Vec<Command> {
+        trace!("loading keyboard status");
+        let mut commands: Vec<Command> = self.pending.drain(..).collect();
+        *keyboard = [false; KEYBOARD_SIZE];
+        for event in self.event_pump.poll_iter() {
+            if let Some(window) = &mut self.memory {
+                if memory::window_id(&event) == Some(window.id()) {
+                    commands.extend(window.event(&event));
+                    continue;
+                }
+            }
+            match event {
+                Event::KeyDown {keycode: Some(keycode), ..} if self.keymap.contains(&keycode) => {
+                    for (key, mapped) in self.keymap.iter().enumerate() {
+                        keyboard[key] |= *mapped == keycode;
+                    }
+                },
+
+                Event::Quit{..} |
+                Event::Window {win_event: WindowEvent::Close, ..} |
+                Event::KeyDown {keycode: Some(Keycode::Q), ..} |
+                Event::KeyDown {keycode: Some(Keycode::Escape), ..} => commands.push(Command::Quit),
+                Event::KeyDown {keycode: Some(Keycode::Z), ..} => commands.push(Command::Dump),
+                Event::KeyDown {keycode: Some(Keycode::M), ..} => commands.push(Command::Mute),
+                Event::KeyDown {keycode: Some(Keycode::P), ..} => commands.push(Command::Pause),
+                Event::KeyDown {keycode: Some(Keycode::N), ..} => commands.push(Command::Advance),
+                Event::KeyDown {keycode: Some(Keycode::Tab), ..} => commands.push(Command::FastForward),
+                Event::KeyDown {keycode: Some(Keycode::S), ..} => commands.push(Command::SlowMotion),
+                Event::KeyDown {keycode: Some(Keycode::Equals), ..} |
+                Event::KeyDown {keycode: Some(Keycode::KpPlus), ..} => commands.push(Command::Faster),
+                Event::KeyDown {keycode: Some(Keycode::Minus), ..} |
+                Event::KeyDown {keycode: Some(Keycode::KpMinus), ..} => commands.push(Command::Slower),
+                Event::KeyDown {keycode: Some(Keycode::I), ..} => commands.push(Command::Stats),
+                Event::KeyDown {keycode: Some(Keycode::V), ..} => commands.push(Command::MemoryViewer),
+                Event::KeyDown {keycode: Some(Keycode::F5), ..} => commands.push(Command::SaveState),
+                Event::KeyDown {keycode: Some(Keycode::F9), ..} => commands.push(Command::LoadState),
+
+
+                _ => {},
+            }
+        }
+        commands
+    }
+}
@@ -0,0 +1,204 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use structopt::clap::arg_enum;
+
+use super::{Command, Frontend};
+use super::super::cpu::*;
+use super::super::osd::Overlay;
+
+arg_enum! {
+    /// How pixels are packed into terminal cells
+    /// HalfBlock: 1x2 pixels per cell using ▀ ▄ █
+    /// Braille: 2x4 pixels per cell using the braille patterns block
+    #[derive(Debug, Clone, Copy)]
+    pub enum Render {
+        HalfBlock,
+        Braille,
+    }
+}
+
+/// Terminal frontend, renders the screen with unicode characters and ANSI colours
+/// and reads the keypad from stdin in raw mode.
+/// Terminals only report key presses, so a key is held down until `key_timeout`
+/// elapses without the terminal repeating it.
+pub struct Tty {
+    render: Render,
+    foreground: u8,
+    background: u8,
+    key_timeout: Duration,
+    pressed: [Option<Instant>; KEYBOARD_SIZE],
+    termios: libc::termios,
+    stdout: io::Stdout,
+}
+
+/// puts stdin in raw mode and clears the terminal,
+/// colours are indexes into the 256 colours ANSI palette
+pub fn new(render: Render, foreground: u8, background: u8, key_timeout: Duration) -> Result<Tty, String> {
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+        return Err(format!("stdin is not a terminal: {}", io::Error::last_os_error()));
+    }
+
+    // output processing is kept so dump() still prints proper lines
+    let mut raw = termios;
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+    raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+    raw.c_cc[libc::VMIN] = 0;
+    raw.c_cc[libc::VTIME] = 0;
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+        return Err(format!("couldn't set the terminal in raw mode: {}", io::Error::last_os_error()));
+    }
+
+    let mut tty = Tty {
+        render,
+        foreground,
+        background,
+        key_timeout,
+        pressed: [None; KEYBOARD_SIZE],
+        termios,
+        stdout: io::stdout(),
+    };
+    // clear the screen and hide the cursor
+    tty.write(b"\x1b[2J\x1b[?25l");
+    Ok(tty)
+}
+
+impl Tty {
+
+    fn write(&mut self, bytes: &[u8]) {
+        let mut out = self.stdout.lock();
+        if let Err(e) = out.write_all(bytes).and_then(|_| out.flush()) {
+            error!("couldn't write to the terminal: {}", e);
+        }
+    }
+
+    /// drains every byte available on stdin without blocking
+    fn read(&mut self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut buffer = [0u8; 64];
+        loop {
+            let mut fds = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+            if unsafe { libc::poll(&mut fds, 1, 0) } <= 0 {
+                break;
+            }
+            let read = unsafe { libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+            if read <= 0 {
+                break;
+            }
+            bytes.extend_from_slice(&buffer[..read as usize]);
+        }
+        bytes
+    }
+
+    fn pixel(display: &[u8], x: usize, y: usize) -> bool {
+        x < SCREEN_WIDTH as usize && y < SCREEN_HEIGHT as usize &&
+            display[x + y * SCREEN_WIDTH as usize] == 1
+    }
+
+    fn half_block(display: &[u8], x: usize, y: usize) -> char {
+        match (Tty::pixel(display, x, y), Tty::pixel(display, x, y + 1)) {
+            (false, false) => ' ',
+            (true, false) => '▀',
+            (false, true) => '▄',
+            (true, true) => '█',
+        }
+    }
+
+    fn braille(display: &[u8], x: usize, y: usize) -> char {
+        // dot numbering of the unicode braille patterns, column by column
+        const DOTS: [(usize, usize, u32); 8] = [
+            (0, 0, 0x01), (0, 1, 0x02), (0, 2, 0x04), (0, 3, 0x40),
+            (1, 0, 0x08), (1, 1, 0x10), (1, 2, 0x20), (1, 3, 0x80),
+        ];
+        let mut pattern = 0x2800;
+        for &(dx, dy, bit) in DOTS.iter() {
+            if Tty::pixel(display, x + dx, y + dy) {
+                pattern |= bit;
+            }
+        }
+        std::char::from_u32(pattern).unwrap_or(' ')
+    }
+}
+
+impl Frontend for Tty {
+
+    fn draw(&mut self, display: &[u8], overlay: &Overlay) {
+        let (cell_width, cell_height) = match self.render {
+            Render::HalfBlock => (1, 2),
+            Render::Braille => (2, 4),
+        };
+        let colours = format!("\x1b[38;5;{}m\x1b[48;5;{}m", self.foreground, self.background);
+        let mut frame = String::from("\x1b[H");
+        for y in (0..SCREEN_HEIGHT as usize).step_by(cell_height) {
+            frame.push_str(&colours);
+            for x in (0..SCREEN_WIDTH as usize).step_by(cell_width) {
+                frame.push(match self.render {
+                    Render::HalfBlock => Tty::half_block(display, x, y),
+                    Render::Braille => Tty::braille(display, x, y),
+                });
+            }
+            frame.push_str("\x1b[0m\r\n");
+        }
+        // the on-screen display goes below the screen, clearing what the last frame left
+        frame.push_str("\x1b[J");
+        if overlay.paused {
+            frame.push_str("PAUSED\r\n");
+        }
+        for line in &overlay.lines {
+            frame.push_str(line);
+            frame.push_str("\r\n");
+        }
+        self.write(frame.as_bytes());
+    }
+
+    fn poll(&mut self, keyboard: &mut [bool; KEYBOARD_SIZE]) -> Vec<Command> {
+        let now = Instant::now();
+        let mut commands = Vec::new();
+        let bytes = self.read();
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'0'..=b'9' => self.pressed[(bytes[i] - b'0') as usize] = Some(now),
+                b'a'..=b'f' => self.pressed[(bytes[i] - b'a' + 0xA) as usize] = Some(now),
+                b'A'..=b'F' => self.pressed[(bytes[i] - b'A' + 0xA) as usize] = Some(now),
+                b'q' | b'Q' | 0x03 => commands.push(Command::Quit),
+                b'z' | b'Z' => commands.push(Command::Dump),
+                b'm' | b'M' => commands.push(Command::Mute),
+                b'p' | b'P' => commands.push(Command::Pause),
+                b'n' | b'N' => commands.push(Command::Advance),
+                b'\t' => commands.push(Command::FastForward),
+                b's' | b'S' => commands.push(Command::SlowMotion),
+                b'+' | b'=' => commands.push(Command::Faster),
+                b'-' => commands.push(Command::Slower),
+                b'i' | b'I' => commands.push(Command::Stats),
+                // escape sequences (arrows, function keys...) are skipped, a lone escape quits
+                0x1B => match bytes.get(i + 1) {
+                    Some(b'[') | Some(b'O') => {
+                        i += 2;
+                        while i < bytes.len() && !(0x40..=0x7E).contains(&bytes[i]) {
+                            i += 1;
+                        }
+                    },
+                    _ => commands.push(Command::Quit),
+                },
+                _ => {},
+            }
+            i += 1;
+        }
+
+        for (key, pressed) in keyboard.iter_mut().zip(self.pressed.iter()) {
+            *key = match pressed {
+                Some(at) => now.duration_since(*at) < self.key_timeout,
+                None => false,
+            };
+        }
+        commands
+    }
+}
+
+impl Drop for Tty {
+    fn drop(&mut self) {
+        self.write(b"\x1b[0m\x1b[?25h\r\n");
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.termios) };
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use super::cpu::*;
+
+/// registers as exposed to gdb, in the order of the `g` packet
+/// v0..vF are 8 bits, i and pc 16 bits little endian, then sp, dt and st 8 bits
+const REGISTERS: usize = 16 + 5;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+/// SIGTRAP and SIGINT, reported in stop replies
+const SIGTRAP: u8 = 5;
+const SIGINT: u8 = 2;
+
+/// Why the target stopped, as told to gdb
+enum Stop {
+    Signal(u8),
+    Exited,
+}
+
+/// GDB remote serial protocol stub driving the cpu over a tcp connection
+pub struct Stub {
+    stream: TcpStream,
+    breakpoints: BTreeSet<u16>,
+    /// bytes that came while the target ran, read before the stream
+    pending: VecDeque<u8>,
+}
+
+/// waits for gdb on 127.0.0.1:port and debugs the cpu until gdb detaches or kills it
+pub fn serve(cpu: &mut Cpu, port: u16) -> Result<(), String> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| e.to_string())?;
+    info!("waiting for gdb on 127.0.0.1:{}", port);
+    let (stream, address) = listener.accept().map_err(|e| e.to_string())?;
+    info!("gdb connected from {}", address);
+    new(stream).attach(cpu).map_err(|e| e.to_string())
+}
+
+pub fn new(stream: TcpStream) -> Stub {
+    Stub {
+        stream,
+        breakpoints: BTreeSet::new(),
+        pending: VecDeque::new(),
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// parses "addr,length" as sent by m, M and Z packets
+fn address_length(text: &str) -> Option<(usize, usize)> {
+    let mut parts = text.splitn(2, ',');
+    let address = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let length = usize::from_str_radix(parts.next()?, 16).ok()?;
+    Some((address, length))
+}
+
+fn registers(cpu: &Cpu) -> [u16; REGISTERS] {
+    let mut registers = [0u16; REGISTERS];
+    for (register, v) in registers.iter_mut().zip(cpu.v.iter()) {
+        *register = u16::from(*v);
+    }
+    registers[16] = cpu.i as u16;
+    registers[17] = cpu.pc;
+    registers[18] = cpu.sp as u16;
+    registers[19] = u16::from(cpu.delay_timer);
+    registers[20] = u16::from(cpu.sound_timer);
+    registers
+}
+
+fn register_size(n: usize) -> usize {
+    if n == 16 || n == 17 { 2 } else { 1 }
+}
+
+fn read_register(cpu: &Cpu, n: usize) -> Option<Vec<u8>> {
+    let value = *registers(cpu).get(n)?;
+    Some(value.to_le_bytes()[..register_size(n)].to_vec())
+}
+
+fn write_register(cpu: &mut Cpu, n: usize, bytes: &[u8]) -> Option<()> {
+    let value = match bytes.len() {
+        1 => u16::from(bytes[0]),
+        2 => u16::from_le_bytes([bytes[0], bytes[1]]),
+        _ => return None,
+    };
+    match n {
+        0..=15 => cpu.v[n] = value as u8,
+        16 => cpu.i = value as usize % MEMORY_SIZE,
+        17 => cpu.pc = value % MEMORY_SIZE as u16,
+        18 => cpu.sp = value as usize % STACK_SIZE,
+        19 => cpu.delay_timer = value as u8,
+        20 => cpu.sound_timer = value as u8,
+        _ => return None,
+    }
+    Some(())
+}
+
+impl Stub {
+
+    /// the next byte from gdb, None when it hung up
+    fn byte(&mut self) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.pending.pop_front() {
+            return Ok(Some(byte));
+        }
+        let mut byte = [0u8; 1];
+        Ok(if self.stream.read(&mut byte)? == 0 { None } else { Some(byte[0]) })
+    }
+
+    /// reads the next packet, acknowledging it, None when gdb hung up
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        // anything before the packet start, acks and interrupts, is noise while halted
+        loop {
+            match self.byte()? {
+                None => return Ok(None),
+                Some(b'$') => break,
+                Some(_) => {},
+            }
+        }
+        let mut data = Vec::new();
+        loop {
+            match self.byte()? {
+                None => return Ok(None),
+                Some(b'#') => break,
+                Some(b'}') => match self.byte()? {
+                    None => return Ok(None),
+                    Some(byte) => data.push(byte ^ 0x20),
+                },
+                Some(byte) => data.push(byte),
+            }
+        }
+        let mut sum = [0u8; 2];
+        for digit in sum.iter_mut() {
+            *digit = match self.byte()? {
+                None => return Ok(None),
+                Some(byte) => byte,
+            };
+        }
+        let expected = std::str::from_utf8(&sum).ok().and_then(|s| u8::from_str_radix(s, 16).ok());
+        if expected != Some(checksum(&data)) {
+            warn!("gdb packet with a bad checksum, asking again");
+            self.stream.write_all(b"-")?;
+            return self.receive();
+        }
+        self.stream.write_all(b"+")?;
+        let packet = String::from_utf8_lossy(&data).into_owned();
+        debug!("gdb <- {}", packet);
+        Ok(Some(packet))
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        debug!("gdb -> {}", data);
+        let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())
+    }
+
+    fn stop_reply(stop: &Stop) -> String {
+        match stop {
+            Stop::Signal(signal) => format!("S{:02x}", signal),
+            Stop::Exited => "W00".to_string(),
+        }
+    }
+
+    /// true when gdb sent an interrupt (ctrl-c) while the target runs, the stream is non-blocking then.
+    /// Other bytes are kept for the packet reader, gdb hanging up is an error
+    fn interrupted(&mut self) -> io::Result<bool> {
+        let mut bytes = [0u8; 64];
+        let count = match self.stream.read(&mut bytes) {
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "gdb hung up while the target ran")),
+            Ok(count) => count,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        let mut interrupted = false;
+        for byte in &bytes[..count] {
+            if *byte == 0x03 && !interrupted {
+                interrupted = true;
+            } else {
+                self.pending.push_back(*byte);
+            }
+        }
+        Ok(interrupted)
+    }
+
+    /// runs until a breakpoint, an interrupt or the emulator quitting
+    fn resume(&mut self, cpu: &mut Cpu) -> io::Result<Stop> {
+        self.stream.set_nonblocking(true)?;
+        let stop = self.run(cpu);
+        self.stream.set_nonblocking(false)?;
+        stop
+    }
+
+    fn run(&mut self, cpu: &mut Cpu) -> io::Result<Stop> {
+        // the instruction under the pc runs even if it holds a breakpoint
+        let mut first = true;
+        loop {
+            let breakpoints = &self.breakpoints;
+            let hit = cpu.run_frame(&mut |cpu| {
+                let hit = !first && breakpoints.contains(&cpu.pc);
+                first = false;
+                hit
+            });
+            if hit {
+                return Ok(Stop::Signal(SIGTRAP));
+            }
+            if cpu.quit {
+                return Ok(Stop::Exited);
+            }
+            if self.interrupted()? {
+                return Ok(Stop::Signal(SIGINT));
+            }
+        }
+    }
+
+    fn step(&mut self, cpu: &mut Cpu) -> Stop {
+        cpu.frame();
+        cpu.draw();
+        if cpu.quit { Stop::Exited } else { Stop::Signal(SIGTRAP) }
+    }
+
+    /// answers a packet that doesn't resume the target
+    fn query(&mut self, cpu: &mut Cpu, packet: &str) -> String {
+        let command = match packet.chars().next() {
+            Some(command) => command,
+            None => return String::new(),
+        };
+        let arguments = &packet[command.len_utf8()..];
+        let fits = |address: usize, length: usize| address.checked_add(length).is_some_and(|end| end <= MEMORY_SIZE);
+        match command {
+            '?' => Stub::stop_reply(&Stop::Signal(SIGTRAP)),
+            'g' => {
+                let bytes: Vec<u8> = (0..REGISTERS).filter_map(|n| read_register(cpu, n)).flatten().collect();
+                hex(&bytes)
+            },
+            'G' => match unhex(arguments) {
+                Some(ref bytes) if bytes.len() >= REGISTERS + 2 => {
+                    let mut offset = 0;
+                    for n in 0..REGISTERS {
+                        let size = register_size(n);
+                        write_register(cpu, n, &bytes[offset..offset + size]);
+                        offset += size;
+                    }
+                    "OK".to_string()
+                },
+                _ => "E01".to_string(),
+            },
+            'p' => usize::from_str_radix(arguments, 16).ok()
+                .and_then(|n| read_register(cpu, n))
+                .map(|bytes| hex(&bytes))
+                .unwrap_or_else(|| "E01".to_string()),
+            'P' => {
+                let mut parts = arguments.splitn(2, '=');
+                let n = parts.next().and_then(|n| usize::from_str_radix(n, 16).ok());
+                let bytes = parts.next().and_then(unhex);
+                match (n, bytes) {
+                    (Some(n), Some(bytes)) if write_register(cpu, n, &bytes).is_some() => "OK".to_string(),
+                    _ => "E01".to_string(),
+                }
+            },
+            'm' => match address_length(arguments) {
+                Some((address, length)) if fits(address, length) => hex(&cpu.memory[address..address + length]),
+                _ => "E01".to_string(),
+            },
+            'M' => {
+                let mut parts = arguments.splitn(2, ':');
+                let range = parts.next().and_then(address_length);
+                let bytes = parts.next().and_then(unhex);
+                match (range, bytes) {
+                    (Some((address, length)), Some(bytes)) if bytes.len() == length && fits(address, length) => {
+                        cpu.memory[address..address + length].copy_from_slice(&bytes);
+                        "OK".to_string()
+                    },
+                    _ => "E01".to_string(),
+                }
+            },
+            'Z' | 'z' if arguments.starts_with("0,") => match address_length(&arguments[2..]) {
+                Some((address, _)) if address < MEMORY_SIZE => {
+                    if command == 'Z' {
+                        self.breakpoints.insert(address as u16);
+                    } else {
+                        self.breakpoints.remove(&(address as u16));
+                    }
+                    "OK".to_string()
+                },
+                _ => "E01".to_string(),
+            },
+            'H' => "OK".to_string(),
+            'q' => {
+                if packet.starts_with("qSupported") {
+                    "PacketSize=1000;qXfer:features:read+".to_string()
+                } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+                    match address_length(range) {
+                        Some((offset, length)) if offset < TARGET_XML.len() => {
+                            let end = (offset + length).min(TARGET_XML.len());
+                            let more = if end < TARGET_XML.len() { "m" } else { "l" };
+                            format!("{}{}", more, &TARGET_XML[offset..end])
+                        },
+                        Some(_) => "l".to_string(),
+                        None => "E01".to_string(),
+                    }
+                } else if packet == "qAttached" {
+                    "1".to_string()
+                } else if packet == "qfThreadInfo" {
+                    "m1".to_string()
+                } else if packet == "qsThreadInfo" {
+                    "l".to_string()
+                } else {
+                    String::new()
+                }
+            },
+            _ => String::new(),
+        }
+    }
+
+    /// serves gdb, the target starts halted
+    pub fn attach(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        loop {
+            let packet = match self.receive()? {
+                Some(packet) => packet,
+                None => {
+                    info!("gdb hung up");
+                    return Ok(());
+                },
+            };
+            match packet.chars().next() {
+                Some('c') | Some('s') => {
+                    if let Ok(address) = u16::from_str_radix(&packet[1..], 16) {
+                        cpu.pc = address % MEMORY_SIZE as u16;
+                    }
+                    let stop = if packet.starts_with('c') { self.resume(cpu)? } else { self.step(cpu) };
+                    self.send(&Stub::stop_reply(&stop))?;
+                    if let Stop::Exited = stop {
+                        return Ok(());
+                    }
+                },
+                Some('k') => {
+                    cpu.quit = true;
+                    return Ok(());
+                },
+                Some('D') => {
+                    self.send("OK")?;
+                    info!("gdb detached, resuming");
+                    cpu.run();
+                    return Ok(());
+                },
+                _ => {
+                    let reply = self.query(cpu, &packet);
+                    self.send(&reply)?;
+                },
+            }
+        }
+    }
+}
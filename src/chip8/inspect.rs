@@ -0,0 +1,121 @@
+use std::ops::Range;
+
+use super::cpu::*;
+use super::frontend::{Machine, Poke};
+
+/// heat lost by an accessed byte every host frame, highlights fade in about half a second
+const HEAT_DECAY: u8 = 8;
+
+/// Recent memory reads and writes, as a heat per byte that fades every host frame
+pub struct Accesses {
+    reads: Vec<u8>,
+    writes: Vec<u8>,
+}
+
+/// memory an instruction reads and writes through I, the ranges may go past the end of memory
+pub(crate) fn accesses(opcode: u16, i: usize) -> (Range<usize>, Range<usize>) {
+    let x = ((opcode >> 8) & 0xF) as usize;
+    match (opcode >> 12, opcode & 0xFF) {
+        (0xD, _) => (i..i + (opcode & 0xF) as usize, 0..0),
+        (0xF, 0x33) => (0..0, i..i + 3),
+        (0xF, 0x55) => (0..0, i..i + x + 1),
+        (0xF, 0x65) => (i..i + x + 1, 0..0),
+        _ => (0..0, 0..0),
+    }
+}
+
+impl Accesses {
+
+    fn new() -> Accesses {
+        Accesses {
+            reads: vec![0; MEMORY_SIZE],
+            writes: vec![0; MEMORY_SIZE],
+        }
+    }
+
+    pub(crate) fn record(&mut self, cpu: &Cpu) {
+        let pc = cpu.pc as usize % MEMORY_SIZE;
+        let opcode = u16::from_be_bytes([cpu.memory[pc], cpu.memory[(pc + 1) % MEMORY_SIZE]]);
+        let (reads, writes) = accesses(opcode, cpu.i);
+        for address in reads {
+            self.reads[address % MEMORY_SIZE] = u8::MAX;
+        }
+        for address in writes {
+            self.writes[address % MEMORY_SIZE] = u8::MAX;
+        }
+    }
+
+    fn decay(&mut self) {
+        for heat in self.reads.iter_mut().chain(self.writes.iter_mut()) {
+            *heat = heat.saturating_sub(HEAT_DECAY);
+        }
+    }
+}
+
+impl Cpu {
+
+    pub(crate) fn toggle_memory_viewer(&mut self) {
+        self.accesses = match self.accesses {
+            Some(_) => None,
+            None => Some(Accesses::new()),
+        };
+        self.notify(if self.accesses.is_some() { "memory viewer on" } else { "memory viewer off" }.to_string());
+    }
+
+    /// shows the machine to the frontend debugger views, once per host frame
+    pub(crate) fn inspect(&mut self) {
+        let accesses = match &mut self.accesses {
+            Some(accesses) => accesses,
+            None => return self.frontend.inspect(None),
+        };
+        accesses.decay();
+        let machine = Machine {
+            memory: &self.memory,
+            reads: &accesses.reads,
+            writes: &accesses.writes,
+            v: &self.v,
+            i: self.i,
+            pc: self.pc,
+            sp: self.sp,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            paused: self.clock.paused,
+        };
+        self.frontend.inspect(Some(&machine));
+    }
+
+    /// edits memory or a register, only while paused so the program doesn't race the edit
+    pub(crate) fn poke(&mut self, poke: Poke) {
+        if !self.clock.paused {
+            self.notify("pause to edit the machine".to_string());
+            return;
+        }
+        match poke {
+            Poke::Memory(address, value) => self.memory[address % MEMORY_SIZE] = value,
+            Poke::V(x, value) => self.v[x & 0xF] = value,
+            Poke::I(value) => self.i = value % MEMORY_SIZE,
+            Poke::Pc(value) => self.pc = value % MEMORY_SIZE as u16,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::frontend::headless::Headless;
+    use crate::chip8::sound;
+
+    #[test]
+    fn pc_past_memory() {
+        let mut cpu = initialize(Box::new(Headless), Box::new(sound::Null));
+        // LD [I], V1 at 002, fetched through the pc 1002
+        cpu.memory[0x002] = 0xF1;
+        cpu.memory[0x003] = 0x55;
+        cpu.pc = 0x1002;
+        cpu.i = 0xFFF;
+        let mut accesses = Accesses::new();
+        accesses.record(&cpu);
+        assert_eq!((accesses.writes[0xFFF], accesses.writes[0x000], accesses.writes[0x001]), (u8::MAX, u8::MAX, 0));
+        assert!(accesses.reads.iter().all(|heat| *heat == 0));
+    }
+}
@@ -1,45 +1,42 @@
-use sdl2::keyboard::Keycode;
-use sdl2::event::Event;
 use super::cpu::*;
+use super::frontend::Command;
 
 
 impl Cpu {
 
-    pub(crate) fn clear_keyboard(&mut self) {
-        //self.sdl_context.event_pump().unwrap();
-        self.keyboard = [false; KEYBOARD_SIZE];
-    }
-    
     pub(crate) fn load_keyboard_status(&mut self) {
         trace!("loading keyboard status");
-        self.clear_keyboard();
-        let mut event_pump = self.sdl_context.event_pump().unwrap();
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::KeyDown {keycode: Some(Keycode::Num0), ..} => self.keyboard[0x0] = true,
-                Event::KeyDown {keycode: Some(Keycode::Num1), ..} => self.keyboard[0x1] = true,
-                Event::KeyDown {keycode: Some(Keycode::Num2), ..} => self.keyboard[0x2] = true,
-                Event::KeyDown {keycode: Some(Keycode::Num3), ..} => self.keyboard[0x3] = true,
-                Event::KeyDown {keycode: Some(Keycode::Num4), ..} => self.keyboard[0x4] = true,
-                Event::KeyDown {keycode: Some(Keycode::Num5), ..} => self.keyboard[0x5] = true,
-                Event::KeyDown {keycode: Some(Keycode::Num6), ..} => self.keyboard[0x6] = true,
-                Event::KeyDown {keycode: Some(Keycode::Num7), ..} => self.keyboard[0x7] = true,
-                Event::KeyDown {keycode: Some(Keycode::Num8), ..} => self.keyboard[0x8] = true,
-                Event::KeyDown {keycode: Some(Keycode::Num9), ..} => self.keyboard[0x9] = true,
-                Event::KeyDown {keycode: Some(Keycode::A), ..} => self.keyboard[0xA] = true,
-                Event::KeyDown {keycode: Some(Keycode::B), ..} => self.keyboard[0xB] = true,
-                Event::KeyDown {keycode: Some(Keycode::C), ..} => self.keyboard[0xC] = true,
-                Event::KeyDown {keycode: Some(Keycode::D), ..} => self.keyboard[0xD] = true,
-                Event::KeyDown {keycode: Some(Keycode::E), ..} => self.keyboard[0xE] = true,
-                Event::KeyDown {keycode: Some(Keycode::F), ..} => self.keyboard[0xF] = true,
-
-                Event::Quit{..} |
-                Event::KeyDown {keycode: Some(Keycode::Q), ..} |
-                Event::KeyDown {keycode: Some(Keycode::Escape), ..} => self.quit = true,
-                Event::KeyDown {keycode: Some(Keycode::Z), ..} => self.dump(),
-
-
-                _ => {},
+        for command in self.frontend.poll(&mut self.keyboard) {
+            match command {
+                Command::Quit => self.quit = true,
+                Command::Dump => self.dump(),
+                Command::Mute => {
+                    self.muted = !self.muted;
+                    self.notify(if self.muted { "audio muted" } else { "audio unmuted" }.to_string());
+                },
+                Command::Pause => self.toggle_pause(),
+                Command::Advance => self.advance_frame(),
+                Command::FastForward => self.toggle_speed(self.clock.fast_forward),
+                Command::SlowMotion => self.toggle_speed(self.clock.slow_motion),
+                Command::Faster => self.scale_fast_forward(2.0),
+                Command::Slower => self.scale_fast_forward(0.5),
+                Command::Stats => self.toggle_stats(),
+                Command::MemoryViewer => self.toggle_memory_viewer(),
+                Command::SaveState => {
+                    self.quick_save = Some(self.save_state());
+                    self.notify("state saved".to_string());
+                },
+                Command::LoadState => match self.quick_save.take() {
+                    Some(state) => {
+                        match self.load_state(&state) {
+                            Ok(()) => self.notify("state loaded".to_string()),
+                            Err(e) => error!("{}", e),
+                        }
+                        self.quick_save = Some(state);
+                    },
+                    None => self.notify("no saved state".to_string()),
+                },
+                Command::Poke(poke) => self.poke(poke),
             }
         }
     }
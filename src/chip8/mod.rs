@@ -1,5 +1,31 @@
 mod optcodes;
 mod display;
 mod keyboard;
-mod sound;
+mod clock;
+pub mod osd;
+pub mod sound;
+pub mod frontend;
 pub mod cpu;
+pub mod gdb;
+pub mod dap;
+pub mod control;
+pub mod netplay;
+pub mod rom;
+pub mod symbols;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod script;
+pub mod disasm;
+pub mod asm;
+pub mod trace;
+pub mod profile;
+pub mod inspect;
+pub mod cheat;
+pub mod patch;
+pub mod analyze;
+pub mod state;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bench;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod config;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
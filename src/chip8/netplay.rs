@@ -0,0 +1,433 @@
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+use super::cpu::*;
+use super::rom;
+
+/// bumped on any change to the messages
+const VERSION: u8 = 1;
+/// frames emulated ahead of the other player on predicted input before waiting for it
+const MAX_ROLLBACK: u64 = 8;
+/// frames between desync checks
+const HASH_INTERVAL: u64 = 60;
+
+const HELLO: u8 = b'H';
+const INPUT: u8 = b'I';
+const HASH: u8 = b'S';
+
+/// keys of the host by default, the left paddle of PONG
+pub const HOST_KEYS: u16 = 1 << 0x1 | 1 << 0x4;
+/// keys of the guest by default, the right paddle of PONG
+pub const GUEST_KEYS: u16 = 1 << 0xC | 1 << 0xD;
+
+/// Netplay settings of one side
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// keys played on this side, bit n for key n
+    pub keys: u16,
+    /// frames between pressing a key and the machine seeing it, hides latency without rolling back.
+    /// The host's is used by both sides
+    pub delay: u64,
+    /// seed of RND, the host's is used by both sides
+    pub seed: u64,
+}
+
+/// first message of both sides
+struct Hello {
+    crc: u32,
+    keys: u16,
+    delay: u64,
+    seed: u64,
+}
+
+enum Message {
+    Input(u64, u16),
+    Hash(u64, u64),
+}
+
+/// Two-player session over tcp. Both sides emulate the whole machine: every frame they send
+/// the keys pressed on their side and go on with the last keys received from the other one.
+/// Once the actual keys arrive, frames emulated with a wrong guess are rolled back to the save state
+/// taken before them and emulated again. Hashes of the memory and the screen are exchanged
+/// every HASH_INTERVAL frames to catch the sides drifting apart.
+pub struct Session {
+    stream: TcpStream,
+    messages: Receiver<Message>,
+    keys: u16,
+    remote_keys: u16,
+    delay: u64,
+    /// the frame emulated next
+    frame: u64,
+    /// the keys of the other player are known for every frame before this one
+    confirmed: u64,
+    local: BTreeMap<u64, u16>,
+    remote: BTreeMap<u64, u16>,
+    /// keys of the other player the frames were emulated with, guessed until confirmed
+    used: BTreeMap<u64, u16>,
+    /// save state at the start of the frames that may be rolled back, with its hash
+    snapshots: BTreeMap<u64, (Vec<u8>, u64)>,
+    /// first frame emulated with a wrong guess
+    mispredicted: Option<u64>,
+    next_hash: u64,
+    local_hashes: BTreeMap<u64, u64>,
+    remote_hashes: BTreeMap<u64, u64>,
+    checked: u64,
+    desync: Option<u64>,
+    rollbacks: u64,
+    left: bool,
+}
+
+/// keys given as hex digits, e.g. 14 for keys 1 and 4
+pub fn parse_keys(text: &str) -> Result<u16, String> {
+    text.chars().try_fold(0, |keys, digit| match digit.to_digit(16) {
+        Some(key) => Ok(keys | 1 << key),
+        None => Err(format!("{} is not a key, keys go from 0 to F", digit)),
+    })
+}
+
+fn keys_text(keys: u16) -> String {
+    (0..KEYBOARD_SIZE).filter(|key| keys & 1 << key != 0).map(|key| format!("{:X}", key)).collect()
+}
+
+/// FNV-1a of the memory and the screen, the same on every platform
+fn hash(cpu: &Cpu) -> u64 {
+    cpu.memory.iter().chain(cpu.display.iter()).fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+impl Hello {
+
+    fn write(&self, stream: &mut TcpStream) -> io::Result<()> {
+        let mut message = vec![HELLO, VERSION];
+        message.extend_from_slice(&self.crc.to_le_bytes());
+        message.extend_from_slice(&self.keys.to_le_bytes());
+        message.extend_from_slice(&self.delay.to_le_bytes());
+        message.extend_from_slice(&self.seed.to_le_bytes());
+        stream.write_all(&message)
+    }
+
+    fn read(stream: &mut TcpStream) -> Result<Hello, String> {
+        let mut message = [0; 24];
+        stream.read_exact(&mut message).map_err(|e| format!("the other player didn't say hello: {}", e))?;
+        if message[0] != HELLO || message[1] != VERSION {
+            return Err("the other player runs another version".to_string());
+        }
+        Ok(Hello {
+            crc: u32::from_le_bytes([message[2], message[3], message[4], message[5]]),
+            keys: u16::from_le_bytes([message[6], message[7]]),
+            delay: u64::from_le_bytes(message[8..16].try_into().unwrap()),
+            seed: u64::from_le_bytes(message[16..24].try_into().unwrap()),
+        })
+    }
+}
+
+/// both sides check the other one plays along
+fn agree(host: &Hello, guest: &Hello) -> Result<(), String> {
+    if host.crc != guest.crc {
+        return Err(format!("the players run different roms, {:08X} and {:08X}", host.crc, guest.crc));
+    }
+    if host.keys & guest.keys != 0 {
+        return Err(format!("both players have the keys {}", keys_text(host.keys & guest.keys)));
+    }
+    Ok(())
+}
+
+fn read_message(stream: &mut TcpStream) -> io::Result<Message> {
+    let mut tag = [0; 1];
+    stream.read_exact(&mut tag)?;
+    let mut frame = [0; 8];
+    stream.read_exact(&mut frame)?;
+    let frame = u64::from_le_bytes(frame);
+    match tag[0] {
+        INPUT => {
+            let mut keys = [0; 2];
+            stream.read_exact(&mut keys)?;
+            Ok(Message::Input(frame, u16::from_le_bytes(keys)))
+        },
+        HASH => {
+            let mut hash = [0; 8];
+            stream.read_exact(&mut hash)?;
+            Ok(Message::Hash(frame, u64::from_le_bytes(hash)))
+        },
+        tag => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown message {:02X}", tag))),
+    }
+}
+
+/// reads the messages of the other player on its own thread
+fn receive(mut stream: TcpStream, sender: Sender<Message>) {
+    loop {
+        match read_message(&mut stream) {
+            Ok(message) => if sender.send(message).is_err() {
+                break;
+            },
+            Err(e) => {
+                info!("the other player left: {}", e);
+                break;
+            },
+        }
+    }
+}
+
+/// listens on a tcp address ("port" or "host:port") for host,
+/// a bare port is open on every interface so the other player can join from another machine
+pub fn listen(address: &str) -> Result<TcpListener, String> {
+    let address = if address.contains(':') { address.to_string() } else { format!("0.0.0.0:{}", address) };
+    let listener = TcpListener::bind(&address).map_err(|e| format!("couldn't listen on {}: {}", address, e))?;
+    if listener.local_addr().is_ok_and(|local| local.ip().is_unspecified()) {
+        warn!("netplay is open to every network interface on {}, give 127.0.0.1:port to keep it on this machine", address);
+    }
+    Ok(listener)
+}
+
+/// waits for the other player and starts the session as player 1
+pub fn host(listener: &TcpListener, cpu: &mut Cpu, rom: &[u8], settings: &Settings) -> Result<Session, String> {
+    if let Ok(address) = listener.local_addr() {
+        info!("waiting for the other player on {}", address);
+    }
+    let (mut stream, peer) = listener.accept().map_err(|e| format!("couldn't accept the other player: {}", e))?;
+    info!("player 2 joined from {}", peer);
+    let hello = Hello { crc: rom::crc32(rom), keys: settings.keys, delay: settings.delay, seed: settings.seed };
+    hello.write(&mut stream).map_err(|e| format!("couldn't greet the other player: {}", e))?;
+    let guest = Hello::read(&mut stream)?;
+    agree(&hello, &guest)?;
+    Session::start(stream, cpu, &hello, guest.keys)
+}
+
+/// joins the session hosted on a tcp address ("port" or "host:port") as player 2
+pub fn join(address: &str, cpu: &mut Cpu, rom: &[u8], settings: &Settings) -> Result<Session, String> {
+    let address = if address.contains(':') { address.to_string() } else { format!("127.0.0.1:{}", address) };
+    let mut stream = TcpStream::connect(&address).map_err(|e| format!("couldn't join {}: {}", address, e))?;
+    let host = Hello::read(&mut stream)?;
+    let hello = Hello { crc: rom::crc32(rom), keys: settings.keys, delay: host.delay, seed: host.seed };
+    hello.write(&mut stream).map_err(|e| format!("couldn't greet the other player: {}", e))?;
+    agree(&host, &hello)?;
+    info!("joined {} as player 2", address);
+    Session::start(stream, cpu, &hello, host.keys)
+}
+
+impl Session {
+
+    fn start(stream: TcpStream, cpu: &mut Cpu, hello: &Hello, remote_keys: u16) -> Result<Session, String> {
+        stream.set_nodelay(true).map_err(|e| e.to_string())?;
+        let reader = stream.try_clone().map_err(|e| e.to_string())?;
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || receive(reader, sender));
+        cpu.reseed(hello.seed);
+        cpu.notify(format!("netplay on keys {}, the other player on {}", keys_text(hello.keys), keys_text(remote_keys)));
+
+        // nobody presses anything during the first frames of delay
+        let nothing: BTreeMap<u64, u16> = (0..hello.delay).map(|frame| (frame, 0)).collect();
+        Ok(Session {
+            stream,
+            messages,
+            keys: hello.keys,
+            remote_keys,
+            delay: hello.delay,
+            frame: 0,
+            confirmed: hello.delay,
+            local: nothing.clone(),
+            remote: nothing,
+            used: BTreeMap::new(),
+            snapshots: BTreeMap::new(),
+            mispredicted: None,
+            next_hash: 0,
+            local_hashes: BTreeMap::new(),
+            remote_hashes: BTreeMap::new(),
+            checked: 0,
+            desync: None,
+            rollbacks: 0,
+            left: false,
+        })
+    }
+
+    /// the frame emulated next
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// the keys of the other player are known for every frame before this one
+    pub fn confirmed(&self) -> u64 {
+        self.confirmed
+    }
+
+    /// the first frame found to differ from the other side
+    pub fn desync(&self) -> Option<u64> {
+        self.desync
+    }
+
+    /// hashes found the same on both sides
+    pub fn checked(&self) -> u64 {
+        self.checked
+    }
+
+    pub fn rollbacks(&self) -> u64 {
+        self.rollbacks
+    }
+
+    /// emulates the next frame with the keys pressed on this side, bit n for key n,
+    /// unless too far ahead of the other player. Returns whether a frame was emulated
+    pub fn advance(&mut self, cpu: &mut Cpu, keys: u16) -> Result<bool, String> {
+        self.settle(cpu);
+        if self.left {
+            return Err("the other player left".to_string());
+        }
+        if self.frame >= self.confirmed + MAX_ROLLBACK {
+            return Ok(false);
+        }
+
+        let frame = self.frame + self.delay;
+        let keys = keys & self.keys;
+        self.local.insert(frame, keys);
+        let mut message = vec![INPUT];
+        message.extend_from_slice(&frame.to_le_bytes());
+        message.extend_from_slice(&keys.to_le_bytes());
+        self.stream.write_all(&message).map_err(|e| format!("the other player left: {}", e))?;
+
+        self.emulate(cpu);
+        self.check(cpu);
+        self.prune();
+        Ok(true)
+    }
+
+    /// takes the messages received so far, rolling back the frames emulated with a wrong guess
+    pub fn settle(&mut self, cpu: &mut Cpu) {
+        loop {
+            match self.messages.try_recv() {
+                Ok(Message::Input(frame, keys)) => self.confirm(frame, keys & self.remote_keys),
+                Ok(Message::Hash(frame, hash)) => match self.local_hashes.remove(&frame) {
+                    Some(local) => self.compare(cpu, frame, local, hash),
+                    None => {
+                        self.remote_hashes.insert(frame, hash);
+                    },
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.left = true;
+                    break;
+                },
+            }
+        }
+        if let Some(from) = self.mispredicted.take() {
+            self.rollback(cpu, from);
+        }
+        self.check(cpu);
+    }
+
+    /// runs the session in the host loop until either player quits
+    pub fn run(&mut self, cpu: &mut Cpu) {
+        while !cpu.quit {
+            cpu.load_keyboard_status();
+            if cpu.quit {
+                break;
+            }
+            let keys = cpu.keyboard.iter().enumerate().fold(0, |keys, (key, down)| if *down { keys | 1 << key } else { keys });
+            if let Err(e) = self.advance(cpu, keys) {
+                error!("netplay - {}", e);
+                break;
+            }
+            cpu.draw();
+            cpu.buzz();
+            cpu.wait();
+        }
+        info!("netplay over at frame {}, confirmed up to {}, {} rollbacks, {} hashes checked", self.frame(), self.confirmed(), self.rollbacks(), self.checked());
+        if let Some(frame) = self.desync() {
+            error!("netplay - the players went apart at frame {}", frame);
+        }
+    }
+
+    fn confirm(&mut self, frame: u64, keys: u16) {
+        self.remote.insert(frame, keys);
+        while self.remote.contains_key(&self.confirmed) {
+            self.confirmed += 1;
+        }
+        if self.used.get(&frame).is_some_and(|used| *used != keys) {
+            self.mispredicted = Some(self.mispredicted.map_or(frame, |from| from.min(frame)));
+        }
+    }
+
+    /// emulates the frame with the keys of this side and the ones of the other player, or the last known
+    fn emulate(&mut self, cpu: &mut Cpu) {
+        let frame = self.frame;
+        let remote = match self.remote.get(&frame) {
+            Some(keys) => *keys,
+            None => self.remote.range(..frame).next_back().map_or(0, |(_, keys)| *keys),
+        };
+        self.used.insert(frame, remote);
+        self.snapshots.insert(frame, (cpu.save_state(), hash(cpu)));
+        let keys = self.local.get(&frame).copied().unwrap_or(0) | remote;
+        for (key, down) in cpu.keyboard.iter_mut().enumerate() {
+            *down = keys & 1 << key != 0;
+        }
+        cpu.frame();
+        self.frame += 1;
+    }
+
+    fn rollback(&mut self, cpu: &mut Cpu, from: u64) {
+        let state = match self.snapshots.get(&from) {
+            Some((state, _)) => state,
+            None => {
+                error!("no save state to roll frame {} back", from);
+                return;
+            },
+        };
+        if let Err(e) = cpu.load_state(state) {
+            error!("couldn't roll frame {} back: {}", from, e);
+            return;
+        }
+        debug!("rolling back {} frames", self.frame - from);
+        let end = self.frame;
+        self.frame = from;
+        while self.frame < end {
+            self.emulate(cpu);
+        }
+        self.rollbacks += 1;
+    }
+
+    /// sends the hashes of the frames both sides now agree on
+    fn check(&mut self, cpu: &mut Cpu) {
+        while self.next_hash < self.frame && self.next_hash <= self.confirmed {
+            let frame = self.next_hash;
+            self.next_hash += HASH_INTERVAL;
+            let local = match self.snapshots.get(&frame) {
+                Some((_, hash)) => *hash,
+                None => continue,
+            };
+            let mut message = vec![HASH];
+            message.extend_from_slice(&frame.to_le_bytes());
+            message.extend_from_slice(&local.to_le_bytes());
+            // a write failing means the other player left, found by the reading side
+            let _ = self.stream.write_all(&message);
+            match self.remote_hashes.remove(&frame) {
+                Some(remote) => self.compare(cpu, frame, local, remote),
+                None => {
+                    self.local_hashes.insert(frame, local);
+                },
+            }
+        }
+    }
+
+    fn compare(&mut self, cpu: &mut Cpu, frame: u64, local: u64, remote: u64) {
+        if local == remote {
+            self.checked += 1;
+        } else if self.desync.is_none() {
+            error!("desync with the other player at frame {}", frame);
+            cpu.notify(format!("desynced at frame {}", frame));
+            self.desync = Some(frame);
+        }
+    }
+
+    /// forgets the frames that can't be rolled back anymore
+    fn prune(&mut self) {
+        let keep = self.confirmed.min(self.frame).min(self.next_hash);
+        self.snapshots = self.snapshots.split_off(&keep);
+        self.used = self.used.split_off(&keep);
+        self.local = self.local.split_off(&keep);
+        // the last known keys are the guess for the frames after them
+        self.remote = self.remote.split_off(&keep.saturating_sub(1));
+    }
+}
@@ -1,5 +1,4 @@
 extern crate rand;
-use rand::Rng;
 
 use std::num::Wrapping;
 
@@ -120,6 +119,7 @@ impl Cpu {
     /// it's internal to this project only
     pub(crate) fn none(&mut self, optcode: u16) {
         error!("None => {:#X}, v:{:?}", optcode, self.v);
+        self.fault = Some(format!("unknown instruction {:04X} at {:03X}", optcode, self.pc));
         self.quit = true;
     }
 
@@ -201,7 +201,10 @@ impl Cpu {
         let (_vx, _) = vx_vy!(optcode);
         for x in 0..=_vx {
             trace!("memory[{}] = {}", self.i + x, self.v[x]);
-            self.memory[self.i + x] = self.v[x];
+            self.memory[(self.i + x) % MEMORY_SIZE] = self.v[x];
+        }
+        if self.quirks.load_store {
+            self.i = (self.i + _vx + 1) % MEMORY_SIZE;
         }
         self.pc += 2;
         //self.quit = true;
@@ -234,7 +237,10 @@ impl Cpu {
         debug!("LDVxI => {:#X} - done", optcode);
         let (_vx, _) = vx_vy!(optcode);
         for x in 0..=_vx {
-            self.v[x] = self.memory[self.i + x];
+            self.v[x] = self.memory[(self.i + x) % MEMORY_SIZE];
+        }
+        if self.quirks.load_store {
+            self.i = (self.i + _vx + 1) % MEMORY_SIZE;
         }
         self.pc += 2;
         //self.quit = true;
@@ -264,9 +270,11 @@ impl Cpu {
         let n = optcode & 0x000F;
         self.v[0xF] &= 0;
         for y in 0..n as u8 {
-            let pixel = self.memory[self.i + y as usize];
+            let pixel = self.memory[(self.i + y as usize) % MEMORY_SIZE];
             for x in 0..8 as u8 {
-                if pixel & (0x80 >> x) >= 1 {
+                let clipped = self.quirks.clip &&
+                    ((_vx as u32 % SCREEN_WIDTH) + x as u32 >= SCREEN_WIDTH || (_vy as u32 % SCREEN_HEIGHT) + y as u32 >= SCREEN_HEIGHT);
+                if pixel & (0x80 >> x) >= 1 && !clipped {
                     let address =
                         (add!(x, _vx) as u32 % SCREEN_WIDTH) as usize +
                         (add!(y, _vy) as u32 % SCREEN_HEIGHT) as usize *
@@ -277,6 +285,7 @@ impl Cpu {
                         error!("x:{} _x:{} x+_x:{}", x, _vx, add!(x, _vx));
                         error!("y:{} _y:{} y+_y:{}", y, _vy, add!(y, _vy));
                         error!("x+y*screen_width:{}", add!(x, _vx) as usize + add!(y, _vy) as usize * SCREEN_WIDTH as usize);                        
+                        self.fault = Some(format!("sprite drawn outside the display at {:03X}", self.pc));
                         self.quit = true;
                         return;
                     }
@@ -368,7 +377,7 @@ impl Cpu {
     /// The results are stored in Vx. See instruction 8xy2 for more information on AND.
     pub(crate) fn rnd_vx_byte(&mut self, optcode: u16) {
         debug!("RNDVxByte => {:#X} - done", optcode);
-        let rnd: u8 = rand::thread_rng().gen_range(0, 255).into();
+        let rnd = self.random();
         let (_vx, _byte) = vx_byte!(optcode);
         self.v[_vx] = rnd & _byte;
         self.pc += 2;
@@ -443,7 +452,8 @@ impl Cpu {
     pub(crate) fn shr_vx_vy(&mut self, optcode: u16) {
         debug!("SHRVxVy => {:#X}", optcode);
         let (_x, _y) = vx_vy!(optcode);
-        self.v[_x] = by!(self.v[_x], 2);
+        let source = if self.quirks.shift { self.v[_y] } else { self.v[_x] };
+        self.v[_x] = by!(source, 2);
         self.v[0xF] = tern!(self.v[_x] & (1 << 7) == 0, 0, 1);
         self.pc += 2;
         //self.quit = true;
@@ -490,7 +500,8 @@ impl Cpu {
     pub(crate) fn shl_vx_vy(&mut self, optcode: u16) {
         debug!("SHLVxVy => {:#X}", optcode);
         let (_x, _y) = vx_vy!(optcode);
-        self.v[_x] = times!(self.v[_x], 2);
+        let source = if self.quirks.shift { self.v[_y] } else { self.v[_x] };
+        self.v[_x] = times!(source, 2);
         self.v[0xF] = tern!(self.v[_x] & (1 << 7) == 0, 0, 1);
         self.pc += 2;
         //self.quit = true;
@@ -514,9 +525,9 @@ impl Cpu {
         // let (_vx, _) = self.vx_vy(optcode);
         let (_x, _) = vx_vy!(optcode);
         let _vx = self.v[_x];
-        self.memory[self.i] = _vx / 100;
-        self.memory[self.i + 1] = _vx / 10 % 10;
-        self.memory[self.i + 2] = _vx % 10;
+        self.memory[self.i % MEMORY_SIZE] = _vx / 100;
+        self.memory[(self.i + 1) % MEMORY_SIZE] = _vx / 10 % 10;
+        self.memory[(self.i + 2) % MEMORY_SIZE] = _vx % 10;
         self.pc += 2;
         //self.quit = true;
     }
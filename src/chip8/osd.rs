@@ -0,0 +1,93 @@
+use std::time::Duration;
+use instant::Instant;
+
+use super::cpu::*;
+
+/// how long a message stays on screen
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+
+/// interval the counters are averaged over
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What the frontend draws over the screen, kept apart from the chip-8 display buffer
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Overlay {
+    pub lines: Vec<String>,
+    pub paused: bool,
+}
+
+/// On-screen display: transient messages and the optional fps / ips counter
+pub struct Osd {
+    pub(crate) show_stats: bool,
+    pub(crate) shown: Overlay,
+    /// lines drawn by scripts, replaced every frame
+    pub(crate) hud: Vec<String>,
+    messages: Vec<(String, Instant)>,
+    stats: String,
+    sample_start: Instant,
+    sample_frames: u64,
+    sample_instructions: u64,
+}
+
+impl Osd {
+
+    pub(crate) fn new() -> Osd {
+        Osd {
+            show_stats: false,
+            shown: Overlay::default(),
+            hud: Vec::new(),
+            messages: Vec::new(),
+            stats: String::new(),
+            sample_start: Instant::now(),
+            sample_frames: 0,
+            sample_instructions: 0,
+        }
+    }
+
+    /// counts a presented frame, the counters are refreshed once per sample interval
+    fn sample(&mut self, now: Instant, instructions: u64) {
+        self.sample_frames += 1;
+        let elapsed = now.duration_since(self.sample_start);
+        if elapsed < SAMPLE_INTERVAL {
+            return;
+        }
+        let seconds = elapsed.as_secs_f64();
+        self.stats = format!("{:.0} fps {:.0} ips",
+            self.sample_frames as f64 / seconds,
+            instructions.saturating_sub(self.sample_instructions) as f64 / seconds);
+        self.sample_start = now;
+        self.sample_frames = 0;
+        self.sample_instructions = instructions;
+    }
+}
+
+impl Cpu {
+
+    /// logs a message and shows it on screen for a while
+    pub(crate) fn notify(&mut self, message: String) {
+        info!("{}", message);
+        self.osd.messages.push((message, Instant::now()));
+    }
+
+    pub(crate) fn toggle_stats(&mut self) {
+        self.osd.show_stats = !self.osd.show_stats;
+    }
+
+    /// builds the overlay of the current host frame, dropping expired messages
+    pub(crate) fn overlay(&mut self) -> Overlay {
+        let now = Instant::now();
+        self.osd.sample(now, self.clock.instructions);
+        self.osd.messages.retain(|(_, at)| now.duration_since(*at) < MESSAGE_DURATION);
+
+        let mut lines = Vec::new();
+        if self.osd.show_stats {
+            lines.push(self.osd.stats.clone());
+        }
+        lines.extend(self.osd.hud.iter().cloned());
+        lines.extend(self.osd.messages.iter().map(|(message, _)| message.clone()));
+        Overlay {
+            lines,
+            paused: self.clock.paused,
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::fs;
+use std::path::Path;
+use structopt::clap::arg_enum;
+
+use super::rom::crc32;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+/// largest record an ips patch can hold
+const IPS_RECORD: usize = 0xFFFF;
+const BPS_MAGIC: &[u8] = b"BPS1";
+
+arg_enum! {
+    /// Ips: offsets and replacement bytes, no validation
+    /// Bps: copy / read actions with crc32 checks of the source, target and patch
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Format {
+        Ips,
+        Bps,
+    }
+}
+
+/// reads a patch file and applies it to the rom, the format is told by its header
+pub fn load(path: &Path, rom: Vec<u8>) -> Result<Vec<u8>, String> {
+    let patch = fs::read(path).map_err(|e| format!("couldn't read the patch: {}", e))?;
+    apply(&patch, rom).map_err(|e| format!("couldn't apply {}: {}", path.display(), e))
+}
+
+pub fn apply(patch: &[u8], rom: Vec<u8>) -> Result<Vec<u8>, String> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(patch, rom)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(patch, &rom)
+    } else {
+        Err("not an ips or bps patch".to_string())
+    }
+}
+
+/// Cursor over the patch bytes
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let bytes = self.bytes.get(self.offset..self.offset + count).ok_or("truncated patch")?;
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    fn number(&mut self, count: usize) -> Result<usize, String> {
+        Ok(self.take(count)?.iter().fold(0, |n, byte| n << 8 | *byte as usize))
+    }
+
+    /// bps variable length number, 7 bits per byte, the last byte has the top bit set
+    fn varint(&mut self) -> Result<usize, String> {
+        let (mut data, mut shift) = (0usize, 1usize);
+        loop {
+            let byte = self.take(1)?[0] as usize;
+            data = data.checked_add((byte & 0x7F) * shift).ok_or("bad number")?;
+            if byte & 0x80 != 0 {
+                return Ok(data);
+            }
+            shift = shift.checked_mul(0x80).ok_or("bad number")?;
+            data = data.checked_add(shift).ok_or("bad number")?;
+        }
+    }
+
+    /// bps signed offset, the sign is in the lowest bit
+    fn offset(&mut self, from: usize) -> Result<usize, String> {
+        let data = self.varint()?;
+        let offset = data >> 1;
+        if data & 1 == 1 { from.checked_sub(offset) } else { from.checked_add(offset) }.ok_or_else(|| "bad offset".to_string())
+    }
+}
+
+fn apply_ips(patch: &[u8], mut rom: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut reader = Reader { bytes: patch, offset: IPS_MAGIC.len() };
+    loop {
+        if reader.bytes[reader.offset..].starts_with(IPS_EOF) {
+            reader.offset += IPS_EOF.len();
+            break;
+        }
+        let offset = reader.number(3)?;
+        let (size, bytes) = match reader.number(2)? {
+            // run length encoded record
+            0 => {
+                let size = reader.number(2)?;
+                (size, vec![reader.take(1)?[0]; size])
+            },
+            size => (size, reader.take(size)?.to_vec()),
+        };
+        if rom.len() < offset + size {
+            rom.resize(offset + size, 0);
+        }
+        rom[offset..offset + size].copy_from_slice(&bytes);
+    }
+    // truncation extension
+    if let Ok(size) = reader.number(3) {
+        rom.truncate(size);
+    }
+    Ok(rom)
+}
+
+fn apply_bps(patch: &[u8], source: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.len() < BPS_MAGIC.len() + 12 {
+        return Err("truncated patch".to_string());
+    }
+    let footer = &patch[patch.len() - 12..];
+    let checksum = |at: usize| u32::from_le_bytes([footer[at], footer[at + 1], footer[at + 2], footer[at + 3]]);
+    if crc32(&patch[..patch.len() - 4]) != checksum(8) {
+        return Err("corrupted patch, checksum mismatch".to_string());
+    }
+    if crc32(source) != checksum(0) {
+        return Err(format!("the patch is for another rom, crc32 {:08X} expected, {:08X} found", checksum(0), crc32(source)));
+    }
+
+    let mut reader = Reader { bytes: &patch[..patch.len() - 12], offset: BPS_MAGIC.len() };
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata_size = reader.varint()?;
+    reader.take(metadata_size)?;
+    if source_size != source.len() {
+        return Err("source size mismatch".to_string());
+    }
+
+    let mut target = Vec::with_capacity(target_size);
+    let (mut source_offset, mut target_offset) = (0, 0);
+    while reader.offset < reader.bytes.len() {
+        let data = reader.varint()?;
+        let length = (data >> 2) + 1;
+        match data & 3 {
+            // source read
+            0 => {
+                let at = target.len();
+                target.extend_from_slice(source.get(at..at + length).ok_or("source read out of bounds")?);
+            },
+            // target read
+            1 => target.extend_from_slice(reader.take(length)?),
+            // source copy
+            2 => {
+                source_offset = reader.offset(source_offset)?;
+                target.extend_from_slice(source.get(source_offset..source_offset + length).ok_or("source copy out of bounds")?);
+                source_offset += length;
+            },
+            // target copy, may overlap what it writes
+            _ => {
+                target_offset = reader.offset(target_offset)?;
+                for _ in 0..length {
+                    let byte = *target.get(target_offset).ok_or("target copy out of bounds")?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            },
+        }
+    }
+
+    if target.len() != target_size || crc32(&target) != checksum(4) {
+        return Err("patched rom checksum mismatch".to_string());
+    }
+    Ok(target)
+}
+
+/// builds a patch turning `original` into `modified`
+pub fn make(original: &[u8], modified: &[u8], format: Format) -> Vec<u8> {
+    match format {
+        Format::Ips => make_ips(original, modified),
+        Format::Bps => make_bps(original, modified),
+    }
+}
+
+fn make_ips(original: &[u8], modified: &[u8]) -> Vec<u8> {
+    let mut patch = IPS_MAGIC.to_vec();
+    let mut offset = 0;
+    while offset < modified.len() {
+        if original.get(offset) == Some(&modified[offset]) {
+            offset += 1;
+            continue;
+        }
+        let start = offset;
+        while offset < modified.len() && offset - start < IPS_RECORD && original.get(offset) != Some(&modified[offset]) {
+            offset += 1;
+        }
+        patch.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+        patch.extend_from_slice(&((offset - start) as u16).to_be_bytes());
+        patch.extend_from_slice(&modified[start..offset]);
+    }
+    patch.extend_from_slice(IPS_EOF);
+    if modified.len() < original.len() {
+        patch.extend_from_slice(&(modified.len() as u32).to_be_bytes()[1..]);
+    }
+    patch
+}
+
+fn varint(patch: &mut Vec<u8>, mut data: usize) {
+    loop {
+        let byte = (data & 0x7F) as u8;
+        data >>= 7;
+        if data == 0 {
+            patch.push(byte | 0x80);
+            return;
+        }
+        patch.push(byte);
+        data -= 1;
+    }
+}
+
+/// linear bps: bytes matching the original are source reads, the others target reads
+fn make_bps(original: &[u8], modified: &[u8]) -> Vec<u8> {
+    let mut patch = BPS_MAGIC.to_vec();
+    varint(&mut patch, original.len());
+    varint(&mut patch, modified.len());
+    varint(&mut patch, 0);
+
+    let same = |offset: usize| original.get(offset) == Some(&modified[offset]);
+    let mut offset = 0;
+    while offset < modified.len() {
+        let start = offset;
+        let matching = same(offset);
+        while offset < modified.len() && same(offset) == matching {
+            offset += 1;
+        }
+        let length = offset - start;
+        if matching {
+            varint(&mut patch, (length - 1) << 2);
+        } else {
+            varint(&mut patch, ((length - 1) << 2) | 1);
+            patch.extend_from_slice(&modified[start..offset]);
+        }
+    }
+
+    patch.extend_from_slice(&crc32(original).to_le_bytes());
+    patch.extend_from_slice(&crc32(modified).to_le_bytes());
+    let checksum = crc32(&patch);
+    patch.extend_from_slice(&checksum.to_le_bytes());
+    patch
+}
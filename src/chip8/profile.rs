@@ -0,0 +1,228 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::cpu::*;
+use super::disasm::{disassemble, pattern};
+use super::inspect::accesses;
+
+/// how many entries the hot spot tables list
+const HOT_SPOTS: usize = 20;
+
+/// Cycles spent in a subroutine, found through CALL and RET
+#[derive(Default)]
+struct Subroutine {
+    calls: u64,
+    /// cycles from the CALL to its RET, nested calls included
+    cycles: u64,
+    /// cycles of the nested calls
+    nested: u64,
+}
+
+/// Execution profiler: counts per pc and per opcode, subroutine cycles and rom coverage
+pub struct Profile {
+    load_address: u16,
+    rom_size: usize,
+    executions: Vec<u64>,
+    reads: Vec<u64>,
+    opcodes: Vec<u64>,
+    subroutines: BTreeMap<u16, Subroutine>,
+    /// subroutines running, with the cycle they were called at and the cycles of their nested calls
+    calls: Vec<(u16, u64, u64)>,
+}
+
+/// the rom is the `rom_size` bytes loaded at `load_address`
+pub fn new(load_address: u16, rom_size: usize) -> Profile {
+    Profile {
+        load_address,
+        rom_size,
+        executions: vec![0; MEMORY_SIZE],
+        reads: vec![0; MEMORY_SIZE],
+        opcodes: vec![0; 0x10000],
+        subroutines: BTreeMap::new(),
+        calls: Vec::new(),
+    }
+}
+
+impl Profile {
+
+    /// counts the instruction under the pc, before it runs
+    pub(crate) fn record(&mut self, cpu: &Cpu) {
+        let pc = cpu.pc as usize % MEMORY_SIZE;
+        let opcode = u16::from_be_bytes([cpu.memory[pc], cpu.memory[(pc + 1) % MEMORY_SIZE]]);
+        let cycle = cpu.clock.instructions;
+        self.executions[pc] += 1;
+        self.opcodes[opcode as usize] += 1;
+
+        let (reads, _) = accesses(opcode, cpu.i);
+        for address in reads {
+            self.reads[address % MEMORY_SIZE] += 1;
+        }
+
+        match opcode >> 12 {
+            0x2 => {
+                let target = opcode & 0x0FFF;
+                self.subroutines.entry(target).or_default().calls += 1;
+                self.calls.push((target, cycle, 0));
+            },
+            0x0 if opcode == 0x00EE => if let Some((target, called, nested)) = self.calls.pop() {
+                let cycles = cycle - called + 1;
+                let subroutine = self.subroutines.entry(target).or_default();
+                subroutine.cycles += cycles;
+                subroutine.nested += nested;
+                if let Some(caller) = self.calls.last_mut() {
+                    caller.2 += cycles;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn executed(&self, address: usize) -> bool {
+        self.executions[address] > 0 || (address > 0 && self.executions[address - 1] > 0)
+    }
+
+    fn rom(&self) -> std::ops::Range<usize> {
+        let start = self.load_address as usize;
+        start..(start + self.rom_size).min(MEMORY_SIZE)
+    }
+
+    /// hot spots, opcode mix, subroutines and coverage
+    pub fn report(&self, path: &Path, cpu: &Cpu) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let total = cpu.clock.instructions.max(1);
+        writeln!(out, "{} instructions in {} frames", cpu.clock.instructions, cpu.clock.frames)?;
+
+        writeln!(out, "\nhot spots")?;
+        let mut pcs: Vec<(usize, u64)> = self.executions.iter().cloned().enumerate().filter(|(_, n)| *n > 0).collect();
+        pcs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for (pc, n) in pcs.into_iter().take(HOT_SPOTS) {
+            let opcode = u16::from_be_bytes([cpu.memory[pc], cpu.memory[(pc + 1) % MEMORY_SIZE]]);
+            writeln!(out, "  {:03X}  {:>10}  {:5.1}%  {}", pc, n, n as f64 * 100.0 / total as f64, disassemble(opcode))?;
+        }
+
+        writeln!(out, "\nopcodes")?;
+        let mut patterns: BTreeMap<&str, u64> = BTreeMap::new();
+        for (opcode, n) in self.opcodes.iter().enumerate().filter(|(_, n)| **n > 0) {
+            *patterns.entry(pattern(opcode as u16)).or_default() += n;
+        }
+        let mut patterns: Vec<(&str, u64)> = patterns.into_iter().collect();
+        patterns.sort_by_key(|(_, n)| Reverse(*n));
+        for (pattern, n) in patterns {
+            writeln!(out, "  {:<24} {:>10}  {:5.1}%", pattern, n, n as f64 * 100.0 / total as f64)?;
+        }
+
+        writeln!(out, "\nsubroutines (cycles include nested calls, self excludes them)")?;
+        let mut subroutines: Vec<(&u16, &Subroutine)> = self.subroutines.iter().collect();
+        subroutines.sort_by_key(|(_, subroutine)| Reverse(subroutine.cycles));
+        for (address, subroutine) in subroutines {
+            writeln!(out, "  {:03X}  {:>8} calls  {:>10} cycles  {:>10} self  {:5.1}%", address, subroutine.calls,
+                subroutine.cycles, subroutine.cycles - subroutine.nested, subroutine.cycles as f64 * 100.0 / total as f64)?;
+        }
+
+        let (mut code, mut data, mut untouched) = (0, 0, 0);
+        for address in self.rom() {
+            if self.executed(address) {
+                code += 1;
+            } else if self.reads[address] > 0 {
+                data += 1;
+            } else {
+                untouched += 1;
+            }
+        }
+        let size = self.rom().len().max(1) as f64;
+        writeln!(out, "\ncoverage of the {} bytes rom", self.rom().len())?;
+        writeln!(out, "  executed      {:>5}  {:5.1}%", code, code as f64 * 100.0 / size)?;
+        writeln!(out, "  read as data  {:>5}  {:5.1}%", data, data as f64 * 100.0 / size)?;
+        writeln!(out, "  untouched     {:>5}  {:5.1}%", untouched, untouched as f64 * 100.0 / size)?;
+        out.flush()
+    }
+
+    /// disassembly of the rom annotated with execution and read counts,
+    /// data bytes are shown as sprite rows and untouched runs are folded
+    pub fn listing(&self, path: &Path, cpu: &Cpu) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let rom = self.rom();
+        let mut address = rom.start;
+        while address < rom.end {
+            if self.executions[address] > 0 {
+                if self.subroutines.contains_key(&(address as u16)) {
+                    writeln!(out, "\nsub_{:03X}:", address)?;
+                }
+                let opcode = u16::from_be_bytes([cpu.memory[address], cpu.memory[(address + 1) % MEMORY_SIZE]]);
+                writeln!(out, "  {:03X}  {:04X}  {:<20} ; {} executions", address, opcode, disassemble(opcode), self.executions[address])?;
+                address += 2;
+            } else if self.reads[address] > 0 {
+                let byte = cpu.memory[address];
+                let sprite: String = (0..8).map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' }).collect();
+                writeln!(out, "  {:03X}  {:02X}    DB {:#04X}  {}    ; {} reads", address, byte, byte, sprite, self.reads[address])?;
+                address += 1;
+            } else {
+                let start = address;
+                while address < rom.end && self.executions[address] == 0 && self.reads[address] == 0 {
+                    address += 1;
+                }
+                writeln!(out, "  {:03X}-{:03X}  untouched, {} bytes", start, address - 1, address - start)?;
+            }
+        }
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::frontend::headless::Headless;
+    use crate::chip8::sound;
+
+    /// CALL 208; LD I, 212; DRW V0, V0, 1; JP 206;
+    /// 208: CALL 20E; LD V1, 1; RET; 20E: LD V2, 2; RET;
+    /// a sprite row at 212 and three bytes nothing touches
+    const ROM: [u8; 22] = [
+        0x22, 0x08, 0xA2, 0x12, 0xD0, 0x01, 0x12, 0x06,
+        0x22, 0x0E, 0x61, 0x01, 0x00, 0xEE,
+        0x62, 0x02, 0x00, 0xEE,
+        0xFF, 0x00, 0x00, 0x00,
+    ];
+
+    fn profiled(frames: usize) -> (Cpu, Profile) {
+        let mut cpu = initialize(Box::new(Headless), Box::new(sound::Null));
+        cpu.bootup(ROM.to_vec());
+        cpu.profile = Some(new(0x200, ROM.len()));
+        for _ in 0..frames {
+            cpu.frame();
+        }
+        let profile = cpu.profile.take().unwrap();
+        (cpu, profile)
+    }
+
+    #[test]
+    fn nested_calls() {
+        let (_, profile) = profiled(10);
+        let outer = &profile.subroutines[&0x208];
+        assert_eq!((outer.calls, outer.cycles, outer.nested), (1, 6, 3), "from its CALL to its RET");
+        let inner = &profile.subroutines[&0x20E];
+        assert_eq!((inner.calls, inner.cycles, inner.nested), (1, 3, 0));
+        assert!(profile.calls.is_empty());
+        assert_eq!(profile.executions[0x206], 2);
+    }
+
+    #[test]
+    fn report() {
+        let (cpu, profile) = profiled(10);
+        let path = std::env::temp_dir().join(format!("chip8-{}-profile", std::process::id()));
+        profile.report(&path, &cpu).unwrap();
+        let report = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(report.starts_with("10 instructions in 10 frames\n"), "{}", report);
+        assert!(report.contains("  208         1 calls           6 cycles           3 self   60.0%\n"), "{}", report);
+        assert!(report.contains("  20E         1 calls           3 cycles           3 self   30.0%\n"), "{}", report);
+        assert!(report.ends_with(concat!(
+            "coverage of the 22 bytes rom\n",
+            "  executed         18   81.8%\n",
+            "  read as data      1    4.5%\n",
+            "  untouched         3   13.6%\n")), "{}", report);
+    }
+}
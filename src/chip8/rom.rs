@@ -0,0 +1,24 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+/// reads a rom image from disk
+pub fn load(path: &Path) -> Result<Vec<u8>, String> {
+    let mut file = File::open(path).map_err(|e| format!("couldn't open the rom: {}", e))?;
+    let mut program_buffer = Vec::<u8>::new();
+    file.read_to_end(&mut program_buffer).map_err(|e| format!("couldn't load rom: {}", e))?;
+    trace!("{:?}", program_buffer);
+    Ok(program_buffer)
+}
+
+/// CRC-32 (IEEE) of a rom image, identifies roms in cheat files and patches
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
@@ -0,0 +1,257 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use rhai::{Array, Dynamic, Engine, FnPtr, Scope, AST};
+
+use super::cpu::*;
+use super::cheat::{Comparison, Search};
+
+/// What scripts see of the machine, copied in before and back out after every callback
+#[derive(Default)]
+struct State {
+    v: [u8; 16],
+    i: usize,
+    pc: u16,
+    memory: Vec<u8>,
+    keyboard: [bool; KEYBOARD_SIZE],
+    frames: u64,
+    /// keys held down by the script on top of the frontend ones
+    held: [bool; KEYBOARD_SIZE],
+    hud: Vec<String>,
+    notices: Vec<String>,
+    screenshots: Vec<String>,
+    quit: bool,
+    /// the cheat search and frozen addresses, moved in from the cpu
+    search: Search,
+    frozen: BTreeMap<u16, u8>,
+    frame_hooks: Vec<FnPtr>,
+    pc_hooks: HashMap<u16, Vec<FnPtr>>,
+    write_hooks: HashMap<u16, Vec<FnPtr>>,
+}
+
+/// Rhai script driving the emulation through callbacks:
+/// on_frame(f) after every host frame, on_pc(address, f) before the instruction at address runs,
+/// on_write(address, f) when the byte at address changes.
+/// The hud lines are cleared before the frame callbacks run, so they redraw it every frame.
+/// The cheat search narrows down candidate addresses with search_equal(value), search_changed(),
+/// search_unchanged(), search_increased() and search_decreased() against the previous search,
+/// freeze(address, value) then keeps a byte at that value.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    state: Rc<RefCell<State>>,
+    /// last seen value of the bytes watched by on_write callbacks
+    watched: HashMap<u16, u8>,
+}
+
+/// compiles the script and runs its top level, which registers the callbacks
+pub fn load(path: &Path, cpu: &mut Cpu) -> Result<Script, String> {
+    let state = Rc::new(RefCell::new(State::default()));
+    let mut engine = Engine::new();
+    register(&mut engine, &state);
+    let ast = engine.compile_file(PathBuf::from(path)).map_err(|e| format!("couldn't load script: {}", e))?;
+
+    let mut script = Script {
+        engine,
+        ast,
+        state,
+        watched: HashMap::new(),
+    };
+    script.load(cpu);
+    let result = script.engine.run_ast_with_scope(&mut Scope::new(), &script.ast);
+    script.store(cpu);
+    result.map_err(|e| format!("script failed: {}", e))?;
+    Ok(script)
+}
+
+/// registers the functions scripts call, integers are rhai's i64
+fn register(engine: &mut Engine, state: &Rc<RefCell<State>>) {
+    let s = state.clone();
+    engine.register_fn("on_frame", move |f: FnPtr| s.borrow_mut().frame_hooks.push(f));
+    let s = state.clone();
+    engine.register_fn("on_pc", move |address: i64, f: FnPtr| {
+        s.borrow_mut().pc_hooks.entry(address as u16).or_default().push(f)
+    });
+    let s = state.clone();
+    engine.register_fn("on_write", move |address: i64, f: FnPtr| {
+        s.borrow_mut().write_hooks.entry(address as u16 % MEMORY_SIZE as u16).or_default().push(f)
+    });
+
+    let s = state.clone();
+    engine.register_fn("v", move |x: i64| s.borrow().v[x as usize & 0xF] as i64);
+    let s = state.clone();
+    engine.register_fn("set_v", move |x: i64, value: i64| s.borrow_mut().v[x as usize & 0xF] = value as u8);
+    let s = state.clone();
+    engine.register_fn("i", move || s.borrow().i as i64);
+    let s = state.clone();
+    engine.register_fn("set_i", move |value: i64| s.borrow_mut().i = value as usize % MEMORY_SIZE);
+    let s = state.clone();
+    engine.register_fn("pc", move || s.borrow().pc as i64);
+    let s = state.clone();
+    engine.register_fn("set_pc", move |value: i64| s.borrow_mut().pc = value as u16 % MEMORY_SIZE as u16);
+    let s = state.clone();
+    engine.register_fn("peek", move |address: i64| s.borrow().memory[address as usize % MEMORY_SIZE] as i64);
+    let s = state.clone();
+    engine.register_fn("poke", move |address: i64, value: i64| {
+        s.borrow_mut().memory[address as usize % MEMORY_SIZE] = value as u8
+    });
+    let s = state.clone();
+    engine.register_fn("frames", move || s.borrow().frames as i64);
+
+    let s = state.clone();
+    engine.register_fn("key", move |k: i64| s.borrow().keyboard[k as usize & 0xF]);
+    let s = state.clone();
+    engine.register_fn("press", move |k: i64| s.borrow_mut().held[k as usize & 0xF] = true);
+    let s = state.clone();
+    engine.register_fn("release", move |k: i64| s.borrow_mut().held[k as usize & 0xF] = false);
+
+    let s = state.clone();
+    engine.register_fn("search_start", move || {
+        let mut state = s.borrow_mut();
+        let state = &mut *state;
+        state.search.start(&state.memory)
+    });
+    for (name, comparison) in [
+        ("search_changed", Comparison::Changed),
+        ("search_unchanged", Comparison::Unchanged),
+        ("search_increased", Comparison::Increased),
+        ("search_decreased", Comparison::Decreased),
+    ].iter().cloned() {
+        let s = state.clone();
+        engine.register_fn(name, move || {
+            let mut state = s.borrow_mut();
+            let state = &mut *state;
+            state.search.filter(&state.memory, comparison)
+        });
+    }
+    let s = state.clone();
+    engine.register_fn("search_equal", move |value: i64| {
+        let mut state = s.borrow_mut();
+        let state = &mut *state;
+        state.search.filter(&state.memory, Comparison::Equal(value as u8))
+    });
+    let s = state.clone();
+    engine.register_fn("search_results", move || -> Array {
+        s.borrow().search.candidates().iter().map(|address| Dynamic::from(*address as i64)).collect()
+    });
+    let s = state.clone();
+    engine.register_fn("freeze", move |address: i64, value: i64| {
+        s.borrow_mut().frozen.insert(address as u16 % MEMORY_SIZE as u16, value as u8);
+    });
+    let s = state.clone();
+    engine.register_fn("unfreeze", move |address: i64| {
+        s.borrow_mut().frozen.remove(&(address as u16 % MEMORY_SIZE as u16));
+    });
+
+    let s = state.clone();
+    engine.register_fn("hud", move |text: &str| s.borrow_mut().hud.push(text.to_string()));
+    let s = state.clone();
+    engine.register_fn("notify", move |text: &str| s.borrow_mut().notices.push(text.to_string()));
+    let s = state.clone();
+    engine.register_fn("screenshot", move |path: &str| s.borrow_mut().screenshots.push(path.to_string()));
+    let s = state.clone();
+    engine.register_fn("quit", move || s.borrow_mut().quit = true);
+}
+
+impl Script {
+
+    fn load(&mut self, cpu: &mut Cpu) {
+        let mut state = self.state.borrow_mut();
+        state.search = std::mem::take(&mut cpu.cheats.search);
+        state.frozen = std::mem::take(&mut cpu.cheats.frozen);
+        state.v = cpu.v;
+        state.i = cpu.i;
+        state.pc = cpu.pc;
+        state.memory = cpu.memory.to_vec();
+        state.keyboard = cpu.keyboard;
+        state.frames = cpu.clock.frames;
+    }
+
+    fn store(&mut self, cpu: &mut Cpu) {
+        let mut state = self.state.borrow_mut();
+        cpu.v = state.v;
+        cpu.i = state.i;
+        cpu.pc = state.pc;
+        cpu.memory.copy_from_slice(&state.memory);
+        cpu.quit |= state.quit;
+        cpu.cheats.search = std::mem::take(&mut state.search);
+        cpu.cheats.frozen = std::mem::take(&mut state.frozen);
+        for message in state.notices.drain(..) {
+            cpu.notify(message);
+        }
+        for path in state.screenshots.drain(..) {
+            if let Err(e) = cpu.screenshot(Path::new(&path)) {
+                error!("{}", e);
+            }
+        }
+        // the script's own writes don't trigger its on_write callbacks
+        for (address, value) in self.watched.iter_mut() {
+            *value = cpu.memory[*address as usize];
+        }
+    }
+
+    fn call(&mut self, cpu: &mut Cpu, hooks: Vec<FnPtr>, args: Vec<i64>) {
+        self.load(cpu);
+        for hook in hooks {
+            if let Err(e) = hook.call::<rhai::Dynamic>(&self.engine, &self.ast, args.clone()) {
+                error!("script error in {}: {}", hook.fn_name(), e);
+            }
+        }
+        self.store(cpu);
+    }
+
+    /// runs the on_write callbacks of the watched bytes the machine changed
+    fn writes(&mut self, cpu: &mut Cpu) {
+        let mut writes = Vec::new();
+        for (address, hooks) in self.state.borrow().write_hooks.iter() {
+            let value = cpu.memory[*address as usize];
+            let seen = self.watched.entry(*address).or_insert(value);
+            if *seen != value {
+                *seen = value;
+                writes.push((*address, value, hooks.clone()));
+            }
+        }
+        for (address, value, hooks) in writes {
+            self.call(cpu, hooks, vec![address as i64, value as i64]);
+        }
+    }
+
+    /// runs before every instruction: applies the held keys, then the write and pc callbacks
+    fn instruction(&mut self, cpu: &mut Cpu) -> bool {
+        let held = self.state.borrow().held;
+        for (key, held) in cpu.keyboard.iter_mut().zip(held.iter()) {
+            *key |= *held;
+        }
+
+        self.writes(cpu);
+
+        let hooks = self.state.borrow().pc_hooks.get(&cpu.pc).cloned();
+        if let Some(hooks) = hooks {
+            self.call(cpu, hooks, vec![cpu.pc as i64]);
+        }
+        false
+    }
+
+    fn frame(&mut self, cpu: &mut Cpu) {
+        // the writes of the last instruction, before the frame callbacks take them as the script's own
+        self.writes(cpu);
+        let hooks = {
+            let mut state = self.state.borrow_mut();
+            state.hud.clear();
+            state.frame_hooks.clone()
+        };
+        self.call(cpu, hooks, Vec::new());
+        cpu.osd.hud = self.state.borrow().hud.clone();
+    }
+
+    /// same as Cpu::run with the script callbacks
+    pub fn run(&mut self, cpu: &mut Cpu) {
+        while !cpu.quit {
+            cpu.run_frame(&mut |cpu| self.instruction(cpu));
+            self.frame(cpu);
+        }
+        cpu.dump();
+        info!("quitting");
+    }
+}
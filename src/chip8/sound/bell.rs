@@ -0,0 +1,26 @@
+use std::io::{self, Write};
+
+use super::Audio;
+
+/// Rings the terminal bell whenever the buzzer starts
+pub struct Bell {
+    buzzing: bool,
+}
+
+impl Audio for Bell {
+    fn buzz(&mut self, on: bool) {
+        if on && !self.buzzing {
+            let mut out = io::stdout();
+            if let Err(e) = out.write_all(b"\x07").and_then(|_| out.flush()) {
+                error!("couldn't ring the terminal bell: {}", e);
+            }
+        }
+        self.buzzing = on;
+    }
+}
+
+pub fn new() -> Bell {
+    Bell {
+        buzzing: false,
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use rodio::{Sink, Source};
+
+use super::*;
+use super::ring::Ring;
+
+/// samples the audio thread takes from the ring at once
+const CHUNK_SIZE: usize = 256;
+
+/// the ring holds up to a quarter of second, anything beyond is dropped
+const RING_CAPACITY: usize = SAMPLE_RATE as usize / 4;
+
+/// Plays the buzzer on the default output device through rodio.
+/// Samples are generated on the emulator side, one timer tick worth at a time,
+/// so the tone lasts exactly as long as the emulated sound timer
+pub struct Device {
+    _sink: Sink,
+    ring: Arc<Mutex<Ring>>,
+    oscillator: Oscillator,
+}
+
+/// Endless rodio source draining the ring, plays silence when it runs dry
+struct Buzzer {
+    ring: Arc<Mutex<Ring>>,
+    chunk: Vec<f32>,
+    position: usize,
+}
+
+impl Iterator for Buzzer {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position == self.chunk.len() {
+            self.chunk.clear();
+            self.position = 0;
+            let mut ring = self.ring.lock().unwrap();
+            while self.chunk.len() < CHUNK_SIZE {
+                match ring.pop() {
+                    Some(sample) => self.chunk.push(sample),
+                    None => break,
+                }
+            }
+            if self.chunk.is_empty() {
+                self.chunk.resize(CHUNK_SIZE / 4, 0.0);
+            }
+        }
+        self.position += 1;
+        Some(self.chunk[self.position - 1])
+    }
+}
+
+impl Source for Buzzer {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Audio for Device {
+    fn buzz(&mut self, on: bool) {
+        let mut ring = self.ring.lock().unwrap();
+        let mut dropped = 0;
+        for _ in 0..SAMPLE_RATE / TIMER_RATE {
+            if !ring.push(self.oscillator.next(on)) {
+                dropped += 1;
+            }
+        }
+        if dropped > 0 {
+            trace!("audio ring full, {} samples dropped", dropped);
+        }
+    }
+
+    fn queued(&self) -> Option<Duration> {
+        let len = self.ring.lock().unwrap().len();
+        Some(Duration::from_secs_f64(len as f64 / f64::from(SAMPLE_RATE)))
+    }
+}
+
+pub fn new(tone: Tone) -> Result<Device, String> {
+    let device = rodio::default_output_device().ok_or("no audio output device found")?;
+    let sink = Sink::new(&device);
+    let ring = Arc::new(Mutex::new(Ring::new(RING_CAPACITY)));
+    sink.append(Buzzer {
+        ring: ring.clone(),
+        chunk: Vec::with_capacity(CHUNK_SIZE),
+        position: 0,
+    });
+
+    Ok(Device {
+        _sink: sink,
+        ring,
+        oscillator: Oscillator::new(tone, SAMPLE_RATE),
+    })
+}
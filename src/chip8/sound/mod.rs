@@ -0,0 +1,138 @@
+pub mod bell;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod device;
+pub mod wav;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
+#[cfg(not(target_arch = "wasm32"))]
+mod ring;
+
+use std::f32::consts::PI;
+use std::time::Duration;
+use structopt::clap::arg_enum;
+
+/// sample rate of the generated tone
+pub(crate) const SAMPLE_RATE: u32 = 44_100;
+
+/// chip-8 timers count down at 60hz
+pub(crate) const TIMER_RATE: u32 = 60;
+
+/// time taken by the tone to fade in and out, avoids clicks when the buzzer toggles
+const RAMP_SECONDS: f32 = 0.005;
+
+/// A sink for the chip-8 buzzer
+pub trait Audio {
+    /// called once per timer tick with the buzzer state,
+    /// produces exactly 1/60 s of sound
+    fn buzz(&mut self, on: bool);
+
+    /// sound produced but not played yet,
+    /// None for outputs without a clock of their own
+    fn queued(&self) -> Option<Duration> {
+        None
+    }
+}
+
+arg_enum! {
+    #[derive(Debug, Clone, Copy)]
+    pub enum Waveform {
+        Square,
+        Sine,
+        Triangle,
+    }
+}
+
+/// Sound of the buzzer, volume goes from 0.0 to 1.0
+#[derive(Debug, Clone, Copy)]
+pub struct Tone {
+    pub waveform: Waveform,
+    pub frequency: f32,
+    pub volume: f32,
+}
+
+/// Generates the buzzer tone one sample at a time,
+/// the volume ramps towards its target so starting and stopping never clicks
+pub(crate) struct Oscillator {
+    tone: Tone,
+    sample_rate: u32,
+    phase: f32,
+    gain: f32,
+    ramp: f32,
+}
+
+impl Oscillator {
+
+    pub(crate) fn new(tone: Tone, sample_rate: u32) -> Oscillator {
+        let volume = tone.volume.clamp(0.0, 1.0);
+        Oscillator {
+            tone: Tone { volume, ..tone },
+            sample_rate,
+            phase: 0.0,
+            gain: 0.0,
+            ramp: volume / (sample_rate as f32 * RAMP_SECONDS),
+        }
+    }
+
+    pub(crate) fn next(&mut self, on: bool) -> f32 {
+        let target = if on { self.tone.volume } else { 0.0 };
+        if self.gain < target {
+            self.gain = (self.gain + self.ramp).min(target);
+        } else if self.gain > target {
+            self.gain = (self.gain - self.ramp).max(target);
+        }
+
+        let sample = match self.tone.waveform {
+            Waveform::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Sine => (2.0 * PI * self.phase).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+        };
+        self.phase = (self.phase + self.tone.frequency / self.sample_rate as f32).fract();
+        sample * self.gain
+    }
+}
+
+/// Silent audio, for machines without a sound device
+pub struct Null;
+
+impl Audio for Null {
+    fn buzz(&mut self, _on: bool) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TONE: Tone = Tone { waveform: Waveform::Square, frequency: 441.0, volume: 0.5 };
+
+    #[test]
+    fn ramps_in_and_out() {
+        let mut oscillator = Oscillator::new(TONE, SAMPLE_RATE);
+        let ramp = (SAMPLE_RATE as f32 * RAMP_SECONDS) as usize;
+        let rising: Vec<f32> = (0..ramp).map(|_| oscillator.next(true).abs()).collect();
+        assert!(rising[0] > 0.0 && rising[0] < 0.01, "starts near silence");
+        assert!(rising.windows(2).all(|pair| pair[1] >= pair[0]), "never jumps down while rising");
+        assert!((oscillator.next(true).abs() - 0.5).abs() < 1e-4, "reaches the volume");
+
+        let falling: Vec<f32> = (0..ramp).map(|_| oscillator.next(false).abs()).collect();
+        assert!(falling.windows(2).all(|pair| pair[1] <= pair[0]), "never jumps up while falling");
+        assert_eq!(oscillator.next(false), 0.0);
+    }
+
+    #[test]
+    fn square_wave() {
+        let mut oscillator = Oscillator::new(Tone { volume: 1.0, ..TONE }, SAMPLE_RATE);
+        for _ in 0..SAMPLE_RATE {
+            oscillator.next(true);
+        }
+        // 441 hz at 44100 hz is 100 samples a period, half of them high
+        let period: Vec<f32> = (0..100).map(|_| oscillator.next(true)).collect();
+        assert_eq!(period.iter().filter(|sample| **sample > 0.0).count(), 50);
+        assert!(period.iter().all(|sample| (sample.abs() - 1.0).abs() < 1e-4));
+    }
+
+    #[test]
+    fn volume_is_clamped() {
+        let mut oscillator = Oscillator::new(Tone { volume: 3.0, ..TONE }, SAMPLE_RATE);
+        assert!((0..SAMPLE_RATE).map(|_| oscillator.next(true)).all(|sample| sample.abs() <= 1.0));
+    }
+}
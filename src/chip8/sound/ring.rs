@@ -0,0 +1,69 @@
+/// Fixed capacity sample queue between the emulator, which produces one timer
+/// tick of samples at a time, and the audio thread consuming them
+pub(crate) struct Ring {
+    samples: Vec<f32>,
+    read: usize,
+    len: usize,
+}
+
+impl Ring {
+
+    pub(crate) fn new(capacity: usize) -> Ring {
+        Ring {
+            samples: vec![0.0; capacity],
+            read: 0,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// queues a sample, returns false and drops it when the ring is full
+    pub(crate) fn push(&mut self, sample: f32) -> bool {
+        if self.len == self.samples.len() {
+            return false;
+        }
+        let write = (self.read + self.len) % self.samples.len();
+        self.samples[write] = sample;
+        self.len += 1;
+        true
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<f32> {
+        if self.len == 0 {
+            return None;
+        }
+        let sample = self.samples[self.read];
+        self.read = (self.read + 1) % self.samples.len();
+        self.len -= 1;
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_around() {
+        let mut ring = Ring::new(3);
+        assert!(ring.push(1.0) && ring.push(2.0));
+        assert_eq!(ring.pop(), Some(1.0));
+        // the writes go past the end of the buffer and back to its start
+        assert!(ring.push(3.0) && ring.push(4.0));
+        assert_eq!(ring.len(), 3);
+        assert_eq!((ring.pop(), ring.pop(), ring.pop()), (Some(2.0), Some(3.0), Some(4.0)));
+        assert_eq!(ring.pop(), None);
+    }
+
+    #[test]
+    fn drops_when_full() {
+        let mut ring = Ring::new(2);
+        assert!(ring.push(1.0) && ring.push(2.0));
+        assert!(!ring.push(3.0));
+        assert_eq!(ring.len(), 2);
+        assert_eq!((ring.pop(), ring.pop(), ring.pop()), (Some(1.0), Some(2.0), None));
+    }
+}
@@ -0,0 +1,38 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use super::*;
+
+/// Writes the buzzer to a 16 bits mono wav file,
+/// every timer tick produces exactly 1/60 s of samples so the output is reproducible
+pub struct Wav {
+    writer: hound::WavWriter<BufWriter<File>>,
+    oscillator: Oscillator,
+}
+
+impl Audio for Wav {
+    fn buzz(&mut self, on: bool) {
+        for _ in 0..SAMPLE_RATE / TIMER_RATE {
+            let sample = self.oscillator.next(on) * f32::from(i16::MAX);
+            if let Err(e) = self.writer.write_sample(sample as i16) {
+                error!("couldn't write wav sample: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+pub fn new(path: &Path, tone: Tone) -> Result<Wav, String> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let writer = hound::WavWriter::create(path, spec).map_err(|e| e.to_string())?;
+    Ok(Wav {
+        writer,
+        oscillator: Oscillator::new(tone, SAMPLE_RATE),
+    })
+}
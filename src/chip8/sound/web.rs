@@ -0,0 +1,21 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use super::Audio;
+
+/// Buzzer state for the page, which plays it through a WebAudio oscillator
+pub struct Web {
+    on: Rc<Cell<bool>>,
+}
+
+impl Audio for Web {
+    fn buzz(&mut self, on: bool) {
+        self.on.set(on);
+    }
+}
+
+/// the sink and the buzzer state it updates once per host frame
+pub fn new() -> (Web, Rc<Cell<bool>>) {
+    let on = Rc::new(Cell::new(false));
+    (Web { on: on.clone() }, on)
+}
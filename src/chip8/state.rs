@@ -0,0 +1,86 @@
+use super::cpu::*;
+
+/// identifies save states, the last byte is the layout version
+const MAGIC: &[u8] = b"C8S\x03";
+const DISPLAY_SIZE: usize = (SCREEN_WIDTH * SCREEN_HEIGHT) as usize;
+
+/// bytes of a save state, the same whatever the machine is doing
+pub const SIZE: usize = MAGIC.len() + 16 + MEMORY_SIZE + 2 + 2 + 1 + 1 + STACK_SIZE * 2 + 1 + DISPLAY_SIZE + 8 + 8 + 8;
+
+/// Cursor over a save state
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+
+    fn take(&mut self, count: usize) -> &'a [u8] {
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        taken
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.take(1)[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes([self.u8(), self.u8()])
+    }
+
+    fn u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8));
+        u64::from_le_bytes(bytes)
+    }
+}
+
+impl Cpu {
+
+    /// registers, memory, timers, stack, screen, frame counters and RND generator, in SIZE bytes.
+    /// Any load of the state goes on with the same random numbers as the run it was saved from
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(SIZE);
+        state.extend_from_slice(MAGIC);
+        state.extend_from_slice(&self.v);
+        state.extend_from_slice(&self.memory);
+        state.extend_from_slice(&(self.i as u16).to_le_bytes());
+        state.extend_from_slice(&self.pc.to_le_bytes());
+        state.push(self.delay_timer);
+        state.push(self.sound_timer);
+        for address in &self.stack {
+            state.extend_from_slice(&address.to_le_bytes());
+        }
+        state.push(self.sp as u8);
+        state.extend_from_slice(&self.display);
+        state.extend_from_slice(&self.clock.frames.to_le_bytes());
+        state.extend_from_slice(&self.clock.instructions.to_le_bytes());
+        state.extend_from_slice(&self.rng.to_le_bytes());
+        state
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        if state.len() < SIZE || !state.starts_with(MAGIC) {
+            return Err("not a save state of this version".to_string());
+        }
+        let mut reader = Reader { bytes: &state[MAGIC.len()..] };
+        self.v.copy_from_slice(reader.take(16));
+        self.memory.copy_from_slice(reader.take(MEMORY_SIZE));
+        self.i = reader.u16() as usize % MEMORY_SIZE;
+        self.pc = reader.u16() % MEMORY_SIZE as u16;
+        self.delay_timer = reader.u8();
+        self.sound_timer = reader.u8();
+        for address in self.stack.iter_mut() {
+            *address = reader.u16();
+        }
+        self.sp = reader.u8() as usize % STACK_SIZE;
+        self.display.copy_from_slice(reader.take(DISPLAY_SIZE));
+        self.clock.frames = reader.u64();
+        self.clock.instructions = reader.u64();
+        self.rng = reader.u64();
+        self.quit = false;
+        self.fault = None;
+        self.display_redraw = true;
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use std::fs;
+use std::path::Path;
+
+/// A rom address and the source line it was assembled from
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub address: u16,
+    pub file: String,
+    pub line: u64,
+}
+
+/// Symbol map relating rom addresses to source lines.
+/// The file holds one `address file:line` entry per line, addresses in hex,
+/// blank lines and lines starting with # are ignored:
+///
+/// ```text
+/// 0x200 pong.8o:12
+/// 0x202 pong.8o:13
+/// ```
+#[derive(Debug, Default)]
+pub struct Symbols {
+    entries: Vec<Symbol>,
+}
+
+pub fn load(path: &Path) -> Result<Symbols, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("couldn't read symbols: {}", e))?;
+    let mut entries = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parse = || -> Option<Symbol> {
+            let mut fields = line.split_whitespace();
+            let address = fields.next()?;
+            let address = u16::from_str_radix(address.trim_start_matches("0x"), 16).ok()?;
+            let (file, line) = fields.next()?.rsplit_once(':')?;
+            Some(Symbol {
+                address,
+                file: file.to_string(),
+                line: line.parse().ok()?,
+            })
+        };
+        match parse() {
+            Some(symbol) => entries.push(symbol),
+            None => return Err(format!("bad symbol at {}:{}", path.display(), n + 1)),
+        }
+    }
+    Ok(Symbols { entries })
+}
+
+/// source files are matched by file name, editors send absolute paths
+fn same_file(a: &str, b: &str) -> bool {
+    Path::new(a).file_name() == Path::new(b).file_name()
+}
+
+impl Symbols {
+
+    /// the source line an address was assembled from
+    pub fn line(&self, address: u16) -> Option<&Symbol> {
+        self.entries.iter().find(|s| s.address == address)
+    }
+
+    /// addresses of the first mapped line at or after `line`, with that line
+    pub fn addresses(&self, file: &str, line: u64) -> Option<(u64, Vec<u16>)> {
+        let found = self.entries.iter()
+            .filter(|s| same_file(&s.file, file) && s.line >= line)
+            .map(|s| s.line)
+            .min()?;
+        let addresses = self.entries.iter()
+            .filter(|s| same_file(&s.file, file) && s.line == found)
+            .map(|s| s.address)
+            .collect();
+        Some((found, addresses))
+    }
+}
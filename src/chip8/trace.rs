@@ -0,0 +1,411 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use serde_json::json;
+use structopt::clap::arg_enum;
+
+use super::cpu::*;
+use super::disasm::disassemble;
+
+arg_enum! {
+    /// Text: one line per instruction, `cycle frame pc opcode disassembly | changes`
+    /// Json: one json record per line
+    #[derive(Debug, Clone, Copy)]
+    pub enum Format {
+        Text,
+        Json,
+    }
+}
+
+/// Execution trace sink, records every executed instruction that passes the filters
+pub struct Trace {
+    writer: Box<dyn Write>,
+    format: Format,
+    pcs: Option<RangeInclusive<u64>>,
+    frames: Option<RangeInclusive<u64>>,
+}
+
+/// The state an instruction may change
+#[derive(Clone, PartialEq)]
+struct Registers {
+    v: [u8; 16],
+    i: usize,
+    sp: usize,
+    delay_timer: u8,
+    sound_timer: u8,
+    stack: [u16; STACK_SIZE],
+}
+
+/// creates the trace file, pcs and frames limit what is recorded
+pub fn new(path: &Path, format: Format, pcs: Option<RangeInclusive<u64>>, frames: Option<RangeInclusive<u64>>) -> Result<Trace, String> {
+    let file = File::create(path).map_err(|e| format!("couldn't create the trace: {}", e))?;
+    Ok(Trace {
+        writer: Box::new(BufWriter::new(file)),
+        format,
+        pcs,
+        frames,
+    })
+}
+
+/// parses an inclusive range `start-end`, bounds in decimal or 0x prefixed hex
+pub fn parse_range(text: &str) -> Result<RangeInclusive<u64>, String> {
+    let number = |text: &str| match text.trim().strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.trim().parse(),
+    }.map_err(|e| format!("bad range {}: {}", text, e));
+    match text.split_once('-') {
+        Some((start, end)) => Ok(number(start)?..=number(end)?),
+        None => number(text).map(|n| n..=n),
+    }
+}
+
+impl Registers {
+
+    fn of(cpu: &Cpu) -> Registers {
+        Registers {
+            v: cpu.v,
+            i: cpu.i,
+            sp: cpu.sp,
+            delay_timer: cpu.delay_timer,
+            sound_timer: cpu.sound_timer,
+            stack: cpu.stack,
+        }
+    }
+
+    /// names and new values of what changed, in a fixed order
+    fn changes(&self, after: &Registers) -> Vec<(String, String)> {
+        let mut changes = Vec::new();
+        for x in 0..self.v.len() {
+            if self.v[x] != after.v[x] {
+                changes.push((format!("V{:X}", x), format!("{:02X}", after.v[x])));
+            }
+        }
+        if self.i != after.i {
+            changes.push(("I".to_string(), format!("{:03X}", after.i)));
+        }
+        if self.sp != after.sp {
+            changes.push(("SP".to_string(), format!("{:X}", after.sp)));
+        }
+        if self.delay_timer != after.delay_timer {
+            changes.push(("DT".to_string(), format!("{:02X}", after.delay_timer)));
+        }
+        if self.sound_timer != after.sound_timer {
+            changes.push(("ST".to_string(), format!("{:02X}", after.sound_timer)));
+        }
+        for s in 0..self.stack.len() {
+            if self.stack[s] != after.stack[s] {
+                changes.push((format!("S[{:X}]", s), format!("{:03X}", after.stack[s])));
+            }
+        }
+        changes
+    }
+}
+
+impl Trace {
+
+    fn wanted(&self, cpu: &Cpu) -> bool {
+        self.pcs.as_ref().is_none_or(|pcs| pcs.contains(&(cpu.pc as u64))) &&
+            self.frames.as_ref().is_none_or(|frames| frames.contains(&cpu.clock.frames))
+    }
+
+    /// executes the instruction under the pc, recording it if wanted
+    pub(crate) fn step(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        if !self.wanted(cpu) {
+            cpu.execute();
+            return Ok(());
+        }
+
+        let (cycle, frame, pc) = (cpu.clock.instructions, cpu.clock.frames, cpu.pc);
+        let opcode = u16::from_be_bytes([cpu.memory[pc as usize % MEMORY_SIZE], cpu.memory[(pc as usize + 1) % MEMORY_SIZE]]);
+        let before = Registers::of(cpu);
+        let memory = cpu.memory;
+        cpu.execute();
+
+        let mut changes = before.changes(&Registers::of(cpu));
+        if memory[..] != cpu.memory[..] {
+            for (address, (old, new)) in memory.iter().zip(cpu.memory.iter()).enumerate() {
+                if old != new {
+                    changes.push((format!("M[{:03X}]", address), format!("{:02X}", new)));
+                }
+            }
+        }
+
+        match self.format {
+            Format::Text => {
+                write!(self.writer, "{:08} {:08} {:03X} {:04X} {:<16} |", cycle, frame, pc, opcode, disassemble(opcode))?;
+                for (name, value) in changes {
+                    write!(self.writer, " {}={}", name, value)?;
+                }
+                writeln!(self.writer)
+            },
+            Format::Json => {
+                let changes: serde_json::Map<String, serde_json::Value> = changes.into_iter()
+                    .map(|(name, value)| (name, json!(value)))
+                    .collect();
+                writeln!(self.writer, "{}", json!({
+                    "cycle": cycle,
+                    "frame": frame,
+                    "pc": format!("{:03X}", pc),
+                    "opcode": format!("{:04X}", opcode),
+                    "asm": disassemble(opcode),
+                    "changes": changes,
+                }))
+            },
+        }
+    }
+}
+
+impl Cpu {
+
+    /// executes one instruction through the trace, a failing trace is dropped
+    pub(crate) fn traced_step(&mut self, mut trace: Trace) {
+        match trace.step(self) {
+            Ok(()) => self.trace = Some(trace),
+            Err(e) => error!("couldn't write the trace, tracing stopped: {}", e),
+        }
+    }
+}
+
+/// One instruction read back from a trace file, text or json
+struct Record {
+    cycle: u64,
+    frame: u64,
+    pc: String,
+    opcode: String,
+    asm: String,
+    changes: Vec<(String, String)>,
+}
+
+impl Record {
+
+    fn parse(line: &str) -> Option<Record> {
+        if line.starts_with('{') {
+            let record: serde_json::Value = serde_json::from_str(line).ok()?;
+            let mut changes: Vec<(String, String)> = record["changes"].as_object()?.iter()
+                .map(|(name, value)| (name.clone(), value.as_str().unwrap_or_default().to_string()))
+                .collect();
+            changes.sort();
+            return Some(Record {
+                cycle: record["cycle"].as_u64()?,
+                frame: record["frame"].as_u64()?,
+                pc: record["pc"].as_str()?.to_string(),
+                opcode: record["opcode"].as_str()?.to_string(),
+                asm: record["asm"].as_str()?.to_string(),
+                changes,
+            });
+        }
+
+        let (instruction, changes) = line.split_once('|')?;
+        let mut fields = instruction.split_whitespace();
+        let cycle = fields.next()?.parse().ok()?;
+        let frame = fields.next()?.parse().ok()?;
+        let pc = fields.next()?.to_string();
+        let opcode = fields.next()?.to_string();
+        let asm = fields.collect::<Vec<_>>().join(" ");
+        let mut changes: Vec<(String, String)> = changes.split_whitespace()
+            .filter_map(|change| change.split_once('='))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        changes.sort();
+        Some(Record { cycle, frame, pc, opcode, asm, changes })
+    }
+
+    fn same(&self, other: &Record) -> bool {
+        self.pc == other.pc && self.opcode == other.opcode && self.changes == other.changes
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:08} {:>3} {} {:<16} |", self.cycle, self.pc, self.opcode, self.asm)?;
+        for (name, value) in &self.changes {
+            write!(f, " {}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<Record>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+    let mut records = Vec::new();
+    for (n, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        match Record::parse(line) {
+            Some(record) => records.push(record),
+            None => return Err(format!("{}:{}: not a trace record", path.display(), n + 1)),
+        }
+    }
+    Ok(records)
+}
+
+/// Compares two traces instruction by instruction, aligned on the cycle number,
+/// and reports the first divergence with `context` instructions around it,
+/// a cycle only one of them has or one ending before the other is one too.
+/// Returns true when the traces diverge.
+pub fn diff(a: &Path, b: &Path, context: usize) -> Result<bool, String> {
+    let (a, b) = (read(a)?, read(b)?);
+
+    // machine state rebuilt from the changes so far, to tell registers apart at the divergence
+    let mut state_a = BTreeMap::new();
+    let mut state_b = BTreeMap::new();
+    let (mut x, mut y) = (0, 0);
+    let mut aligned: Vec<(usize, usize)> = Vec::new();
+    while x < a.len() && y < b.len() {
+        if a[x].cycle != b[y].cycle {
+            // the same filters on both sides keep the same cycles, unless the runs went apart
+            let (name, record) = if a[x].cycle < b[y].cycle { ("a", &a[x]) } else { ("b", &b[y]) };
+            println!("traces diverge at cycle {} (frame {}), only {} has it", record.cycle, record.frame, name);
+            println!();
+            println!("context:");
+            for (p, _) in aligned.iter().skip(aligned.len().saturating_sub(context)) {
+                println!("    {}", a[*p]);
+            }
+            println!("> {} {}", name, record);
+            return Ok(true);
+        }
+        if !a[x].same(&b[y]) {
+            report(&a, &b, &aligned, (x, y), &state_a, &state_b, context);
+            return Ok(true);
+        }
+        state_a.extend(a[x].changes.iter().cloned());
+        state_b.extend(b[y].changes.iter().cloned());
+        aligned.push((x, y));
+        x += 1;
+        y += 1;
+    }
+
+    if a.len() - x != b.len() - y {
+        let (longer, name) = if a.len() - x > b.len() - y { (&a[x..], "a") } else { (&b[y..], "b") };
+        println!("traces agree on {} instructions, then only {} goes on:", aligned.len(), name);
+        for record in longer.iter().take(context) {
+            println!("  {}", record);
+        }
+        return Ok(true);
+    }
+    println!("traces agree on {} instructions", aligned.len());
+    Ok(false)
+}
+
+fn report(a: &[Record], b: &[Record], aligned: &[(usize, usize)], (x, y): (usize, usize),
+          state_a: &BTreeMap<String, String>, state_b: &BTreeMap<String, String>, context: usize) {
+    println!("traces diverge at cycle {} (frame {})", a[x].cycle, a[x].frame);
+    println!();
+    println!("context:");
+    for (p, q) in aligned.iter().skip(aligned.len().saturating_sub(context)) {
+        println!("    {}", a[*p]);
+        if !a[*p].same(&b[*q]) {
+            println!("  b {}", b[*q]);
+        }
+    }
+    println!("> a {}", a[x]);
+    println!("> b {}", b[y]);
+    for offset in 1..=context {
+        match (a.get(x + offset), b.get(y + offset)) {
+            (None, None) => break,
+            (record_a, record_b) => {
+                if let Some(record) = record_a {
+                    println!("  a {}", record);
+                }
+                if let Some(record) = record_b {
+                    println!("  b {}", record);
+                }
+            },
+        }
+    }
+    println!();
+
+    if a[x].pc != b[y].pc {
+        match aligned.last() {
+            Some((p, _)) => println!("responsible instruction: {} {} {}, the pc went to {} in a and {} in b",
+                a[*p].pc, a[*p].opcode, a[*p].asm, a[x].pc, b[y].pc),
+            None => println!("the traces start at different addresses: {} in a and {} in b", a[x].pc, b[y].pc),
+        }
+    } else {
+        println!("responsible instruction: {} {} {}", a[x].pc, a[x].opcode, a[x].asm);
+    }
+
+    // registers as they are after the diverging instruction
+    let mut after_a = state_a.clone();
+    let mut after_b = state_b.clone();
+    after_a.extend(a[x].changes.iter().cloned());
+    after_b.extend(b[y].changes.iter().cloned());
+    let names: BTreeSet<&String> = after_a.keys().chain(after_b.keys()).collect();
+    let mut differences = names.into_iter()
+        .filter(|name| after_a.get(*name) != after_b.get(*name))
+        .peekable();
+    if differences.peek().is_some() {
+        println!("differences after it:");
+        for name in differences {
+            println!("  {:<7} a={:<4} b={}", name,
+                after_a.get(name).map(String::as_str).unwrap_or("?"),
+                after_b.get(name).map(String::as_str).unwrap_or("?"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::frontend::headless::Headless;
+    use crate::chip8::sound;
+
+    /// LD V0, 5; LD V1, 7; ADD V0, V1; JP FFE
+    const ROM: [u8; 8] = [0x60, 0x05, 0x61, 0x07, 0x80, 0x14, 0x1F, 0xFE];
+
+    /// the lines traced over `frames` frames of the rom
+    fn traced(name: &str, format: Format, pcs: Option<RangeInclusive<u64>>, frames: Option<RangeInclusive<u64>>, count: u64) -> Vec<String> {
+        let path = std::env::temp_dir().join(format!("chip8-{}-trace-{}", std::process::id(), name));
+        let mut cpu = initialize(Box::new(Headless), Box::new(sound::Null));
+        cpu.bootup(ROM.to_vec());
+        cpu.trace = Some(new(&path, format, pcs, frames).unwrap());
+        for _ in 0..count {
+            cpu.frame();
+        }
+        cpu.trace = None;
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn text() {
+        let lines = traced("text", Format::Text, None, None, 4);
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], format!("00000001 00000000 200 6005 {:<16} | V0=05", disassemble(0x6005)));
+        assert!(lines[2].starts_with("00000003 00000002 204 8014"), "{}", lines[2]);
+        assert!(lines[2].ends_with("| V0=0C"), "{}", lines[2]);
+        assert!(lines[3].ends_with("|"), "a jump changes nothing but the pc");
+    }
+
+    #[test]
+    fn json() {
+        let lines = traced("json", Format::Json, None, None, 3);
+        let record: serde_json::Value = serde_json::from_str(&lines[1]).unwrap();
+        assert_eq!(record["cycle"], 2);
+        assert_eq!(record["frame"], 1);
+        assert_eq!(record["pc"], "202");
+        assert_eq!(record["opcode"], "6107");
+        assert_eq!(record["changes"], json!({ "V1": "07" }));
+    }
+
+    #[test]
+    fn filters() {
+        let lines = traced("pcs", Format::Text, Some(0x202..=0x204), None, 4);
+        let pcs: Vec<&str> = lines.iter().map(|line| &line[18..21]).collect();
+        assert_eq!(pcs, ["202", "204"]);
+
+        let lines = traced("frames", Format::Text, None, Some(2..=3), 6);
+        let frames: Vec<&str> = lines.iter().map(|line| &line[9..17]).collect();
+        assert_eq!(frames, ["00000002", "00000003"]);
+    }
+
+    #[test]
+    fn pc_past_memory() {
+        // JP FFE runs the empty word at FFE, then the pc reads 1000 onwards from the start of memory
+        let lines = traced("wrap", Format::Text, Some(0x1000..=0x1000), None, 6);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0][18..].starts_with("1000 F090"), "{}", lines[0]);
+    }
+}
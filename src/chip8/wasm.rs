@@ -0,0 +1,85 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+
+use super::cpu::{self, *};
+use super::frontend::canvas;
+use super::sound::web;
+
+/// The emulator as seen from javascript, see web/index.html.
+/// The page calls run_frame 60 times a second, copies the framebuffer into a canvas
+/// and turns its oscillator on and off with buzzing.
+#[wasm_bindgen]
+pub struct Emulator {
+    cpu: Cpu,
+    pixels: Rc<RefCell<Vec<u8>>>,
+    buzzing: Rc<Cell<bool>>,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Emulator {
+        let (canvas, pixels) = canvas::new();
+        let (audio, buzzing) = web::new();
+        Emulator {
+            cpu: cpu::initialize(Box::new(canvas), Box::new(audio)),
+            pixels,
+            buzzing,
+        }
+    }
+
+    /// resets the machine and boots the rom
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsValue> {
+        if rom.len() > MEMORY_SIZE - self.cpu.load_address as usize {
+            return Err(JsValue::from_str(&format!("rom too big, {} bytes", rom.len())));
+        }
+        self.cpu.reset();
+        self.cpu.bootup(rom.to_vec());
+        Ok(())
+    }
+
+    /// emulates the frames of 1/60 s due at the current speed and redraws the framebuffer,
+    /// false once the machine stopped on an instruction it doesn't know
+    pub fn run_frame(&mut self) -> bool {
+        self.cpu.host_frame(&mut |_| false);
+        !self.cpu.quit
+    }
+
+    pub fn key_down(&mut self, key: usize) {
+        self.cpu.keyboard[key & 0xF] = true;
+    }
+
+    pub fn key_up(&mut self, key: usize) {
+        self.cpu.keyboard[key & 0xF] = false;
+    }
+
+    /// rgba pixels of the screen, width * height * 4 bytes that stay at this address
+    pub fn framebuffer(&self) -> *const u8 {
+        self.pixels.borrow().as_ptr()
+    }
+
+    pub fn width(&self) -> u32 {
+        SCREEN_WIDTH
+    }
+
+    pub fn height(&self) -> u32 {
+        SCREEN_HEIGHT
+    }
+
+    pub fn buzzing(&self) -> bool {
+        self.buzzing.get()
+    }
+
+    /// emulated frames per call of run_frame
+    pub fn set_speed(&mut self, speed: f32) {
+        self.cpu.clock.speed = speed.max(0.0);
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Emulator {
+        Emulator::new()
+    }
+}
@@ -0,0 +1,368 @@
+//! Gym-style environment: an agent picks the keys held down, the machine runs a few frames
+//! and the agent gets the screen, a reward and whether the episode is over.
+//! Rewards come from where games keep their score and lives, see roms/rewards.txt:
+//!
+//! ```text
+//! let rom = rom::load(Path::new("roms/BRIX"))?;
+//! let game = environment::bundled().remove(&rom::crc32(&rom)).unwrap();
+//! let mut env = Environment::new(rom, game, Settings::default());
+//! let mut observation = env.reset();
+//! loop {
+//!     let (next, reward, done) = env.step(keys(&[0x4]));
+//!     ...
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::chip8::cpu::{self, *};
+use crate::chip8::frontend::headless::Headless;
+use crate::chip8::sound;
+
+/// keys held down during a step, bit n for key n
+pub type Action = u16;
+
+/// the screen, one byte of 0 or 1 per pixel, row after row
+pub type Observation = [u8; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize];
+
+/// Where a game keeps a number
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Memory(u16),
+    Register(usize),
+}
+
+/// What rewards and ends the episodes of a game
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Game {
+    pub name: String,
+    /// rewarded by how much it goes up, several sources are the decimal digits of one number
+    pub score: Vec<Source>,
+    /// punished by how much it goes up, e.g. the score of the other player
+    pub penalty: Vec<Source>,
+    /// done once it drops to 0
+    pub lives: Vec<Source>,
+    /// done once the score or the penalty reaches it
+    pub goal: Option<u32>,
+    /// done once the pc gets there, for games idling in a loop when over
+    pub end: Option<u16>,
+}
+
+/// Reward file, one section per rom keyed by the crc32 of the rom like cheat files,
+/// then one `field: value` per line. Numbers are V0 to VF or hex addresses,
+/// blank lines and lines starting with # are ignored:
+///
+/// ```text
+/// [AAA44D0B] BRIX
+/// score: 314 315 316
+/// lives: VE
+/// end: 2DE
+/// ```
+pub type Database = HashMap<u32, Game>;
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// frames run per step with the same keys
+    pub frame_skip: u32,
+    /// chance of a frame keeping the keys of the previous one instead of the new action
+    pub sticky: f64,
+    /// episodes stop there even if the game isn't over
+    pub max_frames: Option<u64>,
+    /// seeds the sticky actions and the RND of every episode
+    pub seed: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            frame_skip: 4,
+            sticky: 0.0,
+            max_frames: None,
+            seed: 0,
+        }
+    }
+}
+
+pub struct Environment {
+    cpu: Cpu,
+    rom: Vec<u8>,
+    game: Game,
+    settings: Settings,
+    rng: StdRng,
+    /// keys held down in the last frame
+    held: Action,
+    score: u32,
+    penalty: u32,
+    lives: u32,
+}
+
+/// the action holding these keys down
+pub fn keys(keys: &[usize]) -> Action {
+    keys.iter().fold(0, |action, key| action | 1 << (key & 0xF))
+}
+
+fn source(text: &str) -> Option<Source> {
+    if let Some(register) = text.strip_prefix('V').or_else(|| text.strip_prefix('v')) {
+        return usize::from_str_radix(register, 16).ok().filter(|r| *r < 16).map(Source::Register);
+    }
+    u16::from_str_radix(text.trim_start_matches("0x"), 16).ok().filter(|a| (*a as usize) < MEMORY_SIZE).map(Source::Memory)
+}
+
+fn parse(path: &str, text: &str) -> Result<Database, String> {
+    let mut database = Database::new();
+    let mut rom = None;
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let bad = || format!("bad reward at {}:{}", path, n + 1);
+        if let Some(section) = line.strip_prefix('[') {
+            let (crc, name) = section.split_once(']').ok_or_else(bad)?;
+            let crc = u32::from_str_radix(crc.trim(), 16).map_err(|_| bad())?;
+            database.insert(crc, Game { name: name.trim().to_string(), ..Game::default() });
+            rom = Some(crc);
+            continue;
+        }
+        let game = rom.and_then(|crc| database.get_mut(&crc)).ok_or_else(bad)?;
+        let (field, value) = line.split_once(':').ok_or_else(bad)?;
+        let sources = || value.split_whitespace().map(source).collect::<Option<Vec<Source>>>().ok_or_else(bad);
+        match field.trim() {
+            "score" => game.score = sources()?,
+            "penalty" => game.penalty = sources()?,
+            "lives" => game.lives = sources()?,
+            "goal" => game.goal = Some(value.trim().parse().map_err(|_| bad())?),
+            "end" => game.end = Some(u16::from_str_radix(value.trim(), 16).map_err(|_| bad())?),
+            _ => return Err(bad()),
+        }
+    }
+    Ok(database)
+}
+
+pub fn load(path: &Path) -> Result<Database, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("couldn't read rewards: {}", e))?;
+    parse(&path.display().to_string(), &text)
+}
+
+/// rewards of the bundled games
+pub fn bundled() -> Database {
+    parse("roms/rewards.txt", include_str!("../roms/rewards.txt")).expect("bundled rewards")
+}
+
+impl Environment {
+
+    pub fn new(rom: Vec<u8>, game: Game, settings: Settings) -> Environment {
+        let rng = StdRng::seed_from_u64(settings.seed);
+        Environment {
+            cpu: cpu::initialize(Box::new(Headless), Box::new(sound::Null)),
+            rom,
+            game,
+            settings,
+            rng,
+            held: 0,
+            score: 0,
+            penalty: 0,
+            lives: 0,
+        }
+    }
+
+    /// boots the rom again for a new episode
+    pub fn reset(&mut self) -> Observation {
+        self.cpu.reset();
+        self.cpu.clock.frames = 0;
+        self.cpu.reseed(self.rng.gen());
+        self.cpu.bootup(self.rom.clone());
+        self.held = 0;
+        self.score = self.read(&self.game.score);
+        self.penalty = self.read(&self.game.penalty);
+        self.lives = self.read(&self.game.lives);
+        self.cpu.display
+    }
+
+    /// holds the keys down for frame_skip frames,
+    /// the reward is the score gained minus the penalty over these frames
+    pub fn step(&mut self, action: Action) -> (Observation, f64, bool) {
+        for _ in 0..self.settings.frame_skip {
+            if self.settings.sticky <= 0.0 || !self.rng.gen_bool(self.settings.sticky.min(1.0)) {
+                self.held = action;
+            }
+            for (key, down) in self.cpu.keyboard.iter_mut().enumerate() {
+                *down = self.held & 1 << key != 0;
+            }
+            self.cpu.frame();
+            if self.cpu.quit || self.game.end == Some(self.cpu.pc) {
+                break;
+            }
+        }
+
+        let (score, penalty, lives) = (self.read(&self.game.score), self.read(&self.game.penalty), self.read(&self.game.lives));
+        let reward = score as f64 - self.score as f64 - (penalty as f64 - self.penalty as f64);
+        let done = self.cpu.quit
+            || self.game.end == Some(self.cpu.pc)
+            || (!self.game.lives.is_empty() && self.lives > 0 && lives == 0)
+            || self.game.goal.is_some_and(|goal| score >= goal || penalty >= goal)
+            || self.settings.max_frames.is_some_and(|max| self.cpu.clock.frames >= max);
+        self.score = score;
+        self.penalty = penalty;
+        self.lives = lives;
+        (self.cpu.display, reward, done)
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// frames run since the last reset
+    pub fn frames(&self) -> u64 {
+        self.cpu.clock.frames
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.cpu.memory
+    }
+
+    /// the number the sources hold, their decimal digits when there are several
+    fn read(&self, sources: &[Source]) -> u32 {
+        let value = |source: &Source| match *source {
+            Source::Memory(address) => self.cpu.memory[address as usize],
+            Source::Register(register) => self.cpu.v[register],
+        } as u32;
+        match sources {
+            [source] => value(source),
+            digits => digits.iter().fold(0, |number, digit| number * 10 + value(digit)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::rom::crc32;
+
+    /// LD V1, 1; LD V2, 2; LD V3, 5; JP 206
+    const SCORING: [u8; 8] = [0x61, 0x01, 0x62, 0x02, 0x63, 0x05, 0x12, 0x06];
+
+    fn environment(rom: &[u8], game: Game, settings: Settings) -> Environment {
+        let mut env = Environment::new(rom.to_vec(), game, settings);
+        env.reset();
+        env
+    }
+
+    #[test]
+    fn rewards_file() {
+        let database = parse("rewards", "
+            # two players
+            [0000BEEF] Game
+            score: 314 0x315 V3
+            penalty: vE
+            lives: 2F0
+            goal: 20
+            end: 2DE
+        ").unwrap();
+        assert_eq!(database[&0xBEEF], Game {
+            name: "Game".to_string(),
+            score: vec![Source::Memory(0x314), Source::Memory(0x315), Source::Register(3)],
+            penalty: vec![Source::Register(0xE)],
+            lives: vec![Source::Memory(0x2F0)],
+            goal: Some(20),
+            end: Some(0x2DE),
+        });
+
+        assert!(parse("rewards", "score: 314").unwrap_err().contains("rewards:1"), "a field before any section");
+        assert!(parse("rewards", "[BEEF]\nscore: 1000").unwrap_err().contains("rewards:2"), "past the end of memory");
+        assert!(parse("rewards", "[BEEF]\nscore: VG").is_err());
+        assert!(parse("rewards", "[BEEF]\nspeed: 2").is_err());
+        assert!(parse("rewards", "[BEEF]\ngoal: -1").is_err());
+        assert!(parse("rewards", "[BEEF\n").is_err());
+    }
+
+    #[test]
+    fn score_and_penalty() {
+        let game = Game { score: vec![Source::Register(1), Source::Register(2)], penalty: vec![Source::Register(3)], ..Game::default() };
+        let mut env = environment(&SCORING, game.clone(), Settings::default());
+        assert_eq!(env.step(0).1, 12.0 - 5.0, "the digits of 12 less 5");
+        assert_eq!(env.step(0).1, 0.0, "nothing changed since");
+
+        let mut env = environment(&SCORING, Game { goal: Some(12), ..game.clone() }, Settings::default());
+        assert!(env.step(0).2, "the score reached the goal");
+        let mut env = environment(&SCORING, Game { goal: Some(5), score: vec![], ..game }, Settings::default());
+        assert!(env.step(0).2, "the penalty reached the goal");
+    }
+
+    #[test]
+    fn end_and_lives() {
+        let settings = Settings { frame_skip: 10, ..Settings::default() };
+        let mut env = environment(&SCORING, Game { end: Some(0x206), ..Game::default() }, settings);
+        assert!(env.step(0).2);
+        assert_eq!(env.frames(), 3, "the step stopped at the end");
+
+        // LD V4, 2; ADD V4, FF; ADD V4, FF; JP 206
+        let rom = [0x64, 0x02, 0x74, 0xFF, 0x74, 0xFF, 0x12, 0x06];
+        let settings = Settings { frame_skip: 1, ..Settings::default() };
+        let mut env = environment(&rom, Game { lives: vec![Source::Register(4)], ..Game::default() }, settings);
+        let done: Vec<bool> = (0..3).map(|_| env.step(0).2).collect();
+        assert_eq!(done, [false, false, true], "done once the lives drop to 0, not while they start at 0");
+    }
+
+    #[test]
+    fn frame_skip_and_max_frames() {
+        let settings = Settings { frame_skip: 3, max_frames: Some(7), ..Settings::default() };
+        let mut env = environment(&SCORING, Game::default(), settings);
+        assert!(!env.step(0).2);
+        assert_eq!(env.frames(), 3);
+        assert!(!env.step(0).2);
+        assert!(env.step(0).2);
+        assert_eq!(env.frames(), 9);
+        env.reset();
+        assert_eq!(env.frames(), 0);
+    }
+
+    #[test]
+    fn sticky_actions() {
+        // key 4 held down or not after each single frame step, pressing it every other step
+        let held = |seed: u64| -> Vec<bool> {
+            let settings = Settings { frame_skip: 1, sticky: 0.5, seed, ..Settings::default() };
+            let mut env = environment(&SCORING, Game::default(), settings);
+            (0..32).map(|n| {
+                env.step(if n % 2 == 0 { keys(&[4]) } else { 0 });
+                env.cpu.keyboard[4]
+            }).collect()
+        };
+        let pressed: Vec<bool> = (0..32).map(|n| n % 2 == 0).collect();
+        assert_eq!(held(7), held(7), "the same seed sticks the same frames");
+        assert_ne!(held(7), pressed, "some frames kept the previous keys");
+        assert!(held(7).iter().zip(&pressed).any(|(held, pressed)| held == pressed));
+
+        let settings = Settings { frame_skip: 1, sticky: 1.0, ..Settings::default() };
+        let mut env = environment(&SCORING, Game::default(), settings);
+        env.step(keys(&[4]));
+        assert!(!env.cpu.keyboard[4], "always sticky never lets the keys change");
+    }
+
+    #[test]
+    fn bundled_game() {
+        let rom = include_bytes!("../roms/BRIX").to_vec();
+        let game = bundled().remove(&crc32(&rom)).unwrap();
+        assert_eq!(game.name, "BRIX");
+        let mut env = Environment::new(rom, game, Settings { max_frames: Some(400), ..Settings::default() });
+        env.reset();
+        let mut done = false;
+        let mut lives = 0;
+        let mut steps = 0;
+        while !done {
+            let (_, reward, over) = env.step(keys(&[4]));
+            assert!(reward >= 0.0, "brix has no penalty");
+            done = over;
+            steps += 1;
+            lives = lives.max(env.lives);
+        }
+        assert_eq!(steps, 100, "the episode lasts until max_frames");
+        assert!(lives > 0, "the lives are kept in VE");
+        assert!(env.cpu.display.iter().any(|pixel| *pixel != 0));
+    }
+}
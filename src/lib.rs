@@ -0,0 +1,17 @@
+#[macro_use]
+extern crate log;
+
+pub mod chip8;
+
+// not in chip8 since the binary builds that tree as well,
+// where the exports would clash and the rest would go unused
+pub mod environment;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "libretro")]
+pub mod libretro;
+#[cfg(feature = "python")]
+pub mod python;
+
+#[cfg(target_arch = "wasm32")]
+pub use crate::chip8::wasm::Emulator;
@@ -0,0 +1,477 @@
+//! libretro core, built with `cargo build --release --features libretro`,
+//! see libretro/harness.c for a minimal front-end loading it.
+//! ref: https://github.com/libretro/RetroArch/blob/master/libretro-common/include/libretro.h
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_void, CStr};
+use std::rc::Rc;
+use std::sync::Mutex;
+
+use crate::chip8::cheat;
+use crate::chip8::cpu::{self, *};
+use crate::chip8::frontend::{Command, Frontend};
+use crate::chip8::osd::Overlay;
+use crate::chip8::sound::{Audio, Oscillator, Tone, Waveform, SAMPLE_RATE, TIMER_RATE};
+use crate::chip8::state;
+
+const RETRO_API_VERSION: u32 = 1;
+const RETRO_DEVICE_JOYPAD: u32 = 1;
+const RETRO_ENVIRONMENT_GET_VARIABLE: u32 = 15;
+const RETRO_ENVIRONMENT_SET_VARIABLES: u32 = 16;
+const RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE: u32 = 17;
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: u32 = 10;
+const RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS: u32 = 11;
+const RETRO_PIXEL_FORMAT_XRGB8888: u32 = 1;
+const RETRO_REGION_NTSC: u32 = 0;
+const RETRO_MEMORY_SYSTEM_RAM: u32 = 2;
+
+/// colours of the lit and unlit pixels, xrgb
+const FOREGROUND: u32 = 0x00FF_FFFF;
+const BACKGROUND: u32 = 0x0000_0000;
+
+/// RetroPad button ids and the chip-8 keys they press,
+/// the d-pad and A sit on 2 4 6 8 and 5, the keys most games move and fire with
+const BUTTONS: [(u32, usize, &[u8]); KEYBOARD_SIZE] = [
+    (4, 0x2, b"Up (2)\0"),
+    (5, 0x8, b"Down (8)\0"),
+    (6, 0x4, b"Left (4)\0"),
+    (7, 0x6, b"Right (6)\0"),
+    (8, 0x5, b"A (5)\0"),
+    (0, 0x0, b"B (0)\0"),
+    (9, 0x1, b"X (1)\0"),
+    (1, 0x3, b"Y (3)\0"),
+    (10, 0x7, b"L (7)\0"),
+    (11, 0x9, b"R (9)\0"),
+    (12, 0xA, b"L2 (A)\0"),
+    (13, 0xB, b"R2 (B)\0"),
+    (14, 0xC, b"L3 (C)\0"),
+    (15, 0xD, b"R3 (D)\0"),
+    (2, 0xE, b"Select (E)\0"),
+    (3, 0xF, b"Start (F)\0"),
+];
+
+const SPEED_KEY: &[u8] = b"chip8_speed\0";
+const SPEED_VALUES: &[u8] = b"Emulated frames per frame; 1|2|4|8|16|32|64\0";
+
+#[repr(C)]
+pub struct SystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+pub struct GameGeometry {
+    base_width: u32,
+    base_height: u32,
+    max_width: u32,
+    max_height: u32,
+    aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct SystemTiming {
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct SystemAvInfo {
+    geometry: GameGeometry,
+    timing: SystemTiming,
+}
+
+#[repr(C)]
+pub struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[repr(C)]
+struct Variable {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+#[repr(C)]
+struct InputDescriptor {
+    port: u32,
+    device: u32,
+    index: u32,
+    id: u32,
+    description: *const c_char,
+}
+
+type EnvironmentFn = extern "C" fn(cmd: u32, data: *mut c_void) -> bool;
+type VideoRefreshFn = extern "C" fn(data: *const c_void, width: u32, height: u32, pitch: usize);
+type AudioSampleFn = extern "C" fn(left: i16, right: i16);
+type AudioSampleBatchFn = extern "C" fn(data: *const i16, frames: usize) -> usize;
+type InputPollFn = extern "C" fn();
+type InputStateFn = extern "C" fn(port: u32, device: u32, index: u32, id: u32) -> i16;
+
+/// Callbacks handed over by the front-end
+#[derive(Default, Clone, Copy)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+fn callbacks() -> Callbacks {
+    *CALLBACKS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn environment(cmd: u32, data: *mut c_void) -> bool {
+    callbacks().environment.is_some_and(|environment| environment(cmd, data))
+}
+
+/// Front-end reading the RetroPad and drawing to an xrgb buffer handed to the video callback
+struct Retro {
+    pixels: Rc<RefCell<Vec<u32>>>,
+}
+
+impl Frontend for Retro {
+
+    fn draw(&mut self, display: &[u8], _overlay: &Overlay) {
+        let mut pixels = self.pixels.borrow_mut();
+        for (pixel, lit) in pixels.iter_mut().zip(display.iter()) {
+            *pixel = if *lit != 0 { FOREGROUND } else { BACKGROUND };
+        }
+    }
+
+    fn poll(&mut self, keyboard: &mut [bool; KEYBOARD_SIZE]) -> Vec<Command> {
+        let callbacks = callbacks();
+        if let (Some(input_poll), Some(input_state)) = (callbacks.input_poll, callbacks.input_state) {
+            input_poll();
+            for (button, key, _) in BUTTONS.iter() {
+                keyboard[*key] = input_state(0, RETRO_DEVICE_JOYPAD, 0, *button) != 0;
+            }
+        }
+        Vec::new()
+    }
+
+    fn realtime(&self) -> bool {
+        false
+    }
+}
+
+/// Buzzer samples of the last frame, stereo, for the audio batch callback
+struct Samples {
+    oscillator: Oscillator,
+    samples: Rc<RefCell<Vec<i16>>>,
+}
+
+impl Audio for Samples {
+    fn buzz(&mut self, on: bool) {
+        let mut samples = self.samples.borrow_mut();
+        for _ in 0..SAMPLE_RATE / TIMER_RATE {
+            let sample = (self.oscillator.next(on) * f32::from(i16::MAX)) as i16;
+            samples.extend_from_slice(&[sample, sample]);
+        }
+    }
+}
+
+struct Core {
+    cpu: Cpu,
+    pixels: Rc<RefCell<Vec<u32>>>,
+    samples: Rc<RefCell<Vec<i16>>>,
+    rom: Vec<u8>,
+}
+
+thread_local! {
+    static CORE: RefCell<Option<Core>> = const { RefCell::new(None) };
+}
+
+fn with_core<T: Default>(f: impl FnOnce(&mut Core) -> T) -> T {
+    CORE.with(|core| core.borrow_mut().as_mut().map(f).unwrap_or_default())
+}
+
+impl Core {
+
+    fn new() -> Core {
+        let pixels = Rc::new(RefCell::new(vec![BACKGROUND; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize]));
+        let samples = Rc::new(RefCell::new(Vec::new()));
+        let tone = Tone {
+            waveform: Waveform::Square,
+            frequency: 440.0,
+            volume: 0.25,
+        };
+        let frontend = Retro { pixels: pixels.clone() };
+        let audio = Samples {
+            oscillator: Oscillator::new(tone, SAMPLE_RATE),
+            samples: samples.clone(),
+        };
+        Core {
+            cpu: cpu::initialize(Box::new(frontend), Box::new(audio)),
+            pixels,
+            samples,
+            rom: Vec::new(),
+        }
+    }
+
+    fn boot(&mut self) {
+        self.cpu.reset();
+        self.cpu.bootup(self.rom.clone());
+    }
+
+    fn options(&mut self) {
+        let mut variable = Variable {
+            key: SPEED_KEY.as_ptr() as *const c_char,
+            value: std::ptr::null(),
+        };
+        if environment(RETRO_ENVIRONMENT_GET_VARIABLE, &mut variable as *mut Variable as *mut c_void) && !variable.value.is_null() {
+            let value = unsafe { CStr::from_ptr(variable.value) }.to_string_lossy();
+            if let Ok(speed) = value.parse() {
+                self.cpu.clock.speed = speed;
+            }
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> u32 {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {
+    CORE.with(|core| *core.borrow_mut() = Some(Core::new()));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    CORE.with(|core| *core.borrow_mut() = None);
+}
+
+/// # Safety
+/// info must point to a SystemInfo
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    *info = SystemInfo {
+        library_name: b"chip8\0".as_ptr() as *const c_char,
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: b"ch8|c8\0".as_ptr() as *const c_char,
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+/// info must point to a SystemAvInfo
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    *info = SystemAvInfo {
+        geometry: GameGeometry {
+            base_width: SCREEN_WIDTH,
+            base_height: SCREEN_HEIGHT,
+            max_width: SCREEN_WIDTH,
+            max_height: SCREEN_HEIGHT,
+            aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
+        },
+        timing: SystemTiming {
+            fps: TIMER_RATE as f64,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: EnvironmentFn) {
+    CALLBACKS.lock().unwrap_or_else(|e| e.into_inner()).environment = Some(callback);
+    let mut variables = [
+        Variable { key: SPEED_KEY.as_ptr() as *const c_char, value: SPEED_VALUES.as_ptr() as *const c_char },
+        Variable { key: std::ptr::null(), value: std::ptr::null() },
+    ];
+    environment(RETRO_ENVIRONMENT_SET_VARIABLES, variables.as_mut_ptr() as *mut c_void);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshFn) {
+    CALLBACKS.lock().unwrap_or_else(|e| e.into_inner()).video_refresh = Some(callback);
+}
+
+/// unused, the samples of a frame go through the batch callback
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchFn) {
+    CALLBACKS.lock().unwrap_or_else(|e| e.into_inner()).audio_sample_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollFn) {
+    CALLBACKS.lock().unwrap_or_else(|e| e.into_inner()).input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateFn) {
+    CALLBACKS.lock().unwrap_or_else(|e| e.into_inner()).input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: u32, _device: u32) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    with_core(Core::boot);
+}
+
+/// runs one frame of 1/60 s, or more with the speed option, then presents screen and sound
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let callbacks = callbacks();
+    with_core(|core| {
+        let mut updated = false;
+        if environment(RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE, &mut updated as *mut bool as *mut c_void) && updated {
+            core.options();
+        }
+        core.cpu.host_frame(&mut |_| false);
+
+        if let Some(video_refresh) = callbacks.video_refresh {
+            let pixels = core.pixels.borrow();
+            video_refresh(pixels.as_ptr() as *const c_void, SCREEN_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH as usize * 4);
+        }
+        let mut samples = core.samples.borrow_mut();
+        if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+            audio_sample_batch(samples.as_ptr(), samples.len() / 2);
+        }
+        samples.clear();
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    state::SIZE
+}
+
+/// # Safety
+/// data must point to size writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    if size < state::SIZE {
+        return false;
+    }
+    with_core(|core| {
+        let state = core.cpu.save_state();
+        std::ptr::copy_nonoverlapping(state.as_ptr(), data as *mut u8, state.len());
+        true
+    })
+}
+
+/// # Safety
+/// data must point to size readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let state = std::slice::from_raw_parts(data as *const u8, size);
+    with_core(|core| match core.cpu.load_state(state) {
+        Ok(()) => true,
+        Err(e) => {
+            error!("{}", e);
+            false
+        },
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {
+    with_core(|core| core.cpu.cheats.frozen.clear());
+}
+
+/// codes are the cheat file ones, `address:value` freezes and `address=value` patches,
+/// several codes are separated by spaces or +
+///
+/// # Safety
+/// code must be a nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn retro_cheat_set(_index: u32, enabled: bool, code: *const c_char) {
+    if !enabled || code.is_null() {
+        return;
+    }
+    let text = CStr::from_ptr(code).to_string_lossy();
+    with_core(|core| {
+        for text in text.split(|c: char| c == '+' || c.is_whitespace()).filter(|text| !text.is_empty()) {
+            match cheat::code(text) {
+                Some(code) => core.cpu.apply_code(code),
+                None => error!("bad cheat code {}", text),
+            }
+        }
+    });
+}
+
+/// # Safety
+/// game must point to a GameInfo with the rom data
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() || (*game).size > with_core(|core| MEMORY_SIZE - core.cpu.load_address as usize) {
+        return false;
+    }
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+    if !environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut u32 as *mut c_void) {
+        error!("the front-end doesn't support xrgb8888");
+        return false;
+    }
+    let mut descriptors: Vec<InputDescriptor> = BUTTONS.iter().map(|(button, _, description)| InputDescriptor {
+        port: 0,
+        device: RETRO_DEVICE_JOYPAD,
+        index: 0,
+        id: *button,
+        description: description.as_ptr() as *const c_char,
+    }).collect();
+    descriptors.push(InputDescriptor { port: 0, device: 0, index: 0, id: 0, description: std::ptr::null() });
+    environment(RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS, descriptors.as_mut_ptr() as *mut c_void);
+
+    let rom = std::slice::from_raw_parts((*game).data as *const u8, (*game).size).to_vec();
+    with_core(|core| {
+        core.rom = rom;
+        core.boot();
+        core.options();
+        true
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_type: u32, _info: *const GameInfo, _num: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    with_core(|core| core.rom.clear());
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> u32 {
+    RETRO_REGION_NTSC
+}
+
+/// the 4kb of chip-8 memory, for the front-end's cheat search and achievements
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: u32) -> *mut c_void {
+    if id != RETRO_MEMORY_SYSTEM_RAM {
+        return std::ptr::null_mut();
+    }
+    CORE.with(|core| match core.borrow_mut().as_mut() {
+        Some(core) => core.cpu.memory.as_mut_ptr() as *mut c_void,
+        None => std::ptr::null_mut(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: u32) -> usize {
+    if id == RETRO_MEMORY_SYSTEM_RAM { MEMORY_SIZE } else { 0 }
+}
@@ -1,7 +1,9 @@
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
-use std::fs::File;
-use std::io::prelude::*;
+use std::time::Duration;
+use std::ops::RangeInclusive;
+use structopt::clap::arg_enum;
 
 #[macro_use]
 extern crate log;
@@ -9,18 +11,394 @@ extern crate env_logger;
 use env_logger::Env;
 
 mod chip8;
+use chip8::frontend::Frontend;
+use chip8::sound::{Audio, Tone, Waveform};
+use chip8::config::{AudioProfile, Palette, Profile, QuirkProfile};
 
+arg_enum! {
+    #[derive(Debug)]
+    enum FrontendKind {
+        Sdl,
+        Tty,
+        Headless,
+    }
+}
+
+arg_enum! {
+    #[derive(Debug)]
+    enum AudioKind {
+        Device,
+        Bell,
+        Wav,
+        Null,
+    }
+}
+
+arg_enum! {
+    #[derive(Debug, PartialEq)]
+    enum Quirk {
+        Shift,
+        LoadStore,
+        Clip,
+    }
+}
+
+/// exit codes: 1 a test failed or traces differ, 2 the rom or anything else the command needs couldn't be loaded,
+/// 3 the emulation stopped on an instruction it couldn't run, 4 the debugger or the netplay session failed
+const TEST_FAILED: i32 = 1;
+const LOAD_ERROR: i32 = 2;
+const FAULT: i32 = 3;
+const SESSION_FAILED: i32 = 4;
+
+/// frames test and trace emulate unless told otherwise
+const HEADLESS_FRAMES: u64 = 600;
+
+const SUBCOMMANDS: [&str; 12] = ["run", "disasm", "asm", "info", "test", "bench", "trace", "trace-diff", "make-patch", "analyze", "config", "help"];
+
+/// the exit code of a failed command, and why
+type Failure = (i32, String);
+
+/// counts the allocations bench reports
+#[cfg(feature = "count-allocations")]
+#[global_allocator]
+static ALLOCATOR: chip8::bench::Counting = chip8::bench::Counting;
+
+/// options of every subcommand emulating a rom
+#[derive(StructOpt, Debug)]
+struct Common {
+    /// config file, $XDG_CONFIG_HOME/chip8/config.toml by default, see src/chip8/config.rs
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// where the rom is loaded and starts, 0x200 by default
+    #[structopt(long = "load-address", parse(try_from_str = "parse_address"))]
+    load_address: Option<u16>,
+
+    /// behaviour of another interpreter to follow, may be passed multiple times
+    #[structopt(long, raw(possible_values = "&Quirk::variants()", case_insensitive = "true", number_of_values = "1"))]
+    quirk: Vec<Quirk>,
+
+    /// cheat file, the cheats listed for the rom are applied when it boots
+    #[structopt(long, parse(from_os_str))]
+    cheats: Option<PathBuf>,
+
+    /// ips or bps patch applied to the rom before it boots, may be passed multiple times
+    #[structopt(long, parse(from_os_str), raw(number_of_values = "1"))]
+    patch: Vec<PathBuf>,
+
+    /// seed of the RND instruction, runs with the same seed and input give the same trace
+    #[structopt(long)]
+    seed: Option<u64>,
+
+    /// quit after emulating this many frames, test and trace stop at 600 by default
+    #[structopt(long)]
+    frames: Option<u64>,
+}
+
+#[derive(StructOpt, Debug)]
+struct Run {
+    /// Frontend showing the emulation: sdl window, tty for terminals without a display or headless
+    #[structopt(long, default_value = "sdl", raw(possible_values = "&FrontendKind::variants()", case_insensitive = "true"))]
+    frontend: FrontendKind,
+
+    /// tty frontend: pixels per character, halfblock (1x2) or braille (2x4)
+    #[structopt(long = "tty-render", default_value = "halfblock", raw(possible_values = "&chip8::frontend::tty::Render::variants()", case_insensitive = "true"))]
+    tty_render: chip8::frontend::tty::Render,
+
+    /// tty frontend: ANSI 256 colour index of lit pixels
+    #[structopt(long = "tty-fg", default_value = "15")]
+    tty_fg: u8,
+
+    /// tty frontend: ANSI 256 colour index of unlit pixels
+    #[structopt(long = "tty-bg", default_value = "0")]
+    tty_bg: u8,
+
+    /// tty frontend: milliseconds a key stays pressed after the terminal last reported it
+    #[structopt(long = "tty-key-timeout", default_value = "150")]
+    tty_key_timeout: u64,
+
+    /// Audio output: sound device, terminal bell, wav file or null; defaults to bell on tty and device otherwise
+    #[structopt(long, raw(possible_values = "&AudioKind::variants()", case_insensitive = "true"))]
+    audio: Option<AudioKind>,
+
+    /// wav file written by the wav audio output
+    #[structopt(long, parse(from_os_str), default_value = "chip8.wav")]
+    wav: PathBuf,
+
+    /// buzzer waveform, sine by default
+    #[structopt(long, raw(possible_values = "&Waveform::variants()", case_insensitive = "true"))]
+    waveform: Option<Waveform>,
+
+    /// buzzer frequency in hertz, 440 by default
+    #[structopt(long)]
+    frequency: Option<f32>,
+
+    /// buzzer volume, from 0.0 to 1.0 (the default)
+    #[structopt(long)]
+    volume: Option<f32>,
+
+    /// start with the buzzer muted, toggled at runtime with M
+    #[structopt(long)]
+    mute: bool,
+
+    /// let the sound device drive the emulation clock instead of sleeping between ticks
+    #[structopt(long = "audio-sync")]
+    audio_sync: bool,
+
+    /// start paused, toggled at runtime with P, N advances a single frame
+    #[structopt(long)]
+    paused: bool,
+
+    /// initial emulation speed multiplier, below 1.0 for slow motion, 1.0 by default
+    #[structopt(long, parse(try_from_str = "parse_speed"))]
+    speed: Option<f32>,
+
+    /// speed multiplier toggled with Tab, adjusted at runtime with + and -, uncapped in headless mode
+    #[structopt(long = "fast-forward", default_value = "4.0", parse(try_from_str = "parse_speed"))]
+    fast_forward: f32,
+
+    /// speed multiplier toggled with S
+    #[structopt(long = "slow-motion", default_value = "0.25", parse(try_from_str = "parse_speed"))]
+    slow_motion: f32,
+
+    /// silence the buzzer while fast forwarding instead of keeping its pitch
+    #[structopt(long = "fast-forward-mute")]
+    fast_forward_mute: bool,
+
+    /// show the fps / instructions per second counter, toggled at runtime with I
+    #[structopt(long)]
+    stats: bool,
+
+    /// open the sdl memory viewer at start, toggled at runtime with V
+    #[structopt(long = "memory-viewer")]
+    memory_viewer: bool,
+
+    /// sdl frontend: window pixels per chip-8 pixel, 10 by default
+    #[structopt(long)]
+    scale: Option<u32>,
+
+    /// sdl frontend: colour of lit pixels as #RRGGBB
+    #[structopt(long)]
+    foreground: Option<String>,
+
+    /// sdl frontend: colour of unlit pixels as #RRGGBB
+    #[structopt(long)]
+    background: Option<String>,
+
+    /// TrueType font of the sdl on-screen display, a system monospace font is looked up otherwise
+    #[structopt(long, parse(from_os_str))]
+    font: Option<PathBuf>,
+
+    /// debug through the gdb remote protocol, waits for gdb on 127.0.0.1:<port>
+    #[structopt(long)]
+    gdb: Option<u16>,
+
+    /// serve the debug adapter protocol on stdio or a tcp address (port or host:port),
+    /// the rom is then given by the editor launch request
+    #[structopt(long)]
+    dap: Option<String>,
+
+    /// rhai script hooking into the emulation, see scripts/ for examples
+    #[structopt(long, parse(from_os_str), raw(conflicts_with_all = r#"&["gdb", "dap", "control"]"#))]
+    script: Option<PathBuf>,
+
+    /// remote control for automated tests, json-rpc on a tcp address (port or host:port),
+    /// see src/chip8/control.rs for the methods
+    #[structopt(long, raw(conflicts_with_all = r#"&["gdb", "dap"]"#))]
+    control: Option<String>,
+
+    /// host a two-player netplay session on a tcp address (port or host:port), waits for the other player.
+    /// A bare port listens on every network interface, 127.0.0.1:port keeps the session on this machine
+    #[structopt(long = "netplay-host", raw(conflicts_with_all = r#"&["gdb", "dap", "control", "script", "netplay_join"]"#))]
+    netplay_host: Option<String>,
+
+    /// join the two-player netplay session hosted on a tcp address (port or host:port)
+    #[structopt(long = "netplay-join", raw(conflicts_with_all = r#"&["gdb", "dap", "control", "script"]"#))]
+    netplay_join: Option<String>,
+
+    /// keys played on this side of netplay as hex digits, 14 for the host and CD for the other player by default
+    #[structopt(long = "netplay-keys", parse(try_from_str = "chip8::netplay::parse_keys"))]
+    netplay_keys: Option<u16>,
+
+    /// frames of netplay input delay, hiding latency without rolling back, the host's is used by both
+    #[structopt(long = "netplay-delay", default_value = "2")]
+    netplay_delay: u64,
+
+    /// write an execution trace of every instruction to this file
+    #[structopt(long, parse(from_os_str))]
+    trace: Option<PathBuf>,
+
+    /// trace file format
+    #[structopt(long = "trace-format", default_value = "text", raw(possible_values = "&chip8::trace::Format::variants()", case_insensitive = "true"))]
+    trace_format: chip8::trace::Format,
+
+    /// only trace instructions at these addresses, as start-end (e.g. 0x200-0x2FF)
+    #[structopt(long = "trace-pc", parse(try_from_str = "chip8::trace::parse_range"))]
+    trace_pc: Option<RangeInclusive<u64>>,
+
+    /// only trace instructions of these frames, as start-end
+    #[structopt(long = "trace-frames", parse(try_from_str = "chip8::trace::parse_range"))]
+    trace_frames: Option<RangeInclusive<u64>>,
+
+    /// profile the run and write hot spots, subroutine cycles and rom coverage to this file
+    #[structopt(long, parse(from_os_str))]
+    profile: Option<PathBuf>,
+
+    /// profile the run and write the rom disassembly annotated with execution counts to this file
+    #[structopt(long = "profile-listing", parse(from_os_str))]
+    profile_listing: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    common: Common,
+
+    /// rom to emulate
+    #[structopt(parse(from_os_str), raw(required_unless = r#""dap""#))]
+    rom: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+enum ConfigCommand {
+    /// print the configuration in effect, for a rom when given
+    #[structopt(name = "dump")]
+    Dump {
+        #[structopt(flatten)]
+        common: Common,
+        #[structopt(parse(from_os_str))]
+        rom: Option<PathBuf>,
+    },
+}
+
+// parsed once, boxing run isn't worth it
+#[allow(clippy::large_enum_variant)]
 #[derive(StructOpt, Debug)]
-#[structopt(name = "chip8", about = "chip8 emulator, have funn !!!")]
+enum Command {
+    /// emulate a rom, what chip8 <rom> does without a subcommand
+    #[structopt(name = "run")]
+    Run(Run),
+    /// print the disassembly of a rom, which asm assembles back into the same rom
+    #[structopt(name = "disasm")]
+    Disasm {
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+        /// address of the first byte of the rom
+        #[structopt(long = "load-address", default_value = "200", parse(try_from_str = "parse_address"))]
+        load_address: u16,
+    },
+    /// assemble the mnemonics disasm prints into a rom, see src/chip8/asm.rs
+    #[structopt(name = "asm")]
+    Asm {
+        #[structopt(parse(from_os_str))]
+        source: PathBuf,
+        /// rom file, defaults to the source with the ch8 extension
+        #[structopt(long, short, parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// address the rom is loaded at, where labels count from
+        #[structopt(long = "load-address", default_value = "200", parse(try_from_str = "parse_address"))]
+        load_address: u16,
+    },
+    /// print the size, crc32 and settings of a rom
+    #[structopt(name = "info")]
+    Info {
+        #[structopt(flatten)]
+        common: Common,
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+    },
+    /// run a rom headless and check it doesn't fault, and that its last frame looks like a screenshot
+    #[structopt(name = "test")]
+    Test {
+        #[structopt(flatten)]
+        common: Common,
+        /// png screenshot the last frame has to match
+        #[structopt(long, parse(from_os_str))]
+        expect: Option<PathBuf>,
+        /// write the last frame to the expected screenshot instead of comparing them
+        #[structopt(long, raw(requires = r#""expect""#))]
+        update: bool,
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+    },
+    /// run a rom headless as fast as it goes and report instructions per second, time per opcode and allocations
+    #[structopt(name = "bench")]
+    Bench {
+        #[structopt(flatten)]
+        common: Common,
+        /// instructions to run
+        #[structopt(long, default_value = "1000000")]
+        cycles: u64,
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+    },
+    /// run a rom headless and write an execution trace of every instruction
+    #[structopt(name = "trace")]
+    Trace {
+        #[structopt(flatten)]
+        common: Common,
+        /// trace file
+        #[structopt(long, short, parse(from_os_str), default_value = "chip8.trace")]
+        output: PathBuf,
+        /// trace file format
+        #[structopt(long, default_value = "text", raw(possible_values = "&chip8::trace::Format::variants()", case_insensitive = "true"))]
+        format: chip8::trace::Format,
+        /// only trace instructions at these addresses, as start-end (e.g. 0x200-0x2FF)
+        #[structopt(long, parse(try_from_str = "chip8::trace::parse_range"))]
+        pc: Option<RangeInclusive<u64>>,
+        /// only trace instructions of these frames, as start-end
+        #[structopt(long = "frame-range", parse(try_from_str = "chip8::trace::parse_range"))]
+        frame_range: Option<RangeInclusive<u64>>,
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+    },
+    /// compare two execution traces and report where they first diverge
+    #[structopt(name = "trace-diff")]
+    TraceDiff {
+        #[structopt(parse(from_os_str))]
+        a: PathBuf,
+        #[structopt(parse(from_os_str))]
+        b: PathBuf,
+        /// instructions shown around the divergence
+        #[structopt(long, default_value = "5")]
+        context: usize,
+    },
+    /// write an ips or bps patch turning the original rom into the modified one
+    #[structopt(name = "make-patch")]
+    MakePatch {
+        #[structopt(parse(from_os_str))]
+        original: PathBuf,
+        #[structopt(parse(from_os_str))]
+        modified: PathBuf,
+        /// patch file, defaults to the modified rom with the format as extension
+        #[structopt(long, short, parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// patch format, defaults to the output extension or ips
+        #[structopt(long, raw(possible_values = "&chip8::patch::Format::variants()", case_insensitive = "true"))]
+        format: Option<chip8::patch::Format>,
+    },
+    /// report the opcodes, extensions and quirks a rom uses and the interpreter it most likely expects
+    #[structopt(name = "analyze")]
+    Analyze {
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+        /// frames the rom is run for, with random key presses, to find what only shows at run time
+        #[structopt(long, default_value = "36000")]
+        frames: u64,
+    },
+    /// configuration file with global settings and settings per rom
+    #[structopt(name = "config")]
+    Config(ConfigCommand),
+}
+
+/// chip8 <rom> runs the rom, the same as chip8 run <rom>
+#[derive(StructOpt, Debug)]
+#[structopt(name = "chip8", about = "chip8 emulator, have funn !!!",
+    raw(setting = "structopt::clap::AppSettings::SubcommandRequiredElseHelp"))]
 struct Opt {
     /// Log level, will increase log level if passed multiple times: error, warn, info, debug, trace
-    #[structopt(long, short, parse(from_occurrences))]
+    #[structopt(long, short, parse(from_occurrences), raw(global = "true"))]
     debug: usize,
 
-    /// rom to emulate 
-    #[structopt(parse(from_os_str))]
-    rom: PathBuf,
-
+    #[structopt(subcommand)]
+    command: Command,
 }
 
 fn log_level(lvl: usize) -> String {
@@ -32,38 +410,395 @@ fn log_level(lvl: usize) -> String {
     };
 }
 
-fn main() {
-    let opt = Opt::from_args();
-    env_logger::from_env(Env::default().default_filter_or(log_level(opt.debug))).init();
-    debug!("{:?}", opt); 
-    let mut file = match File::open(opt.rom) {
-        Ok(f) => f,
-        Err(e) => {
-            error!("chip8 - couldn't open the rom: {}", e);
-            return
+fn parse_address(text: &str) -> Result<u16, String> {
+    u16::from_str_radix(text.trim_start_matches("0x"), 16).map_err(|_| format!("{} is not a hex address", text))
+}
+
+/// speed multipliers, zero or less would never run a frame
+fn parse_speed(text: &str) -> Result<f32, String> {
+    match text.parse::<f32>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        _ => Err(format!("{} is not a speed, speeds are above 0", text)),
+    }
+}
+
+/// the arguments with run put before a bare rom path, past the debug flags
+fn with_run(mut args: Vec<OsString>) -> Vec<OsString> {
+    let debug = |arg: &str| arg == "--debug" || arg.len() > 1 && arg.starts_with('-') && arg[1..].chars().all(|c| c == 'd');
+    let at = match args.iter().skip(1).position(|arg| !debug(&arg.to_string_lossy())) {
+        Some(at) => at + 1,
+        None => return args,
+    };
+    let arg = args[at].to_string_lossy();
+    if !SUBCOMMANDS.contains(&arg.as_ref()) && !["-h", "--help", "-V", "--version"].contains(&arg.as_ref()) {
+        args.insert(at, OsString::from("run"));
+    }
+    args
+}
+
+/// the settings given on the command line, overriding the config file
+fn command_line(common: &Common, opt: Option<&Run>) -> Profile {
+    let quirk = |quirk| if common.quirk.contains(&quirk) { Some(true) } else { None };
+    let profile = Profile {
+        load_address: common.load_address,
+        quirks: QuirkProfile {
+            shift: quirk(Quirk::Shift),
+            load_store: quirk(Quirk::LoadStore),
+            clip: quirk(Quirk::Clip),
         },
+        ..Profile::default()
     };
-    let mut program_buffer = Vec::<u8>::new();
-    match file.read_to_end(&mut program_buffer) {
-        Err(e) => {
-            error!("chip8 - couldn't load rom: {}", e);
-            return
+    match opt {
+        Some(opt) => Profile {
+            speed: opt.speed,
+            scale: opt.scale,
+            palette: Palette {
+                foreground: opt.foreground.clone(),
+                background: opt.background.clone(),
+            },
+            audio: AudioProfile {
+                waveform: opt.waveform.map(|waveform| waveform.to_string().to_lowercase()),
+                frequency: opt.frequency,
+                volume: opt.volume,
+                mute: if opt.mute { Some(true) } else { None },
+            },
+            ..profile
         },
-        _ => {},
+        None => profile,
+    }
+}
+
+/// the rom with its patches, and the settings it runs with
+fn load(common: &Common, command_line: &Profile, rom: Option<&Path>) -> Result<(Vec<u8>, Profile), String> {
+    let program_buffer = match rom {
+        Some(rom) => chip8::rom::load(rom).and_then(|rom| {
+            common.patch.iter().try_fold(rom, |rom, patch| chip8::patch::load(patch, rom))
+        })?,
+        None => Vec::new(),
+    };
+    let settings = chip8::config::load(common.config.as_deref())?.effective(command_line, rom, &program_buffer);
+    debug!("{:?}", settings);
+    let load_address = settings.load_address()?;
+    if load_address as usize + program_buffer.len() > chip8::cpu::MEMORY_SIZE {
+        return Err(format!("the rom doesn't fit in memory at {:03X}, {} bytes", load_address, program_buffer.len()));
+    }
+    Ok((program_buffer, settings))
+}
+
+/// applies the settings every subcommand emulating a rom shares
+fn configure(chip8: &mut chip8::cpu::Cpu, common: &Common, settings: &Profile) -> Result<(), String> {
+    chip8.quirks = settings.quirks();
+    chip8.load_address = settings.load_address()?;
+    chip8.clock.frame_limit = common.frames;
+    if let Some(seed) = common.seed {
+        chip8.reseed(seed);
+    }
+    if let Some(path) = &common.cheats {
+        chip8.cheats.database = chip8::cheat::load(path)?;
+    }
+    Ok(())
+}
+
+/// a machine without frontend nor audio, booted up, that quits after `frames` unless told otherwise
+fn headless(common: &Common, rom: &Path, frames: u64) -> Result<chip8::cpu::Cpu, String> {
+    let (program_buffer, settings) = load(common, &command_line(common, None), Some(rom))?;
+    let mut chip8 = chip8::cpu::initialize(Box::new(chip8::frontend::headless::Headless), Box::new(chip8::sound::Null));
+    configure(&mut chip8, common, &settings)?;
+    chip8.clock.frame_limit = Some(common.frames.unwrap_or(frames));
+    chip8.bootup(program_buffer);
+    Ok(chip8)
+}
+
+/// emulates frames as fast as they go until the machine quits, fails if it stopped on a fault
+fn emulate(chip8: &mut chip8::cpu::Cpu) -> Result<(), Failure> {
+    while !chip8.quit {
+        chip8.frame();
+    }
+    match chip8.fault.take() {
+        Some(fault) => Err((FAULT, fault)),
+        None => Ok(()),
+    }
+}
+
+/// prints the settings a run would use, with the file they come from
+fn dump_config(common: &Common, rom: Option<&Path>) -> Result<(), String> {
+    let config = chip8::config::load(common.config.as_deref())?;
+    let program_buffer = match rom {
+        Some(rom) => chip8::rom::load(rom)?,
+        None => Vec::new(),
+    };
+    match &config.path {
+        Some(path) => println!("# {}", path.display()),
+        None => println!("# no config file, {} is read when there", chip8::config::default_path()
+            .map_or("none".to_string(), |path| path.display().to_string())),
+    }
+    if rom.is_some() && config.rom(rom, &program_buffer).is_some() {
+        println!("# with the settings of {}", rom.unwrap_or(Path::new("")).display());
+    }
+    print!("{}", config.effective(&command_line(common, None), rom, &program_buffer).to_toml()?);
+    Ok(())
+}
+
+fn audio(opt: &Run, tone: Tone) -> Result<Box<dyn Audio>, String> {
+    let kind = match (&opt.audio, &opt.frontend) {
+        (Some(kind), _) => kind,
+        (None, FrontendKind::Tty) => &AudioKind::Bell,
+        (None, FrontendKind::Sdl) => &AudioKind::Device,
+        (None, FrontendKind::Headless) => &AudioKind::Null,
     };
+    Ok(match kind {
+        AudioKind::Device => match chip8::sound::device::new(tone) {
+            Ok(device) => Box::new(device),
+            Err(e) => {
+                warn!("no sound, falling back to null audio: {}", e);
+                Box::new(chip8::sound::Null)
+            },
+        },
+        AudioKind::Bell => Box::new(chip8::sound::bell::new()),
+        AudioKind::Wav => Box::new(chip8::sound::wav::new(&opt.wav, tone)?),
+        AudioKind::Null => Box::new(chip8::sound::Null),
+    })
+}
 
+fn make_patch(original: &Path, modified: &Path, output: Option<&Path>, format: Option<chip8::patch::Format>) -> Result<(), String> {
+    let format = format.unwrap_or_else(|| match output.and_then(|output| output.extension()) {
+        Some(extension) if extension.eq_ignore_ascii_case("bps") => chip8::patch::Format::Bps,
+        _ => chip8::patch::Format::Ips,
+    });
+    let output = output.map(Path::to_path_buf)
+        .unwrap_or_else(|| modified.with_extension(format.to_string().to_lowercase()));
+    let patch = chip8::patch::make(&chip8::rom::load(original)?, &chip8::rom::load(modified)?, format);
+    std::fs::write(&output, patch).map_err(|e| format!("couldn't write the patch: {}", e))?;
+    info!("wrote {}", output.display());
+    Ok(())
+}
 
-    trace!("{:?}", program_buffer);
+fn assemble(source: &Path, output: Option<&Path>, load_address: u16) -> Result<(), String> {
+    let text = std::fs::read_to_string(source).map_err(|e| format!("couldn't read {}: {}", source.display(), e))?;
+    let rom = chip8::asm::assemble(&text, load_address).map_err(|e| format!("{}: {}", source.display(), e))?;
+    let output = output.map(Path::to_path_buf).unwrap_or_else(|| source.with_extension("ch8"));
+    std::fs::write(&output, &rom).map_err(|e| format!("couldn't write the rom: {}", e))?;
+    info!("wrote {}, {} bytes", output.display(), rom.len());
+    Ok(())
+}
 
-    let mut chip8 = match chip8::cpu::initialize() {
-        Ok(cpu) => cpu,
-        Err(e) => {
-            error!("An error ocourred: {}", e);
-            return
+fn rom_info(common: &Common, rom: &Path) -> Result<(), String> {
+    let (program_buffer, settings) = load(common, &command_line(common, None), Some(rom))?;
+    let config = chip8::config::load(common.config.as_deref())?;
+    let load_address = settings.load_address()?;
+    let quirks: Vec<&str> = [("shift", settings.quirks().shift), ("load-store", settings.quirks().load_store), ("clip", settings.quirks().clip)]
+        .iter().filter(|(_, on)| *on).map(|(name, _)| *name).collect();
+    println!("file     {}", rom.display());
+    println!("size     {} bytes, {:03X}-{:03X}", program_buffer.len(), load_address,
+        (load_address as usize + program_buffer.len()).saturating_sub(1));
+    println!("crc32    {:08X}", chip8::rom::crc32(&program_buffer));
+    match (&config.path, config.rom(Some(rom), &program_buffer)) {
+        (Some(path), Some(_)) => println!("config   settings in {}", path.display()),
+        _ => println!("config   none"),
+    }
+    println!("quirks   {}", if quirks.is_empty() { "none".to_string() } else { quirks.join(", ") });
+    Ok(())
+}
+
+fn test(common: &Common, rom: &Path, expect: Option<&Path>, update: bool) -> Result<(), Failure> {
+    let mut chip8 = headless(common, rom, HEADLESS_FRAMES).map_err(|e| (LOAD_ERROR, e))?;
+    emulate(&mut chip8)?;
+    if let Some(screenshot) = expect {
+        if update {
+            chip8.screenshot(screenshot).map_err(|e| (LOAD_ERROR, e))?;
+            println!("wrote {}", screenshot.display());
+        } else if !chip8.matches_screenshot(screenshot).map_err(|e| (LOAD_ERROR, e))? {
+            println!("FAILED {}", rom.display());
+            return Err((TEST_FAILED, format!("frame {} differs from {}", chip8.clock.frames, screenshot.display())));
         }
+    }
+    println!("ok {}", rom.display());
+    Ok(())
+}
+
+fn bench(common: &Common, rom: &Path, cycles: u64) -> Result<(), Failure> {
+    let mut chip8 = headless(common, rom, cycles).map_err(|e| (LOAD_ERROR, e))?;
+    let report = chip8::bench::run(&mut chip8, cycles);
+    report.print(&mut std::io::stdout().lock()).map_err(|e| (LOAD_ERROR, e.to_string()))?;
+    match report.fault {
+        Some(fault) => Err((FAULT, fault)),
+        None => Ok(()),
+    }
+}
+
+fn trace(common: &Common, rom: &Path, trace: chip8::trace::Trace) -> Result<(), Failure> {
+    let mut chip8 = headless(common, rom, HEADLESS_FRAMES).map_err(|e| (LOAD_ERROR, e))?;
+    chip8.trace = Some(trace);
+    emulate(&mut chip8)
+}
+
+fn run(opt: &Run) -> Result<(), Failure> {
+    let (program_buffer, settings) = load(&opt.common, &command_line(&opt.common, Some(opt)), opt.rom.as_deref())
+        .map_err(|e| (LOAD_ERROR, e))?;
+
+    let frontend: Result<Box<dyn Frontend>, String> = match opt.frontend {
+        FrontendKind::Sdl => settings.sdl()
+            .and_then(|sdl| chip8::frontend::sdl::new(opt.font.as_deref(), &sdl))
+            .map(|f| Box::new(f) as Box<dyn Frontend>),
+        FrontendKind::Tty => chip8::frontend::tty::new(opt.tty_render, opt.tty_fg, opt.tty_bg, Duration::from_millis(opt.tty_key_timeout))
+            .map(|f| Box::new(f) as Box<dyn Frontend>),
+        FrontendKind::Headless => Ok(Box::new(chip8::frontend::headless::Headless) as Box<dyn Frontend>),
+    };
+    let frontend = frontend.map_err(|e| (LOAD_ERROR, format!("An error ocourred: {}", e)))?;
+    let audio = settings.tone().and_then(|tone| audio(opt, tone))
+        .map_err(|e| (LOAD_ERROR, format!("An error ocourred: {}", e)))?;
+
+    let mut chip8 = chip8::cpu::initialize(frontend, audio);
+    chip8.muted = settings.audio.mute.unwrap_or_default();
+    chip8.audio_sync = opt.audio_sync;
+    chip8.clock.paused = opt.paused;
+    chip8.clock.speed = settings.speed().map_err(|e| (LOAD_ERROR, e))?;
+    configure(&mut chip8, &opt.common, &settings).map_err(|e| (LOAD_ERROR, e))?;
+    chip8.clock.fast_forward = opt.fast_forward;
+    chip8.clock.slow_motion = opt.slow_motion;
+    chip8.clock.fast_forward_mute = opt.fast_forward_mute;
+    if let Some(path) = &opt.trace {
+        let trace = chip8::trace::new(path, opt.trace_format, opt.trace_pc.clone(), opt.trace_frames.clone())
+            .map_err(|e| (LOAD_ERROR, e))?;
+        chip8.trace = Some(trace);
+    }
+    if opt.profile.is_some() || opt.profile_listing.is_some() {
+        chip8.profile = Some(chip8::profile::new(chip8.load_address, program_buffer.len()));
+    }
+    let control = match &opt.control {
+        Some(address) => {
+            let server = chip8::control::listen(address, program_buffer.clone()).map_err(|e| (LOAD_ERROR, e))?;
+            chip8.notify(format!("remote control on {}", server.address()));
+            Some(server)
+        },
+        None => None,
     };
-    
+    let netplay_rom = if opt.netplay_host.is_some() || opt.netplay_join.is_some() { Some(program_buffer.clone()) } else { None };
+
     chip8.bootup(program_buffer);
+    chip8.osd.show_stats = opt.stats;
+    if opt.memory_viewer {
+        chip8.toggle_memory_viewer();
+    }
+    if let Some(rom) = &opt.rom {
+        chip8.notify(format!("loaded {}", rom.display()));
+    }
 
-    chip8.run();
+    // the profile is written even when the debugger or the session failed
+    let outcome = if let Some(transport) = &opt.dap {
+        chip8::dap::serve(&mut chip8, transport).map_err(|e| (SESSION_FAILED, format!("debug adapter failed: {}", e)))
+    } else if let Some(port) = opt.gdb {
+        chip8::gdb::serve(&mut chip8, port).map_err(|e| (SESSION_FAILED, format!("gdb stub failed: {}", e)))
+    } else if let Some(mut server) = control {
+        server.run(&mut chip8);
+        Ok(())
+    } else if let Some(rom) = &netplay_rom {
+        let session = match &opt.netplay_host {
+            Some(address) => {
+                let settings = chip8::netplay::Settings {
+                    keys: opt.netplay_keys.unwrap_or(chip8::netplay::HOST_KEYS),
+                    delay: opt.netplay_delay,
+                    seed: opt.common.seed.unwrap_or_else(rand::random),
+                };
+                chip8::netplay::listen(address).and_then(|listener| chip8::netplay::host(&listener, &mut chip8, rom, &settings))
+            },
+            None => {
+                let settings = chip8::netplay::Settings {
+                    keys: opt.netplay_keys.unwrap_or(chip8::netplay::GUEST_KEYS),
+                    delay: opt.netplay_delay,
+                    seed: 0,
+                };
+                chip8::netplay::join(opt.netplay_join.as_deref().unwrap_or_default(), &mut chip8, rom, &settings)
+            },
+        };
+        session.map(|mut session| session.run(&mut chip8)).map_err(|e| (SESSION_FAILED, format!("netplay - {}", e)))
+    } else {
+        match &opt.script {
+            Some(path) => chip8::script::load(path, &mut chip8)
+                .map(|mut script| script.run(&mut chip8))
+                .map_err(|e| (LOAD_ERROR, e)),
+            None => {
+                chip8.run();
+                Ok(())
+            },
+        }
+    };
+
+    if let Some(profile) = &chip8.profile {
+        if let Some(path) = &opt.profile {
+            if let Err(e) = profile.report(path, &chip8) {
+                error!("couldn't write the profile: {}", e);
+            }
+        }
+        if let Some(path) = &opt.profile_listing {
+            if let Err(e) = profile.listing(path, &chip8) {
+                error!("couldn't write the profile listing: {}", e);
+            }
+        }
+    }
+    outcome?;
+    match chip8.fault.take() {
+        Some(fault) => Err((FAULT, fault)),
+        None => Ok(()),
+    }
+}
+
+fn main() {
+    let opt = Opt::from_iter(with_run(std::env::args_os().collect()));
+    env_logger::from_env(Env::default().default_filter_or(log_level(opt.debug))).init();
+    debug!("{:?}", opt);
+    let load_error = |e: String| (LOAD_ERROR, e);
+    let result = match &opt.command {
+        Command::Run(run_opt) => run(run_opt),
+        Command::Disasm { rom, load_address } => chip8::rom::load(rom)
+            .map(|rom| print!("{}", chip8::disasm::listing(&rom, *load_address)))
+            .map_err(load_error),
+        Command::Asm { source, output, load_address } => assemble(source, output.as_deref(), *load_address).map_err(load_error),
+        Command::Info { common, rom } => rom_info(common, rom).map_err(load_error),
+        Command::Test { common, expect, update, rom } => test(common, rom, expect.as_deref(), *update),
+        Command::Bench { common, cycles, rom } => bench(common, rom, *cycles),
+        Command::Trace { common, output, format, pc, frame_range, rom } => chip8::trace::new(output, *format, pc.clone(), frame_range.clone())
+            .map_err(load_error)
+            .and_then(|sink| trace(common, rom, sink)),
+        Command::TraceDiff { a, b, context } => match chip8::trace::diff(a, b, *context) {
+            Ok(false) => Ok(()),
+            Ok(true) => Err((TEST_FAILED, "the traces differ".to_string())),
+            Err(e) => Err(load_error(e)),
+        },
+        Command::MakePatch { original, modified, output, format } => make_patch(original, modified, output.as_deref(), *format)
+            .map_err(load_error),
+        Command::Analyze { rom, frames } => chip8::rom::load(rom)
+            .map(|rom| chip8::analyze::analyze(&rom, *frames))
+            .and_then(|analysis| analysis.report(&mut std::io::stdout().lock()).map_err(|e| e.to_string()))
+            .map_err(load_error),
+        Command::Config(ConfigCommand::Dump { common, rom }) => dump_config(common, rom.as_deref()).map_err(load_error),
+    };
+    if let Err((code, e)) = result {
+        // logging is off by default, the reason shows anyway
+        eprintln!("chip8 - {}", e);
+        std::process::exit(code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn bare_rom_runs() {
+        assert_eq!(with_run(args(&["chip8", "PONG"])), args(&["chip8", "run", "PONG"]));
+        assert_eq!(with_run(args(&["chip8", "-dd", "--debug", "PONG"])), args(&["chip8", "-dd", "--debug", "run", "PONG"]));
+        assert_eq!(with_run(args(&["chip8", "--frontend", "tty", "PONG"])), args(&["chip8", "run", "--frontend", "tty", "PONG"]));
+    }
+
+    #[test]
+    fn subcommands_stay() {
+        for command in [&["chip8", "trace", "PONG"][..], &["chip8", "-d", "disasm", "PONG"], &["chip8", "--help"], &["chip8", "-V"], &["chip8"], &["chip8", "-d"]].iter() {
+            assert_eq!(with_run(args(command)), args(command));
+        }
+    }
 }
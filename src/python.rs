@@ -0,0 +1,178 @@
+//! python module, built with `cargo build --release --features python`
+//! and imported once target/release/libchip8.so is copied to chip8.so, see python/.
+//! Machines are headless, so a process can hold as many as it likes:
+//!
+//! ```text
+//! import chip8, numpy
+//! machine = chip8.Machine(seed=0)
+//! machine.load(open("roms/PONG", "rb").read())
+//! machine.press(1)
+//! machine.step(600)
+//! screen = numpy.asarray(machine.framebuffer())   # (32, 64) uint8
+//! ```
+
+use std::ffi::{c_int, c_void};
+use std::ptr;
+
+use pyo3::exceptions::{PyBufferError, PyValueError};
+use pyo3::ffi;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::chip8::cpu::{self, *};
+use crate::chip8::frontend::headless::Headless;
+use crate::chip8::sound;
+
+/// A chip-8 machine with no window and no sound
+#[pyclass(unsendable)]
+pub struct Machine {
+    cpu: Cpu,
+}
+
+#[pymethods]
+impl Machine {
+
+    /// seed of RND, random if not given
+    #[new]
+    #[pyo3(signature = (seed=None))]
+    fn new(seed: Option<u64>) -> Machine {
+        let mut cpu = cpu::initialize(Box::new(Headless), Box::new(sound::Null));
+        if let Some(seed) = seed {
+            cpu.reseed(seed);
+        }
+        Machine { cpu }
+    }
+
+    /// resets the machine and boots the rom
+    fn load(&mut self, rom: &[u8]) -> PyResult<()> {
+        if rom.len() > MEMORY_SIZE - self.cpu.load_address as usize {
+            return Err(PyValueError::new_err(format!("rom too big, {} bytes", rom.len())));
+        }
+        self.cpu.reset();
+        self.cpu.clock.frames = 0;
+        self.cpu.bootup(rom.to_vec());
+        Ok(())
+    }
+
+    /// runs n frames of one instruction and a timer tick,
+    /// false once the machine stopped on an instruction it doesn't know
+    #[pyo3(signature = (n=1))]
+    fn step(&mut self, n: u64) -> bool {
+        for _ in 0..n {
+            if self.cpu.quit {
+                break;
+            }
+            self.cpu.frame();
+        }
+        !self.cpu.quit
+    }
+
+    /// holds a key down until it's released
+    fn press(&mut self, key: usize) -> PyResult<()> {
+        *self.key(key)? = true;
+        Ok(())
+    }
+
+    fn release(&mut self, key: usize) -> PyResult<()> {
+        *self.key(key)? = false;
+        Ok(())
+    }
+
+    /// copy of the screen, a 32 x 64 buffer of 0 and 1 bytes
+    fn framebuffer(&self) -> Framebuffer {
+        Framebuffer {
+            pixels: self.cpu.display.to_vec(),
+            shape: [SCREEN_HEIGHT as ffi::Py_ssize_t, SCREEN_WIDTH as ffi::Py_ssize_t],
+            strides: [SCREEN_WIDTH as ffi::Py_ssize_t, 1],
+        }
+    }
+
+    /// copy of the 4KB of memory
+    fn memory<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.cpu.memory)
+    }
+
+    /// V0 to VF, as bytes
+    fn registers(&self) -> Vec<u8> {
+        self.cpu.v.to_vec()
+    }
+
+    fn save_state<'py>(&mut self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.cpu.save_state())
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> PyResult<()> {
+        self.cpu.load_state(state).map_err(PyValueError::new_err)
+    }
+
+    /// frames run since the rom was loaded
+    #[getter]
+    fn frames(&self) -> u64 {
+        self.cpu.clock.frames
+    }
+
+    #[getter]
+    fn running(&self) -> bool {
+        !self.cpu.quit
+    }
+}
+
+impl Machine {
+
+    fn key(&mut self, key: usize) -> PyResult<&mut bool> {
+        self.cpu.keyboard.get_mut(key)
+            .ok_or_else(|| PyValueError::new_err(format!("no key {:X}, keys go from 0 to F", key)))
+    }
+}
+
+/// Read-only 2d buffer of bytes, taken as is by numpy.asarray and memoryview
+#[pyclass(frozen)]
+pub struct Framebuffer {
+    pixels: Vec<u8>,
+    shape: [ffi::Py_ssize_t; 2],
+    strides: [ffi::Py_ssize_t; 2],
+}
+
+#[pymethods]
+impl Framebuffer {
+
+    fn __len__(&self) -> usize {
+        self.shape[0] as usize
+    }
+
+    unsafe fn __getbuffer__(slf: Bound<'_, Self>, view: *mut ffi::Py_buffer, flags: c_int) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("no view to fill"));
+        }
+        if flags & ffi::PyBUF_WRITABLE == ffi::PyBUF_WRITABLE {
+            return Err(PyBufferError::new_err("the framebuffer is read-only"));
+        }
+        let this = slf.get();
+        (*view).buf = this.pixels.as_ptr() as *mut c_void;
+        (*view).len = this.pixels.len() as ffi::Py_ssize_t;
+        (*view).readonly = 1;
+        (*view).itemsize = 1;
+        (*view).format = if flags & ffi::PyBUF_FORMAT == ffi::PyBUF_FORMAT {
+            b"B\0".as_ptr() as *mut _
+        } else {
+            ptr::null_mut()
+        };
+        (*view).ndim = 2;
+        // both live as long as the object, which the view holds a reference to
+        (*view).shape = this.shape.as_ptr() as *mut _;
+        (*view).strides = this.strides.as_ptr() as *mut _;
+        (*view).suboffsets = ptr::null_mut();
+        (*view).internal = ptr::null_mut();
+        (*view).obj = slf.into_ptr();
+        Ok(())
+    }
+
+    unsafe fn __releasebuffer__(&self, _view: *mut ffi::Py_buffer) {}
+}
+
+#[pymodule]
+fn chip8(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Machine>()?;
+    module.add_class::<Framebuffer>()?;
+    Ok(())
+}
@@ -0,0 +1,12 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use chip8::chip8::analyze::analyze;
+
+#[test]
+fn pc_past_memory() {
+    // JP FFE runs the empty word at FFE, then the pc reads 1000 onwards from the font at the start of memory
+    let mut report = Vec::new();
+    analyze(&[0x1F, 0xFE], 10).report(&mut report).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    assert!(report.contains("stopped at 1000 "), "{}", report);
+}
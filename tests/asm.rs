@@ -0,0 +1,48 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::fs;
+
+use chip8::chip8::asm::assemble;
+use chip8::chip8::disasm::listing;
+
+#[test]
+fn round_trip() {
+    for entry in fs::read_dir("roms").unwrap() {
+        let path = entry.unwrap().path();
+        if !path.is_file() || path.extension().is_some_and(|extension| extension == "txt") {
+            continue;
+        }
+        let rom = fs::read(&path).unwrap();
+        assert_eq!(assemble(&listing(&rom, 0x200), 0x200).unwrap(), rom, "{}", path.display());
+    }
+}
+
+#[test]
+fn labels() {
+    let source = "
+        start:  LD V0, #05      ; a comment
+                CALL draw
+                jp start
+        draw:   DRW V0, V1, 0b101
+                LD [I], VF
+                SHR V3
+                RET
+        sprite: DB 0xF0, 144
+                DW sprite
+    ";
+    assert_eq!(assemble(source, 0x200).unwrap(), [
+        0x60, 0x05, 0x22, 0x06, 0x12, 0x00, 0xD0, 0x15, 0xFF, 0x55, 0x83, 0x36, 0x00, 0xEE,
+        0xF0, 0x90, 0x02, 0x0E,
+    ]);
+}
+
+#[test]
+fn mistakes() {
+    assert!(assemble("JP nowhere", 0x200).unwrap_err().contains("line 1: label nowhere is not defined"));
+    assert!(assemble("\nLD V0, 0x100", 0x200).unwrap_err().starts_with("line 2:"));
+    assert!(assemble("a: CLS\na: CLS", 0x200).unwrap_err().contains("twice"));
+    assert!(assemble("V1: CLS", 0x200).is_err());
+    assert!(assemble("DRW V0, V1", 0x200).is_err());
+    assert!(assemble("DB 0", 0xFFF).is_ok());
+    assert!(assemble("CLS", 0xFFF).is_err());
+}
@@ -0,0 +1,71 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use chip8::chip8::cheat::{self, Code, Comparison, Search};
+
+const CHEATS: &str = "
+# pong
+[A1B2C3D4] Pong
+infinite lives: 2F6:03
+skip the intro: 22A=12 22B=54
+
+[0000BEEF]
+both: 0x300:01 301=0x02
+";
+
+#[test]
+fn parse() {
+    let database = cheat::parse("cheats", CHEATS).unwrap();
+    let pong = &database[&0xA1B2_C3D4];
+    assert_eq!(pong.len(), 2);
+    assert_eq!(pong[0].name, "infinite lives");
+    assert_eq!(pong[0].codes, [Code::Freeze(0x2F6, 0x03)]);
+    assert_eq!(pong[1].name, "skip the intro");
+    assert_eq!(pong[1].codes, [Code::Patch(0x22A, 0x12), Code::Patch(0x22B, 0x54)]);
+    let other = &database[&0xBEEF];
+    assert_eq!(other[0].name, "both");
+    assert_eq!(other[0].codes, [Code::Freeze(0x300, 0x01), Code::Patch(0x301, 0x02)]);
+}
+
+#[test]
+fn mistakes() {
+    assert!(cheat::parse("cheats", "infinite lives: 2F6:03").unwrap_err().contains("cheats:1"), "a cheat before any section");
+    assert!(cheat::parse("cheats", "[PONG]\n").is_err());
+    assert!(cheat::parse("cheats", "[A1B2C3D4\n").is_err());
+    assert!(cheat::parse("cheats", "[A1B2C3D4]\nlives: 1000:03").unwrap_err().contains("cheats:2"), "past the end of memory");
+    assert!(cheat::parse("cheats", "[A1B2C3D4]\nlives: 2F6:100").is_err());
+    assert!(cheat::parse("cheats", "[A1B2C3D4]\nlives 2F6:03").is_err());
+    assert!(cheat::parse("cheats", "[A1B2C3D4]\nlives: 2F6").is_err());
+}
+
+#[test]
+fn search() {
+    let mut memory = vec![5u8; 16];
+    let mut search = Search::default();
+    search.start(&memory);
+    assert_eq!(search.candidates().len(), 16);
+
+    memory[3] = 4;
+    memory[7] = 4;
+    memory[9] = 6;
+    search.filter(&memory, Comparison::Decreased);
+    assert_eq!(search.candidates(), [3, 7]);
+
+    memory[7] = 3;
+    search.filter(&memory, Comparison::Changed);
+    assert_eq!(search.candidates(), [7]);
+
+    search.filter(&memory, Comparison::Unchanged);
+    assert_eq!(search.candidates(), [7]);
+    search.filter(&memory, Comparison::Equal(3));
+    assert_eq!(search.candidates(), [7]);
+    memory[7] = 9;
+    search.filter(&memory, Comparison::Increased);
+    assert_eq!(search.candidates(), [7]);
+    search.filter(&memory, Comparison::Equal(0));
+    assert!(search.candidates().is_empty());
+
+    // filtering without a start takes the memory as the first snapshot
+    let mut fresh = Search::default();
+    fresh.filter(&memory, Comparison::Equal(6));
+    assert_eq!(fresh.candidates(), [9]);
+}
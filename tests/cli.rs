@@ -0,0 +1,41 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::ffi::OsStr;
+
+use common::{chip8, temp_file, ROM};
+
+/// the exit code of chip8 run headless for a few frames, `options` come before the rom
+fn run(options: &[&str], rom: &[u8]) -> i32 {
+    let rom = temp_file("cli.ch8", rom);
+    let mut args: Vec<&OsStr> = ["--frontend", "headless", "--frames", "5"].iter().chain(options).map(OsStr::new).collect();
+    args.push(rom.path.as_os_str());
+    chip8(args).status.code().unwrap()
+}
+
+#[test]
+fn bare_rom() {
+    assert_eq!(run(&[], &ROM), 0, "runs without the run subcommand");
+    assert_eq!(run(&["-d"], &ROM), 0);
+}
+
+#[test]
+fn load_error() {
+    let missing = chip8(["run", "--frontend", "headless", "no/such/rom"]);
+    assert_eq!(missing.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("chip8 - "));
+    assert_eq!(run(&["--script", "no/such/script.rhai"], &ROM), 2);
+}
+
+#[test]
+fn fault() {
+    // LD V0, 5; then an unknown instruction
+    assert_eq!(run(&[], &[0x60, 0x05, 0xFF, 0xFF]), 3);
+}
+
+#[test]
+fn session_failed() {
+    // nothing listens on port 1
+    assert_eq!(run(&["--netplay-join", "127.0.0.1:1"], &ROM), 4);
+}
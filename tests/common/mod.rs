@@ -0,0 +1,47 @@
+//! machines, roms and files shared by the integration tests
+#![allow(dead_code)]
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chip8::chip8::{cpu, sound};
+use chip8::chip8::cpu::Cpu;
+use chip8::chip8::frontend::headless::Headless;
+
+/// LD V0, 5; LD V1, 7; CLS; JP 206
+pub const ROM: [u8; 8] = [0x60, 0x05, 0x61, 0x07, 0x00, 0xE0, 0x12, 0x06];
+
+/// a headless, silent machine with the rom booted
+pub fn machine(rom: &[u8]) -> Cpu {
+    let mut cpu = cpu::initialize(Box::new(Headless), Box::new(sound::Null));
+    cpu.bootup(rom.to_vec());
+    cpu
+}
+
+/// runs the chip8 binary to completion
+pub fn chip8<I: IntoIterator<Item = S>, S: AsRef<OsStr>>(args: I) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_chip8")).args(args).output().unwrap()
+}
+
+/// A file in the temp directory, removed when dropped
+pub struct TempFile {
+    pub path: PathBuf,
+}
+
+/// writes `contents` to a new temp file, `name` ends its file name
+pub fn temp_file(name: &str, contents: impl AsRef<[u8]>) -> TempFile {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let count = COUNT.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!("chip8-{}-{}-{}", std::process::id(), count, name));
+    fs::write(&path, contents).unwrap();
+    TempFile { path }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
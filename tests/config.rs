@@ -0,0 +1,70 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::path::Path;
+
+use chip8::chip8::config::{self, Profile};
+use chip8::chip8::rom;
+
+const CONFIG: &str = r##"
+speed = 1.5
+[palette]
+foreground = "#33FF66"
+[keymap]
+5 = "Space"
+
+[roms.7D75A857]
+speed = 2.0
+quirks = { shift = true }
+[roms."BRIX"]
+load_address = 0x600
+"##;
+
+#[test]
+fn precedence() {
+    let config = config::parse("test", CONFIG).unwrap();
+    let pong = include_bytes!("../roms/PONG");
+    let crc = rom::crc32(pong);
+    assert_eq!(crc, 0x7D75A857);
+
+    // the rom by crc over the global settings over the defaults
+    let settings = config.effective(&Profile::default(), None, pong);
+    assert_eq!(settings.speed, Some(2.0));
+    assert!(settings.quirks().shift);
+    assert!(!settings.quirks().clip);
+    assert_eq!(settings.load_address(), Ok(0x200));
+    assert_eq!(settings.palette.foreground.as_deref(), Some("#33FF66"));
+    assert_eq!(settings.palette.background.as_deref(), Some("#000000"));
+    let sdl = settings.sdl().unwrap();
+    assert_eq!(sdl.foreground, (0x33, 0xFF, 0x66));
+    assert_eq!((sdl.keymap[5].as_str(), sdl.keymap[6].as_str()), ("Space", "6"));
+
+    // the rom by file name, and the command line over everything
+    let command_line = Profile { speed: Some(0.5), ..Profile::default() };
+    let settings = config.effective(&command_line, Some(Path::new("roms/BRIX")), b"not brix");
+    assert_eq!(settings.speed, Some(0.5));
+    assert_eq!(settings.load_address(), Ok(0x600));
+    assert!(!settings.quirks().shift);
+
+    // no profile matches
+    let settings = config.effective(&Profile::default(), Some(Path::new("roms/TETRIS")), b"tetris");
+    assert_eq!(settings.speed, Some(1.5));
+
+    let dump = settings.to_toml().unwrap();
+    assert!(dump.contains("speed = 1.5"));
+    assert_eq!(config::parse("dump", &dump).unwrap().effective(&Profile::default(), None, b""), settings);
+}
+
+#[test]
+fn mistakes() {
+    assert!(config::parse("test", "spead = 2").unwrap_err().contains("spead"));
+    assert!(config::parse("test", "[roms.PONG]\nquirks = { wrap = true }").is_err());
+    assert!(config::parse("test", "speed = 0").unwrap_err().contains("speed"));
+    assert!(config::parse("test", "speed = inf").is_err());
+    assert!(config::parse("test", "[roms.PONG]\nspeed = -1.0").is_err());
+    let settings = config::parse("test", "[palette]\nforeground = \"green\"").unwrap()
+        .effective(&Profile::default(), None, b"");
+    assert!(settings.sdl().is_err());
+    let settings = config::parse("test", "[keymap]\nG = \"Space\"").unwrap()
+        .effective(&Profile::default(), None, b"");
+    assert!(settings.sdl().is_err());
+}
@@ -0,0 +1,122 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+use serde_json::{json, Value};
+
+use chip8::chip8::control;
+
+use common::{machine, ROM};
+
+struct Client {
+    stream: TcpStream,
+    lines: std::io::Lines<BufReader<TcpStream>>,
+    id: u64,
+    machine: JoinHandle<()>,
+}
+
+/// a machine running the rom behind a control server on loopback
+fn connect() -> Client {
+    let (sender, address) = mpsc::channel();
+    let machine = thread::spawn(move || {
+        let mut server = control::listen("127.0.0.1:0", ROM.to_vec()).unwrap();
+        sender.send(server.address()).unwrap();
+        server.run(&mut machine(&ROM));
+    });
+    let stream = TcpStream::connect(address.recv().unwrap()).unwrap();
+    let lines = BufReader::new(stream.try_clone().unwrap()).lines();
+    Client { stream, lines, id: 0, machine }
+}
+
+impl Client {
+
+    fn send(&mut self, line: &str) {
+        self.stream.write_all(format!("{}\n", line).as_bytes()).unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        serde_json::from_str(&self.lines.next().unwrap().unwrap()).unwrap()
+    }
+
+    /// the response to a request, skipping the notifications sent meanwhile
+    fn call(&mut self, method: &str, params: Value) -> Value {
+        self.id += 1;
+        let request = json!({ "jsonrpc": "2.0", "id": self.id, "method": method, "params": params });
+        self.send(&request.to_string());
+        loop {
+            let message = self.receive();
+            if message["id"] == json!(self.id) || message["id"].is_null() && message["error"].is_object() {
+                return message;
+            }
+        }
+    }
+
+    fn notification(&mut self, method: &str) -> Value {
+        loop {
+            let message = self.receive();
+            if message["method"] == method {
+                return message["params"].clone();
+            }
+        }
+    }
+
+    fn quit(mut self) {
+        self.call("quit", json!({}));
+        self.notification("quit");
+        self.machine.join().unwrap();
+    }
+}
+
+#[test]
+fn breakpoints() {
+    let mut client = connect();
+    client.call("pause", json!({}));
+    assert_eq!(client.call("breakpoints", json!({ "addresses": [0x204] }))["result"]["addresses"], json!([0x204]));
+    client.call("reset", json!({}));
+    client.call("resume", json!({}));
+    assert_eq!(client.notification("breakpoint")["pc"], 0x204);
+
+    let registers = client.call("registers", json!({}))["result"].clone();
+    assert_eq!(registers["pc"], 0x204);
+    assert_eq!(registers["v"][0], 5);
+    assert_eq!(registers["v"][1], 7);
+    assert_eq!(registers["paused"], true);
+    assert_eq!(client.call("step", json!({ "frames": 1 }))["result"]["pc"], 0x206);
+    client.quit();
+}
+
+#[test]
+fn memory_and_framebuffer() {
+    let mut client = connect();
+    assert_eq!(client.call("memory", json!({ "address": 0x200, "length": 2 }))["result"]["data"], "YAU=");
+    let framebuffer = client.call("framebuffer", json!({}))["result"].clone();
+    assert_eq!((framebuffer["width"].clone(), framebuffer["height"].clone()), (json!(64), json!(32)));
+    assert_eq!(client.call("press", json!({ "key": 3 }))["result"], Value::Null);
+    client.quit();
+}
+
+#[test]
+fn frame_notifications() {
+    let mut client = connect();
+    client.call("subscribe", json!({ "every": 1 }));
+    assert_eq!(client.notification("frame")["pixels"].as_str().unwrap().len(), 64 * 32 / 3 * 4 + 4);
+    client.call("subscribe", json!({ "every": 0 }));
+    client.quit();
+}
+
+#[test]
+fn errors() {
+    let mut client = connect();
+    assert_eq!(client.call("press", json!({ "key": 16 }))["error"]["code"], -32602);
+    assert_eq!(client.call("jump", json!({}))["error"]["code"], -32601);
+    assert_eq!(client.call("memory", json!({ "address": 0xFFF, "length": 2 }))["error"]["code"], -32602);
+    assert_eq!(client.call("load", json!({ "path": "no/such/rom" }))["error"]["code"], -32000);
+    client.send("{ not json");
+    assert_eq!(client.receive()["error"]["code"], -32700);
+    client.quit();
+}
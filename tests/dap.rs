@@ -0,0 +1,156 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+use serde_json::{json, Value};
+
+use chip8::chip8::dap;
+
+use common::{machine, temp_file, TempFile};
+
+/// CALL 208; LD V1, 7; JP 204; -; LD V0, 5; RET
+const ROM: [u8; 12] = [0x22, 0x08, 0x61, 0x07, 0x12, 0x04, 0x00, 0x00, 0x60, 0x05, 0x00, 0xEE];
+
+struct Client {
+    stream: TcpStream,
+    input: BufReader<TcpStream>,
+    seq: i64,
+    adapter: JoinHandle<()>,
+    rom: TempFile,
+}
+
+/// an adapter with nothing launched yet, initialized
+fn connect() -> Client {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let adapter = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        dap::new(stream.try_clone().unwrap(), Box::new(stream)).attach(&mut machine(&[])).unwrap();
+    });
+    let stream = TcpStream::connect(address).unwrap();
+    let mut editor = Client {
+        input: BufReader::new(stream.try_clone().unwrap()),
+        stream,
+        seq: 0,
+        adapter,
+        rom: temp_file("dap.ch8", ROM),
+    };
+    let capabilities = editor.call("initialize", json!({ "adapterID": "chip8" }));
+    assert_eq!(capabilities["body"]["supportsSteppingGranularity"], Value::Null);
+    editor.event("initialized");
+    editor
+}
+
+impl Client {
+
+    fn receive(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            self.input.read_line(&mut header).unwrap();
+            match header.trim().strip_prefix("Content-Length:") {
+                Some(value) => length = value.trim().parse().unwrap(),
+                None if header.trim().is_empty() => break,
+                None => {},
+            }
+        }
+        let mut body = vec![0u8; length];
+        self.input.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// sends a request and returns its response
+    fn call(&mut self, command: &str, arguments: Value) -> Value {
+        self.seq += 1;
+        let body = json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments }).to_string();
+        write!(self.stream, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        let response = self.receive();
+        assert_eq!(response["type"], "response", "{}", response);
+        assert_eq!(response["request_seq"], self.seq);
+        assert_eq!(response["success"], true, "{}", response);
+        response
+    }
+
+    fn event(&mut self, event: &str) -> Value {
+        let message = self.receive();
+        assert_eq!(message["event"], event, "{}", message);
+        message
+    }
+
+    fn launch(&mut self, stop_on_entry: bool) {
+        let program = self.rom.path.clone();
+        self.call("launch", json!({ "program": program, "stopOnEntry": stop_on_entry }));
+        self.call("configurationDone", json!({}));
+        if stop_on_entry {
+            assert_eq!(self.event("stopped")["body"]["reason"], "entry");
+        }
+    }
+
+    fn step(&mut self, command: &str) -> Value {
+        self.call(command, json!({ "threadId": 1 }));
+        self.event("stopped")["body"]["reason"].clone()
+    }
+
+    fn pc(&mut self) -> Value {
+        let trace = self.call("stackTrace", json!({ "threadId": 1 }));
+        trace["body"]["stackFrames"][0]["instructionPointerReference"].clone()
+    }
+
+    fn disconnect(mut self) {
+        self.call("disconnect", json!({}));
+        self.adapter.join().unwrap();
+    }
+}
+
+#[test]
+fn stop_on_entry() {
+    let mut editor = connect();
+    editor.launch(true);
+    assert_eq!(editor.pc(), "0x200");
+    let scopes = editor.call("scopes", json!({ "frameId": 0 }));
+    assert_eq!(scopes["body"]["scopes"][0]["name"], "Registers");
+    editor.disconnect();
+}
+
+#[test]
+fn next_steps_over_calls() {
+    let mut editor = connect();
+    editor.launch(true);
+    assert_eq!(editor.step("next"), "step");
+    assert_eq!(editor.pc(), "0x202", "the whole subroutine ran");
+    let registers = editor.call("variables", json!({ "variablesReference": 1 }));
+    assert_eq!(registers["body"]["variables"][0]["value"], "0x05");
+
+    editor.step("next");
+    assert_eq!(editor.pc(), "0x204", "a single instruction elsewhere");
+    editor.disconnect();
+}
+
+#[test]
+fn step_in_and_out() {
+    let mut editor = connect();
+    editor.launch(true);
+    editor.step("stepIn");
+    assert_eq!(editor.pc(), "0x208");
+    let trace = editor.call("stackTrace", json!({ "threadId": 1 }));
+    assert_eq!(trace["body"]["stackFrames"][1]["instructionPointerReference"], "0x200", "the call site");
+    editor.step("stepOut");
+    assert_eq!(editor.pc(), "0x202");
+    editor.disconnect();
+}
+
+#[test]
+fn breakpoints() {
+    let mut editor = connect();
+    editor.call("setInstructionBreakpoints", json!({ "breakpoints": [{ "instructionReference": "0x20A" }] }));
+    editor.launch(false);
+    assert_eq!(editor.event("stopped")["body"]["reason"], "breakpoint");
+    assert_eq!(editor.pc(), "0x20A");
+    let outside = editor.call("setInstructionBreakpoints", json!({ "breakpoints": [{ "instructionReference": "0x1000" }] }));
+    assert_eq!(outside["body"]["breakpoints"][0]["verified"], false);
+    editor.disconnect();
+}
@@ -0,0 +1,166 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+use chip8::chip8::gdb;
+
+use common::{machine, ROM};
+
+struct Client {
+    stream: TcpStream,
+    stub: JoinHandle<io::Result<()>>,
+}
+
+/// a stub serving the test rom, halted at its start
+fn connect() -> Client {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let stub = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        gdb::new(stream).attach(&mut machine(&ROM))
+    });
+    Client { stream: TcpStream::connect(address).unwrap(), stub }
+}
+
+fn packet(data: &str) -> String {
+    let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+    format!("${}#{:02x}", data, checksum)
+}
+
+impl Client {
+
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0u8; 1];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    /// sends a packet and waits for its acknowledgement
+    fn send(&mut self, data: &str) {
+        self.stream.write_all(packet(data).as_bytes()).unwrap();
+        assert_eq!(self.byte(), b'+');
+    }
+
+    /// the next packet from the stub, acknowledged
+    fn reply(&mut self) -> String {
+        assert_eq!(self.byte(), b'$');
+        let mut reply = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                b => reply.push(b),
+            }
+        }
+        self.byte();
+        self.byte();
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    fn call(&mut self, data: &str) -> String {
+        self.send(data);
+        self.reply()
+    }
+
+    fn kill(mut self) {
+        self.stream.write_all(packet("k").as_bytes()).unwrap();
+        self.stub.join().unwrap().unwrap();
+    }
+}
+
+#[test]
+fn registers() {
+    let mut gdb = connect();
+    assert_eq!(gdb.call("?"), "S05");
+    let registers = gdb.call("g");
+    assert_eq!(&registers[32 + 4..32 + 8], "0002", "pc is 0x200, little endian");
+    assert_eq!(gdb.call("P3=2a"), "OK");
+    assert_eq!(gdb.call("p3"), "2a");
+    assert_eq!(gdb.call("p15"), "E01", "there are 21 registers");
+    gdb.kill();
+}
+
+#[test]
+fn memory() {
+    let mut gdb = connect();
+    assert_eq!(gdb.call("m200,4"), "60056107");
+    assert_eq!(gdb.call("M300,2:abcd"), "OK");
+    assert_eq!(gdb.call("m300,2"), "abcd");
+    assert_eq!(gdb.call("mfff,1"), "00");
+    gdb.kill();
+}
+
+#[test]
+fn breakpoints() {
+    let mut gdb = connect();
+    assert_eq!(gdb.call("Z0,206,2"), "OK");
+    assert_eq!(gdb.call("c"), "S05");
+    assert_eq!(gdb.call("p11"), "0602");
+    assert_eq!(gdb.call("p0"), "05");
+    assert_eq!(gdb.call("p1"), "07");
+    // the jump to itself stops on its breakpoint again
+    assert_eq!(gdb.call("c"), "S05");
+    assert_eq!(gdb.call("p11"), "0602");
+    assert_eq!(gdb.call("z0,206,2"), "OK");
+    gdb.kill();
+}
+
+#[test]
+fn malformed_packets() {
+    let mut gdb = connect();
+    assert_eq!(gdb.call("mffffffffffffffff,2"), "E01");
+    assert_eq!(gdb.call("Mffffffffffffffff,1:00"), "E01");
+    assert_eq!(gdb.call("m1000,1"), "E01");
+    assert_eq!(gdb.call("M200,2:00"), "E01", "fewer bytes than the length");
+    assert_eq!(gdb.call("é"), "");
+    assert_eq!(gdb.call("Z0,1000,2"), "E01");
+    // and the stub goes on
+    assert_eq!(gdb.call("?"), "S05");
+    gdb.kill();
+}
+
+#[test]
+fn stepping() {
+    let mut gdb = connect();
+    assert_eq!(gdb.call("s"), "S05");
+    assert_eq!(gdb.call("p11"), "0202");
+    assert_eq!(gdb.call("sffff"), "S05", "the pc wraps into memory");
+    assert_eq!(gdb.call("s"), "S05");
+    gdb.kill();
+}
+
+#[test]
+fn interrupt() {
+    let mut gdb = connect();
+    gdb.send("c");
+    gdb.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(gdb.reply(), "S02");
+    assert_eq!(gdb.call("?"), "S05", "halted again");
+    gdb.kill();
+}
+
+#[test]
+fn packet_while_running() {
+    let mut gdb = connect();
+    gdb.send("c");
+    // the packet ahead of the interrupt is answered once the target stopped
+    let mut bytes = packet("m200,2").into_bytes();
+    bytes.push(0x03);
+    gdb.stream.write_all(&bytes).unwrap();
+    assert_eq!(gdb.reply(), "S02");
+    assert_eq!(gdb.byte(), b'+');
+    assert_eq!(gdb.reply(), "6005");
+    gdb.kill();
+}
+
+#[test]
+fn hang_up_while_running() {
+    let mut gdb = connect();
+    gdb.send("c");
+    drop(gdb.stream);
+    assert!(gdb.stub.join().unwrap().is_err());
+}
@@ -0,0 +1,79 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+
+use chip8::chip8::{cpu, netplay, sound};
+use chip8::chip8::cpu::Cpu;
+use chip8::chip8::frontend::headless::Headless;
+use chip8::chip8::netplay::{Session, Settings};
+
+const FRAMES: u64 = 1200;
+
+fn machine(rom: &[u8]) -> Cpu {
+    let mut cpu = cpu::initialize(Box::new(Headless), Box::new(sound::Null));
+    cpu.bootup(rom.to_vec());
+    cpu
+}
+
+/// plays with the keys of a side going up and down every period frames, as fast as the other side allows,
+/// then returns the save state of the last frame once the other side confirmed it
+fn play(session: &mut Session, cpu: &mut Cpu, period: u64) -> Vec<u8> {
+    while session.frame() < FRAMES {
+        let keys = if (session.frame() / period).is_multiple_of(2) { 0xFFFF } else { 0 };
+        session.advance(cpu, keys).unwrap();
+    }
+    while session.confirmed() < FRAMES {
+        session.settle(cpu);
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(session.desync(), None);
+    assert!(session.checked() > 0);
+    cpu.save_state()
+}
+
+#[test]
+fn loopback() {
+    let rom = include_bytes!("../roms/PONG");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let host = thread::spawn(move || {
+        let mut cpu = machine(rom);
+        let settings = Settings { keys: netplay::HOST_KEYS, delay: 1, seed: 42 };
+        let mut session = netplay::host(&listener, &mut cpu, rom, &settings).unwrap();
+        play(&mut session, &mut cpu, 7)
+    });
+    let mut cpu = machine(rom);
+    let settings = Settings { keys: netplay::GUEST_KEYS, delay: 0, seed: 0 };
+    let mut session = netplay::join(&address, &mut cpu, rom, &settings).unwrap();
+    let guest = play(&mut session, &mut cpu, 11);
+
+    assert!(host.join().unwrap() == guest, "both sides end on the same frame");
+}
+
+#[test]
+fn different_roms() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let host = thread::spawn(move || {
+        let rom = include_bytes!("../roms/PONG");
+        let settings = Settings { keys: netplay::HOST_KEYS, delay: 2, seed: 0 };
+        netplay::host(&listener, &mut machine(rom), rom, &settings).err()
+    });
+    let rom = include_bytes!("../roms/BRIX");
+    let settings = Settings { keys: netplay::GUEST_KEYS, delay: 2, seed: 0 };
+    let guest = netplay::join(&address, &mut machine(rom), rom, &settings).err();
+
+    assert!(guest.unwrap().contains("different roms"));
+    assert!(host.join().unwrap().unwrap().contains("different roms"));
+}
+
+#[test]
+fn parse_keys() {
+    assert_eq!(netplay::parse_keys("14"), Ok(netplay::HOST_KEYS));
+    assert_eq!(netplay::parse_keys("cD"), Ok(netplay::GUEST_KEYS));
+    assert!(netplay::parse_keys("G").is_err());
+}
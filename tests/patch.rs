@@ -0,0 +1,57 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use chip8::chip8::patch::{apply, make, Format};
+
+const ROM: &[u8] = include_bytes!("../roms/PONG");
+
+#[test]
+fn round_trip() {
+    let mut changed = ROM.to_vec();
+    changed[0x10] ^= 0xFF;
+    changed[0x11] ^= 0xFF;
+    changed[0x80] = 0;
+    let mut longer = changed.clone();
+    longer.extend_from_slice(&[0x12, 0x00, 0x12, 0x00]);
+    let shorter = changed[..0x40].to_vec();
+
+    for format in [Format::Ips, Format::Bps].iter() {
+        for modified in [ROM.to_vec(), changed.clone(), longer.clone(), shorter.clone()].iter() {
+            let patch = make(ROM, modified, *format);
+            assert_eq!(&apply(&patch, ROM.to_vec()).unwrap(), modified, "{:?}", format);
+        }
+    }
+}
+
+#[test]
+fn ips_records() {
+    // a run of three 0xAA at 2, then truncated to 4 bytes
+    let patch = b"PATCH\x00\x00\x02\x00\x00\x00\x03\xAAEOF\x00\x00\x04";
+    assert_eq!(apply(patch, vec![0, 1, 2, 3, 4, 5, 6, 7]).unwrap(), [0, 1, 0xAA, 0xAA]);
+
+    // a record past the end grows the rom
+    let patch = b"PATCH\x00\x00\x03\x00\x02\x12\x34EOF";
+    assert_eq!(apply(patch, vec![0, 1]).unwrap(), [0, 1, 0, 0x12, 0x34]);
+
+    // cut in the middle of a record, or without its end
+    assert!(apply(b"PATCH\x00\x00\x03\x00\x02\x12", vec![0, 1]).unwrap_err().contains("truncated"));
+    assert!(apply(b"PATCH\x00\x00\x03\x00\x02\x12\x34", vec![0, 1]).unwrap_err().contains("truncated"));
+    assert!(apply(b"PATCH\x00\x00\x03\x00\x00\x00", vec![0, 1]).unwrap_err().contains("truncated"));
+}
+
+#[test]
+fn bps_checks() {
+    let mut modified = ROM.to_vec();
+    modified[0x20] ^= 0xFF;
+    let patch = make(ROM, &modified, Format::Bps);
+
+    let mut corrupted = patch.clone();
+    corrupted[6] ^= 0x01;
+    assert!(apply(&corrupted, ROM.to_vec()).unwrap_err().contains("checksum"));
+
+    let mut other = ROM.to_vec();
+    other[0] ^= 0xFF;
+    assert!(apply(&patch, other).unwrap_err().contains("another rom"));
+
+    assert!(apply(&patch[..10], ROM.to_vec()).is_err());
+    assert!(apply(b"NOPE", ROM.to_vec()).unwrap_err().contains("not an ips or bps patch"));
+}
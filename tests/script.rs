@@ -0,0 +1,40 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use chip8::chip8::script;
+
+use common::{machine, temp_file};
+
+/// LD V0, 0; LD I, 300; ADD V0, 1; LD [I], V0; JP 204
+const ROM: [u8; 10] = [0x60, 0x00, 0xA3, 0x00, 0x70, 0x01, 0xF0, 0x55, 0x12, 0x04];
+
+/// counts the ADD in 400, copies the writes to 300 in 401, counts the frames in 402,
+/// freezes 500 through an address past the end of memory and copies it to 403, then unfreezes it the same way
+const SCRIPT: &str = r#"
+on_pc(0x204, |address| poke(0x400, peek(0x400) + 1));
+on_write(0x300, |address, value| poke(0x401, value));
+on_frame(|| {
+    poke(0x402, peek(0x402) + 1);
+    if frames() == 10 { freeze(0x1500, 0xAA); }
+    if frames() == 15 { poke(0x403, peek(0x500)); }
+    if frames() == 20 { unfreeze(0x1500); poke(0x500, 0); }
+    if frames() == 30 { quit(); }
+});
+"#;
+
+#[test]
+fn hooks() {
+    let file = temp_file("script.rhai", SCRIPT);
+    let mut cpu = machine(&ROM);
+    script::load(&file.path, &mut cpu).unwrap().run(&mut cpu);
+
+    // the memory comes after the magic and the registers
+    let saved = cpu.save_state();
+    let memory = &saved[4 + 16..][..4096];
+    assert_eq!(memory[0x402], 30, "on_frame runs every frame");
+    assert_eq!(memory[0x400], 10, "on_pc runs before every ADD");
+    assert_eq!(memory[0x401], memory[0x300], "on_write sees the last write");
+    assert_eq!(memory[0x403], 0xAA, "freeze keeps the byte");
+    assert_eq!(memory[0x500], 0, "unfreeze lets it go");
+}
@@ -0,0 +1,53 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use chip8::chip8::{bench, cpu, sound};
+use chip8::chip8::cpu::Cpu;
+use chip8::chip8::frontend::headless::Headless;
+
+/// 64 times RND V0, FF; LD I, 300; LD [I], V0; ADD V1, 1; SE V1, 40; JP 200, then an unknown instruction
+const ROM: [u8; 14] = [0xC0, 0xFF, 0xA3, 0x00, 0xF0, 0x55, 0x71, 0x01, 0x31, 0x40, 0x12, 0x00, 0xFF, 0xFF];
+
+fn machine() -> Cpu {
+    cpu::initialize(Box::new(Headless), Box::new(sound::Null))
+}
+
+#[test]
+fn round_trip() {
+    let mut cpu = machine();
+    cpu.bootup(ROM.to_vec());
+    let boot = cpu.save_state();
+    bench::spin(&mut cpu, 100);
+    let middle = cpu.save_state();
+    assert_eq!(cpu.save_state(), middle, "saving leaves the machine as it was");
+    assert!(bench::spin(&mut cpu, 1000) < 1000, "the rom stops on its unknown instruction");
+    let end = cpu.save_state();
+
+    // the same random numbers, whether the run was saved on the way or not
+    let mut other = machine();
+    other.load_state(&boot).unwrap();
+    assert!(bench::spin(&mut other, 1000) < 1000);
+    assert_eq!(other.save_state(), end);
+
+    // a stopped machine runs again from a loaded state
+    cpu.load_state(&middle).unwrap();
+    assert!(bench::spin(&mut cpu, 1000) > 0);
+    assert_eq!(cpu.save_state(), end);
+}
+
+#[test]
+fn generator_kept_whole() {
+    let mut cpu = machine();
+    cpu.bootup(ROM.to_vec());
+    let mut state = cpu.save_state();
+    // the last bytes hold the generator state, a huge one loads at once
+    let end = state.len() - 8;
+    state[end..].copy_from_slice(&[0xFF; 8]);
+    cpu.load_state(&state).unwrap();
+    assert_eq!(cpu.save_state(), state);
+
+    bench::spin(&mut cpu, 10);
+    let mut other = machine();
+    other.load_state(&state).unwrap();
+    bench::spin(&mut other, 10);
+    assert_eq!(other.save_state(), cpu.save_state());
+}
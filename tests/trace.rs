@@ -0,0 +1,75 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::ffi::OsStr;
+
+use chip8::chip8::trace::diff;
+
+use common::{chip8, temp_file, TempFile};
+
+/// LD V0, 0x10; LD V1, 3; SHR V0, V1; JP 206, the shift quirk shifts V1 instead of V0
+const ROM: [u8; 8] = [0x60, 0x10, 0x61, 0x03, 0x80, 0x16, 0x12, 0x06];
+
+/// traces a run of the rom through the trace subcommand, `options` come before the rom
+fn trace(name: &str, options: &[&str]) -> TempFile {
+    let rom = temp_file("trace.ch8", ROM);
+    let output = temp_file(name, "");
+    let mut args: Vec<&OsStr> = ["trace", "--frames", "5"].iter().chain(options).map(OsStr::new).collect();
+    args.extend(&[OsStr::new("--output"), output.path.as_os_str(), rom.path.as_os_str()]);
+    let run = chip8(args);
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    output
+}
+
+/// the exit code and report of the trace diff subcommand
+fn differ(a: &TempFile, b: &TempFile) -> (i32, String) {
+    let run = chip8([OsStr::new("trace-diff"), a.path.as_os_str(), b.path.as_os_str()]);
+    (run.status.code().unwrap(), String::from_utf8(run.stdout).unwrap())
+}
+
+#[test]
+fn identical() {
+    let (code, report) = differ(&trace("a.log", &[]), &trace("b.log", &[]));
+    assert_eq!(code, 0, "{}", report);
+    assert_eq!(report, "traces agree on 5 instructions\n");
+}
+
+#[test]
+fn text_against_json() {
+    let (code, report) = differ(&trace("a.log", &[]), &trace("b.json", &["--format", "json"]));
+    assert_eq!(code, 0, "{}", report);
+}
+
+#[test]
+fn shift_quirk() {
+    let (code, report) = differ(&trace("a.log", &[]), &trace("b.log", &["--quirk", "shift"]));
+    assert_eq!(code, 1);
+    assert!(report.starts_with("traces diverge at cycle 3 (frame 2)"), "{}", report);
+    assert!(report.contains("\nresponsible instruction: 204 8016 SHR V0, V1\n"), "{}", report);
+    assert!(report.contains("\n  V0      a=08   b=01\n"), "{}", report);
+}
+
+#[test]
+fn truncated() {
+    let short = trace("short.log", &["--frame-range", "0-2"]);
+    let (code, report) = differ(&short, &trace("b.log", &[]));
+    assert_eq!(code, 1);
+    assert!(report.starts_with("traces agree on 3 instructions, then only b goes on"), "{}", report);
+}
+
+#[test]
+fn unmatched_cycle() {
+    let skipping = trace("skipping.log", &["--pc", "0x202-0x206"]);
+    let (code, report) = differ(&trace("a.log", &[]), &skipping);
+    assert_eq!(code, 1);
+    assert!(report.starts_with("traces diverge at cycle 1 (frame 0), only a has it"), "{}", report);
+}
+
+#[test]
+fn not_a_trace() {
+    let rom = temp_file("trace.ch8", ROM);
+    assert!(diff(&rom.path, &rom.path, 2).is_err());
+    let missing = rom.path.with_extension("missing");
+    assert!(diff(&missing, &missing, 2).is_err());
+}
@@ -0,0 +1,54 @@
+//! runs in node with `wasm-pack test --node`
+#![cfg(target_arch = "wasm32")]
+
+use wasm_bindgen_test::*;
+use chip8::Emulator;
+
+/// clears the screen, draws the font's 0 at the top left corner then sets the sound timer
+const ROM: [u8; 16] = [
+    0x00, 0xE0, // CLS
+    0x60, 0x00, // LD V0, 0
+    0xF0, 0x29, // LD F, V0
+    0xD0, 0x05, // DRW V0, V0, 5
+    0x61, 0x20, // LD V1, 0x20
+    0xF1, 0x18, // LD ST, V1
+    0x12, 0x0C, // JP 0x20C
+    0x00, 0x00,
+];
+
+fn pixel(emulator: &Emulator, x: u32, y: u32) -> u8 {
+    let offset = ((y * emulator.width() + x) * 4) as usize;
+    unsafe { *emulator.framebuffer().add(offset) }
+}
+
+#[wasm_bindgen_test]
+fn draws_to_the_framebuffer() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&ROM).unwrap();
+    emulator.set_speed(10.0);
+    assert!(emulator.run_frame());
+    // 0 is 0xF0 0x90 0x90 0x90 0xF0
+    assert_eq!(pixel(&emulator, 0, 0), 0xFF);
+    assert_eq!(pixel(&emulator, 3, 0), 0xFF);
+    assert_eq!(pixel(&emulator, 1, 1), 0x00);
+    assert_eq!(pixel(&emulator, 4, 0), 0x00);
+}
+
+#[wasm_bindgen_test]
+fn buzzes_while_the_sound_timer_runs() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&ROM).unwrap();
+    emulator.set_speed(10.0);
+    emulator.run_frame();
+    assert!(emulator.buzzing());
+    for _ in 0..0x20 {
+        emulator.run_frame();
+    }
+    assert!(!emulator.buzzing());
+}
+
+#[wasm_bindgen_test]
+fn rejects_roms_bigger_than_memory() {
+    let mut emulator = Emulator::new();
+    assert!(emulator.load_rom(&[0; 4096]).is_err());
+}